@@ -5,9 +5,12 @@ use std::ffi::OsString;
 use std::fmt;
 use std::io;
 use std::num::{ParseFloatError, ParseIntError};
+use std::path::Path;
 use std::str::ParseBoolError;
 
 use las;
+#[cfg(feature = "laz")]
+use laz;
 #[cfg(feature = "rxp-source")]
 use rivlib;
 use sdc;
@@ -20,14 +23,30 @@ use toml;
 pub enum Error {
     /// Invalid configuration for a source or sink.
     Configuration(String),
+    /// Another error, along with where it happened.
+    Context(Context, Box<Error>),
     /// Wrapper around `toml::DecodeError`.
     Decode(toml::DecodeError),
+    /// Data that doesn't have the layout its format calls for, e.g. a line with too few values.
+    Format(String),
+    /// A variable length record that doesn't fit in a sink's format.
+    InvalidVlr(String),
+    /// A value couldn't be written exactly, and the sink was told to be strict about it.
+    LossyConversion(String),
     /// A point is missing a dimension that is required by someone else, usually a `Sink`.
+    ///
+    /// This is the name of the dimension, either a `Dimension` name or an extra dimension's name.
+    /// If a schema check finds more than one, they're all listed, separated by commas.
     MissingDimension(String),
     /// Wrapper around `std::io::Error`.
     Io(io::Error),
     /// A wrapper around a las error.
     Las(las::Error),
+    #[cfg(feature = "laz")]
+    /// A wrapper around a laszip error.
+    Laz(laz::LasZipError),
+    /// An error reading or writing a PLY file.
+    Ply(String),
     /// A wrapper around `std::str::ParseBoolError`.
     ParseBool(ParseBoolError),
     /// A wrapper around `std::num::ParseIntError`.
@@ -42,6 +61,10 @@ pub enum Error {
     #[cfg(feature = "sdf-source")]
     /// A wrapper around an sdf error.
     Sdf(sdf::Error),
+    /// A dimension name that doesn't match any `point::Dimension`.
+    UnknownDimension(String),
+    /// A format name that doesn't match any registered source or sink.
+    UnknownFormat(String),
     /// Unregistered file extension when intuiting a source or sink type.
     UnregisteredFileExtension(OsString),
 }
@@ -50,10 +73,17 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Configuration(_) => "configuration error",
+            Error::Context(_, ref err) => (**err).description(),
             Error::Decode(ref err) => err.description(),
+            Error::Format(_) => "format error",
+            Error::InvalidVlr(_) => "invalid vlr",
+            Error::LossyConversion(_) => "lossy conversion",
             Error::MissingDimension(_) => "missing dimension",
             Error::Io(ref err) => err.description(),
             Error::Las(ref err) => err.description(),
+            #[cfg(feature = "laz")]
+            Error::Laz(ref err) => err.description(),
+            Error::Ply(_) => "PLY error",
             Error::ParseBool(ref err) => err.description(),
             Error::ParseInt(ref err) => err.description(),
             Error::ParseFloat(ref err) => err.description(),
@@ -62,15 +92,20 @@ impl error::Error for Error {
             Error::Sdc(ref err) => err.description(),
             #[cfg(feature = "sdf-source")]
             Error::Sdf(ref err) => err.description(),
+            Error::UnknownDimension(_) => "unknown dimension",
+            Error::UnknownFormat(_) => "unknown format",
             Error::UnregisteredFileExtension(_) => "unregistered file extension",
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
+            Error::Context(_, ref err) => Some(&**err),
             Error::Decode(ref err) => Some(err),
             Error::Io(ref err) => Some(err),
             Error::Las(ref err) => Some(err),
+            #[cfg(feature = "laz")]
+            Error::Laz(ref err) => Some(err),
             Error::ParseBool(ref err) => Some(err),
             Error::ParseInt(ref err) => Some(err),
             Error::ParseFloat(ref err) => Some(err),
@@ -88,10 +123,17 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Configuration(ref s) => write!(f, "Configuration error: {}", s),
+            Error::Context(ref context, ref err) => write!(f, "{}: {}", context, err),
             Error::Decode(ref err) => write!(f, "Decode error: {}", err),
+            Error::Format(ref s) => write!(f, "Format error: {}", s),
+            Error::InvalidVlr(ref s) => write!(f, "Invalid VLR: {}", s),
+            Error::LossyConversion(ref s) => write!(f, "Lossy conversion: {}", s),
             Error::MissingDimension(ref s) => write!(f, "Missing dimension: {}", s),
             Error::Io(ref err) => write!(f, "IO error: {}", err),
             Error::Las(ref err) => write!(f, "las error: {}", err),
+            #[cfg(feature = "laz")]
+            Error::Laz(ref err) => write!(f, "laz error: {}", err),
+            Error::Ply(ref s) => write!(f, "PLY error: {}", s),
             Error::ParseBool(ref err) => write!(f, "Parse bool error: {}", err),
             Error::ParseInt(ref err) => write!(f, "Parse int error: {}", err),
             Error::ParseFloat(ref err) => write!(f, "Parse float error: {}", err),
@@ -100,12 +142,136 @@ impl fmt::Display for Error {
             Error::Sdc(ref err) => write!(f, "sdc error: {}", err),
             #[cfg(feature = "sdf-source")]
             Error::Sdf(ref err) => write!(f, "sdf error: {}", err),
+            Error::UnknownDimension(ref s) => write!(f, "Unknown dimension: {}", s),
+            Error::UnknownFormat(ref s) => write!(f, "Unknown format: {}", s),
             Error::UnregisteredFileExtension(ref s) =>
                 write!(f, "Unregistered file extension: {}", s.to_string_lossy()),
         }
     }
 }
 
+impl Error {
+    /// Adds context to this error.
+    ///
+    /// Errors that already have context keep it, and only gain the parts they're missing, so the
+    /// innermost, most specific context wins.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::Error;
+    /// use pabst::error::Context;
+    /// let err = Error::MissingDimension("gps_time".to_string())
+    ///               .context(Context::new("sdc").path("points.sdc").index(42));
+    /// assert_eq!("sdc points.sdc, point 42: Missing dimension: gps_time", err.to_string());
+    /// ```
+    pub fn context(self, context: Context) -> Error {
+        match self {
+            Error::Context(inner, err) => {
+                Error::Context(Context {
+                                   name: inner.name,
+                                   path: inner.path.or(context.path),
+                                   line: inner.line.or(context.line),
+                                   index: inner.index.or(context.index),
+                               },
+                               err)
+            }
+            err => Error::Context(context, Box::new(err)),
+        }
+    }
+
+    /// Returns the error underneath any context.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::Error;
+    /// use pabst::error::Context;
+    /// let err = Error::MissingDimension("gps_time".to_string()).context(Context::new("sdc"));
+    /// match *err.root() {
+    ///     Error::MissingDimension(ref name) => assert_eq!("gps_time", name),
+    ///     _ => panic!(),
+    /// }
+    /// ```
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::Context(_, ref err) => err.root(),
+            ref err => err,
+        }
+    }
+}
+
+/// Where an error happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Context {
+    /// The name of the source, sink, or filter, e.g. "las" or "decimate".
+    pub name: String,
+    /// The path of the file being read or written.
+    pub path: Option<String>,
+    /// The one-based line number, for text files.
+    pub line: Option<usize>,
+    /// The zero-based index of the point being read or written.
+    pub index: Option<usize>,
+}
+
+impl Context {
+    /// Creates a new context for a named source, sink, or filter.
+    pub fn new<S: Into<String>>(name: S) -> Context {
+        Context {
+            name: name.into(),
+            path: None,
+            line: None,
+            index: None,
+        }
+    }
+
+    /// Sets the path, builder style.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Context {
+        self.path = Some(path.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Sets the line number, builder style.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::Error;
+    /// use pabst::error::Context;
+    /// let err = "foo".parse::<f64>().map_err(Error::from).unwrap_err();
+    /// let err = err.context(Context::new("text").line(4))
+    ///              .context(Context::new("text").path("points.xyz").index(2));
+    /// assert_eq!("text points.xyz, line 4, point 2: Parse float error: invalid float literal",
+    ///            err.to_string());
+    /// ```
+    pub fn line(mut self, line: usize) -> Context {
+        self.line = Some(line);
+        self
+    }
+
+    /// Sets the point index, builder style.
+    pub fn index(mut self, index: usize) -> Context {
+        self.index = Some(index);
+        self
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}", self.name));
+        if let Some(ref path) = self.path {
+            try!(write!(f, " {}", path));
+        }
+        if let Some(line) = self.line {
+            try!(write!(f, ", line {}", line));
+        }
+        if let Some(index) = self.index {
+            try!(write!(f, ", point {}", index));
+        }
+        Ok(())
+    }
+}
+
 impl From<toml::DecodeError> for Error {
     fn from(err: toml::DecodeError) -> Error {
         Error::Decode(err)
@@ -124,6 +290,13 @@ impl From<las::Error> for Error {
     }
 }
 
+#[cfg(feature = "laz")]
+impl From<laz::LasZipError> for Error {
+    fn from(err: laz::LasZipError) -> Error {
+        Error::Laz(err)
+    }
+}
+
 impl From<ParseBoolError> for Error {
     fn from(err: ParseBoolError) -> Error {
         Error::ParseBool(err)
@@ -0,0 +1,203 @@
+//! The las extra bytes VLR, shared by the las source and sink.
+//!
+//! The las library hands us each point's extra bytes as an opaque `Vec<u8>`, so we have to read
+//! the extra bytes VLR ourselves to figure out what's in there.
+
+use byteorder::{ByteOrder, LittleEndian};
+use las::Vlr;
+
+use {Error, Result};
+use point::{Point, Value};
+
+const USER_ID: &'static [u8] = b"LASF_Spec";
+const RECORD_ID: u16 = 4;
+const DESCRIPTOR_LENGTH: usize = 192;
+const NAME_LENGTH: usize = 32;
+const DOUBLE: u8 = 10;
+
+/// One extra dimension, as described by an extra bytes VLR.
+#[derive(Clone, Debug)]
+pub struct ExtraBytes {
+    name: String,
+    data_type: u8,
+    len: usize,
+    scale: Option<f64>,
+    offset: Option<f64>,
+}
+
+impl ExtraBytes {
+    /// Reads the extra dimensions out of a las file's VLRs.
+    ///
+    /// Undocumented extra bytes (data type zero) and the deprecated array types take up space but
+    /// aren't decoded.
+    pub fn from_vlrs(vlrs: &[Vlr]) -> Result<Vec<ExtraBytes>> {
+        let vlr = match vlrs.iter().find(|v| is_extra_bytes(v)) {
+            Some(vlr) => vlr,
+            None => return Ok(Vec::new()),
+        };
+        let mut extra_bytes = Vec::new();
+        for descriptor in vlr.record.chunks(DESCRIPTOR_LENGTH) {
+            if descriptor.len() < DESCRIPTOR_LENGTH {
+                return Err(Error::Configuration("las extra bytes VLR has a partial descriptor"
+                                                    .to_string()));
+            }
+            let data_type = descriptor[2];
+            let options = descriptor[3];
+            let len = match data_type {
+                0 => options as usize,
+                n if n <= 30 => base_len((n - 1) % 10 + 1) * ((n as usize - 1) / 10 + 1),
+                _ => {
+                    return Err(Error::Configuration(format!("unknown las extra bytes data \
+                                                             type: {}",
+                                                            data_type)))
+                }
+            };
+            let name = &descriptor[4..4 + NAME_LENGTH];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(NAME_LENGTH)];
+            extra_bytes.push(ExtraBytes {
+                name: String::from_utf8_lossy(name).into_owned(),
+                data_type: data_type,
+                len: len,
+                scale: if options & 0x08 != 0 {
+                    Some(LittleEndian::read_f64(&descriptor[112..120]))
+                } else {
+                    None
+                },
+                offset: if options & 0x10 != 0 {
+                    Some(LittleEndian::read_f64(&descriptor[136..144]))
+                } else {
+                    None
+                },
+            });
+        }
+        Ok(extra_bytes)
+    }
+
+    /// Creates an extra bytes VLR that stores each of the named dimensions as a double.
+    pub fn vlr(names: &[String]) -> Result<Vlr> {
+        let mut vlr = Vlr::new();
+        vlr.user_id[..USER_ID.len()].copy_from_slice(USER_ID);
+        vlr.record_id = RECORD_ID;
+        for name in names {
+            if name.len() > NAME_LENGTH {
+                return Err(Error::Configuration(format!("las extra dimension names can be at \
+                                                         most {} bytes: {}",
+                                                        NAME_LENGTH,
+                                                        name)));
+            }
+            let mut descriptor = [0; DESCRIPTOR_LENGTH];
+            descriptor[2] = DOUBLE;
+            descriptor[4..4 + name.len()].copy_from_slice(name.as_bytes());
+            vlr.record.extend_from_slice(&descriptor);
+        }
+        vlr.record_length_after_header = vlr.record.len() as u16;
+        Ok(vlr)
+    }
+
+    /// Returns the name of the extra dimension these bytes decode into, if they're decoded at all.
+    pub fn dimension(&self) -> Option<&str> {
+        match self.data_type {
+            1...10 => Some(&self.name),
+            _ => None,
+        }
+    }
+
+    /// Decodes this point's extra bytes into the point's extra dimensions.
+    pub fn decode(extra_bytes: &[ExtraBytes], bytes: &[u8], point: &mut Point) {
+        let mut bytes = bytes;
+        for extra in extra_bytes {
+            if bytes.len() < extra.len {
+                return;
+            }
+            if let Some(value) = extra.value(&bytes[..extra.len]) {
+                let _ = point.extra.insert(extra.name.clone(), value);
+            }
+            bytes = &bytes[extra.len..];
+        }
+    }
+
+    /// Encodes the named extra dimensions of a point as doubles, in order.
+    pub fn encode(names: &[String], point: &Point) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; 8 * names.len()];
+        for (name, chunk) in names.iter().zip(bytes.chunks_mut(8)) {
+            match point.extra.get(name) {
+                Some(value) => LittleEndian::write_f64(chunk, value.as_f64()),
+                None => return Err(Error::MissingDimension(name.clone())),
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn value(&self, bytes: &[u8]) -> Option<Value> {
+        let value = match self.data_type {
+            1 => Value::U8(bytes[0]),
+            2 => Value::I64(bytes[0] as i8 as i64),
+            3 => Value::U16(LittleEndian::read_u16(bytes)),
+            4 => Value::I64(LittleEndian::read_i16(bytes) as i64),
+            5 => Value::U64(LittleEndian::read_u32(bytes) as u64),
+            6 => Value::I64(LittleEndian::read_i32(bytes) as i64),
+            7 => Value::U64(LittleEndian::read_u64(bytes)),
+            8 => Value::I64(LittleEndian::read_i64(bytes)),
+            9 => Value::F64(LittleEndian::read_f32(bytes) as f64),
+            10 => Value::F64(LittleEndian::read_f64(bytes)),
+            _ => return None,
+        };
+        if self.scale.is_some() || self.offset.is_some() {
+            let scale = self.scale.unwrap_or(1.0);
+            Some(Value::F64(value.as_f64() * scale + self.offset.unwrap_or(0.0)))
+        } else {
+            Some(value)
+        }
+    }
+}
+
+fn is_extra_bytes(vlr: &Vlr) -> bool {
+    vlr.record_id == RECORD_ID && vlr.user_id.starts_with(USER_ID) &&
+    vlr.user_id[USER_ID.len()..].iter().all(|&b| b == 0)
+}
+
+fn base_len(data_type: u8) -> usize {
+    match data_type {
+        1 | 2 => 1,
+        3 | 4 => 2,
+        5 | 6 | 9 => 4,
+        _ => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::repeat;
+
+    use point::{Point, Value};
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let names = vec!["deviation".to_string(), "amplitude".to_string()];
+        let vlr = ExtraBytes::vlr(&names).unwrap();
+        let extra_bytes = ExtraBytes::from_vlrs(&[vlr]).unwrap();
+        assert_eq!(2, extra_bytes.len());
+
+        let mut point = Point::default();
+        let _ = point.extra.insert("deviation".to_string(), Value::U16(12));
+        let _ = point.extra.insert("amplitude".to_string(), Value::F64(3.5));
+        let bytes = ExtraBytes::encode(&names, &point).unwrap();
+        assert_eq!(16, bytes.len());
+
+        let mut after = Point::default();
+        ExtraBytes::decode(&extra_bytes, &bytes, &mut after);
+        assert_eq!(Some(&Value::F64(12.0)), after.extra.get("deviation"));
+        assert_eq!(Some(&Value::F64(3.5)), after.extra.get("amplitude"));
+    }
+
+    #[test]
+    fn missing_extra() {
+        assert!(ExtraBytes::encode(&["deviation".to_string()], &Point::default()).is_err());
+    }
+
+    #[test]
+    fn name_too_long() {
+        assert!(ExtraBytes::vlr(&[repeat("a").take(33).collect()]).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Keep only every nth point.
+
+use Result;
+use filter::{ConfigurableFilter, Filter};
+use point::Point;
+
+/// A filter that keeps every `step`th point, counting across chunks.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimate {
+    count: usize,
+    step: usize,
+}
+
+impl Decimate {
+    /// Creates a new decimation filter.
+    ///
+    /// A step of zero or one keeps every point.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::decimate::Decimate;
+    /// let filter = Decimate::new(10);
+    /// ```
+    pub fn new(step: usize) -> Decimate {
+        Decimate {
+            count: 0,
+            step: if step == 0 { 1 } else { step },
+        }
+    }
+}
+
+impl Filter for Decimate {
+    fn filter(&mut self, points: Vec<Point>) -> Result<Vec<Point>> {
+        let mut kept = Vec::with_capacity(points.len() / self.step + 1);
+        for point in points {
+            if self.count % self.step == 0 {
+                kept.push(point);
+            }
+            self.count += 1;
+        }
+        Ok(kept)
+    }
+}
+
+impl ConfigurableFilter for Decimate {
+    type Config = DecimateConfig;
+
+    fn open_filter(config: DecimateConfig) -> Result<Box<Filter>> {
+        Ok(Box::new(Decimate::new(config.step.unwrap_or(1))))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// `step` is the spacing between kept points, and defaults to one (keep everything).
+#[derive(Clone, Copy, Debug, Default, RustcDecodable)]
+pub struct DecimateConfig {
+    step: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::Filter;
+    use point::Point;
+    use super::*;
+
+    #[test]
+    fn across_chunks() {
+        let mut filter = Decimate::new(3);
+        assert_eq!(2, filter.filter(vec![Point::default(); 4]).unwrap().len());
+        assert_eq!(1, filter.filter(vec![Point::default(); 4]).unwrap().len());
+    }
+}
@@ -0,0 +1,86 @@
+//! Convert each point's GPS time from one standard to another.
+
+use Result;
+use filter::{ConfigurableFilter, Filter};
+use gps_time::{Converter, Standard};
+use metadata::Metadata;
+use point::Point;
+
+/// A filter that converts GPS times.
+///
+/// Points without a GPS time are passed through untouched.
+#[derive(Clone, Copy, Debug)]
+pub struct GpsTime {
+    converter: Converter,
+}
+
+impl GpsTime {
+    /// Creates a new GPS time filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::gps_time::GpsTime;
+    /// use pabst::gps_time::{Converter, Standard};
+    /// let converter = Converter::new(Standard::Utc, Standard::AdjustedStandard, None).unwrap();
+    /// let filter = GpsTime::new(converter);
+    /// ```
+    pub fn new(converter: Converter) -> GpsTime {
+        GpsTime { converter: converter }
+    }
+}
+
+impl Filter for GpsTime {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        for point in &mut points {
+            point.gps_time = point.gps_time.map(|t| self.converter.convert(t));
+        }
+        Ok(points)
+    }
+
+    fn metadata(&self, mut metadata: Metadata) -> Metadata {
+        metadata.gps_time = Some(self.converter.to());
+        metadata
+    }
+}
+
+impl ConfigurableFilter for GpsTime {
+    type Config = GpsTimeConfig;
+
+    fn open_filter(config: GpsTimeConfig) -> Result<Box<Filter>> {
+        let from = try!(Standard::from_name(config.from.as_ref().map_or("week_seconds", |s| s)));
+        let to = try!(Standard::from_name(config.to.as_ref().map_or("adjusted_standard", |s| s)));
+        Ok(Box::new(GpsTime::new(try!(Converter::new(from, to, config.week)))))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// `from` and `to` are "week_seconds", "standard", "adjusted_standard", or "utc", and default to
+/// converting week seconds to adjusted standard time. `week` is the GPS week, which is needed to
+/// convert from week seconds.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct GpsTimeConfig {
+    from: Option<String>,
+    to: Option<String>,
+    week: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::{Filter, open_filter};
+    use point::Point;
+    use toml;
+
+    #[test]
+    fn week_seconds() {
+        assert!(open_filter("gps_time", None).is_err());
+        let config: toml::Value = "week = 1721".parse().unwrap();
+        let mut filter = open_filter("gps_time", Some(config)).unwrap();
+        let points = filter.filter(vec![Point { gps_time: Some(100.0), ..Default::default() },
+                                        Point::default()])
+                           .unwrap();
+        assert_eq!(Some(1721.0 * 604800.0 + 100.0 - 1e9), points[0].gps_time);
+        assert_eq!(None, points[1].gps_time);
+    }
+}
@@ -0,0 +1,327 @@
+//! Filters that sit between a source and a sink.
+//!
+//! A filter takes a chunk of points and returns a chunk of points. It can change them, drop some,
+//! or add some. Filters can be strung together with a `Chain`, and a `FilteredSource` runs a
+//! source's points through a filter so the result can be used anywhere a `Source` can.
+
+pub mod decimate;
+pub mod gps_time;
+pub mod propagate;
+pub mod tpu;
+pub mod trajectory;
+pub mod transform;
+
+use std::fmt;
+
+use rustc_serialize::Decodable;
+use toml;
+
+use Result;
+use error::{Context, Error};
+use metadata::Metadata;
+use point::Point;
+use schema::Schema;
+use source::Source;
+
+enum FilterType {
+    Decimate,
+    GpsTime,
+    Propagate,
+    Tpu,
+    Trajectory,
+    Transform,
+}
+
+impl FilterType {
+    fn from_name(name: &str) -> Result<FilterType> {
+        match name {
+            "decimate" => Ok(FilterType::Decimate),
+            "gps_time" => Ok(FilterType::GpsTime),
+            "propagate" => Ok(FilterType::Propagate),
+            "tpu" => Ok(FilterType::Tpu),
+            "trajectory" => Ok(FilterType::Trajectory),
+            "transform" => Ok(FilterType::Transform),
+            _ => Err(Error::Configuration(format!("unknown filter: {}", name))),
+        }
+    }
+}
+
+/// Opens a filter by name, with the given options.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::filter::open_filter;
+/// let filter = open_filter("decimate", None).unwrap();
+/// assert!(open_filter("not-a-filter", None).is_err());
+/// ```
+pub fn open_filter(name: &str, config: Option<toml::Value>) -> Result<Box<Filter>> {
+    let filter = try!(open(try!(FilterType::from_name(name)), config)
+                          .map_err(|e| e.context(Context::new(name))));
+    Ok(Box::new(ContextFilter {
+        name: name.to_string(),
+        filter: filter,
+    }))
+}
+
+fn open(filter_type: FilterType, config: Option<toml::Value>) -> Result<Box<Filter>> {
+    let mut decoder = config.map(|c| toml::Decoder::new(c));
+    match filter_type {
+        FilterType::Decimate => decimate::Decimate::open_filter(decode_or_default!(ConfigurableFilter, decimate::Decimate, decoder)),
+        FilterType::GpsTime => gps_time::GpsTime::open_filter(decode_or_default!(ConfigurableFilter, gps_time::GpsTime, decoder)),
+        FilterType::Propagate => propagate::Propagate::open_filter(decode_or_default!(ConfigurableFilter, propagate::Propagate, decoder)),
+        FilterType::Tpu => tpu::Tpu::open_filter(decode_or_default!(ConfigurableFilter, tpu::Tpu, decoder)),
+        FilterType::Trajectory => trajectory::Attach::open_filter(decode_or_default!(ConfigurableFilter, trajectory::Attach, decoder)),
+        FilterType::Transform => transform::Transform::open_filter(decode_or_default!(ConfigurableFilter, transform::Transform, decoder)),
+    }
+}
+
+/// A filter on chunks of points.
+pub trait Filter {
+    /// Filters a chunk of points.
+    ///
+    /// The returned chunk doesn't have to be the same size as the one that went in, and can be
+    /// empty.
+    fn filter(&mut self, points: Vec<Point>) -> Result<Vec<Point>>;
+
+    /// Returns the dimensions of the points that come out of this filter, given the dimensions
+    /// of the points that go in.
+    ///
+    /// Filters that don't add any dimensions can leave this alone.
+    fn schema(&self, schema: Schema) -> Schema {
+        schema
+    }
+
+    /// Returns the metadata of the points that come out of this filter, given the metadata of
+    /// the points that go in.
+    ///
+    /// Filters that change what the metadata says, e.g. the GPS time standard, should change the
+    /// metadata to match.
+    fn metadata(&self, metadata: Metadata) -> Metadata {
+        metadata
+    }
+}
+
+impl Filter for Box<Filter> {
+    fn filter(&mut self, points: Vec<Point>) -> Result<Vec<Point>> {
+        (**self).filter(points)
+    }
+
+    fn schema(&self, schema: Schema) -> Schema {
+        (**self).schema(schema)
+    }
+
+    fn metadata(&self, metadata: Metadata) -> Metadata {
+        (**self).metadata(metadata)
+    }
+}
+
+/// A filter that says where its errors happened.
+struct ContextFilter {
+    name: String,
+    filter: Box<Filter>,
+}
+
+impl Filter for ContextFilter {
+    fn filter(&mut self, points: Vec<Point>) -> Result<Vec<Point>> {
+        let name = &self.name;
+        self.filter.filter(points).map_err(|e| e.context(Context::new(name.clone())))
+    }
+
+    fn schema(&self, schema: Schema) -> Schema {
+        self.filter.schema(schema)
+    }
+
+    fn metadata(&self, metadata: Metadata) -> Metadata {
+        self.filter.metadata(metadata)
+    }
+}
+
+/// A filter that can be created from a decodable configuration.
+pub trait ConfigurableFilter {
+    /// Decodable configuration object.
+    type Config: Decodable;
+
+    /// Creates a new filter with the given config.
+    fn open_filter(config: Self::Config) -> Result<Box<Filter>>;
+}
+
+/// A sequence of filters, applied in order.
+#[derive(Default)]
+pub struct Chain {
+    filters: Vec<Box<Filter>>,
+}
+
+impl fmt::Debug for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Chain {{ {} filters }}", self.filters.len())
+    }
+}
+
+impl Chain {
+    /// Creates a new, empty chain, which passes points through untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::Chain;
+    /// let chain = Chain::new();
+    /// ```
+    pub fn new() -> Chain {
+        Chain { filters: Vec::new() }
+    }
+
+    /// Adds a filter to the end of this chain.
+    pub fn push(&mut self, filter: Box<Filter>) {
+        self.filters.push(filter);
+    }
+
+    /// Returns the number of filters in this chain.
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Returns true if this chain has no filters.
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+}
+
+impl Filter for Chain {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        for filter in &mut self.filters {
+            points = try!(filter.filter(points));
+        }
+        Ok(points)
+    }
+
+    fn schema(&self, schema: Schema) -> Schema {
+        self.filters.iter().fold(schema, |schema, filter| filter.schema(schema))
+    }
+
+    fn metadata(&self, metadata: Metadata) -> Metadata {
+        self.filters.iter().fold(metadata, |metadata, filter| filter.metadata(metadata))
+    }
+}
+
+/// A source whose points are run through a filter.
+#[derive(Debug)]
+pub struct FilteredSource<S: Source, F: Filter> {
+    filter: F,
+    source: S,
+}
+
+impl<S: Source, F: Filter> FilteredSource<S, F> {
+    /// Wraps a source with a filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::{Chain, FilteredSource};
+    /// use pabst::source::{open_file_source, Source};
+    /// let source = open_file_source("data/1.0_0.las", None).unwrap();
+    /// let mut source = FilteredSource::new(source, Chain::new());
+    /// assert_eq!(1, source.source_to_end(100).unwrap().len());
+    /// ```
+    pub fn new(source: S, filter: F) -> FilteredSource<S, F> {
+        FilteredSource {
+            filter: filter,
+            source: source,
+        }
+    }
+
+    /// Returns the wrapped source and filter.
+    pub fn into_inner(self) -> (S, F) {
+        (self.source, self.filter)
+    }
+}
+
+impl<S: Source, F: Filter> Source for FilteredSource<S, F> {
+    /// Sources points and filters them.
+    ///
+    /// If the filter throws away an entire chunk we keep reading, so this only returns `None` when
+    /// the underlying source is out of points.
+    fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        loop {
+            match try!(self.source.source(want)) {
+                Some(points) => {
+                    let points = try!(self.filter.filter(points));
+                    if !points.is_empty() {
+                        return Ok(Some(points));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the underlying source's guess, which won't be right if the filter adds or drops
+    /// points.
+    fn source_len(&mut self) -> Option<usize> {
+        self.source.source_len()
+    }
+
+    fn resumable(&self) -> bool {
+        self.source.resumable()
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        self.source.schema().map(|schema| self.filter.schema(schema))
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        self.source.metadata().map(|metadata| self.filter.metadata(metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Result;
+    use point::Point;
+    use source::{open_file_source, Source};
+    use super::*;
+
+    struct Scale(f64);
+
+    impl Filter for Scale {
+        fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+            for point in &mut points {
+                point.x *= self.0;
+            }
+            Ok(points)
+        }
+    }
+
+    struct DropAll;
+
+    impl Filter for DropAll {
+        fn filter(&mut self, _: Vec<Point>) -> Result<Vec<Point>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let mut chain = Chain::new();
+        chain.push(Box::new(Scale(2.0)));
+        chain.push(Box::new(Scale(3.0)));
+        let points = chain.filter(vec![Point { x: 1.0, ..Default::default() }]).unwrap();
+        assert_eq!(6.0, points[0].x);
+    }
+
+    #[test]
+    fn filtered_source() {
+        let source = open_file_source("data/4-points.xyz", None).unwrap();
+        let mut source = FilteredSource::new(source, Scale(10.0));
+        let points = source.source_to_end(3).unwrap();
+        assert_eq!(4, points.len());
+        assert_eq!(40.0, points[3].x);
+    }
+
+    #[test]
+    fn dropped_chunks() {
+        let source = open_file_source("data/4-points.xyz", None).unwrap();
+        let mut source = FilteredSource::new(source, DropAll);
+        assert!(source.source(1).unwrap().is_none());
+    }
+}
@@ -0,0 +1,139 @@
+//! Propagate component errors through each point's partials.
+//!
+//! The results go into each point's extra dimensions, so any sink that writes extra dimensions can
+//! write them: `sigma_x`, `sigma_y`, and `sigma_z` are the standard deviations of the point's
+//! coordinates, and `tpu_horizontal` and `tpu_vertical` are its total propagated uncertainty.
+
+use Result;
+use filter::{ConfigurableFilter, Filter};
+use point::{Component, ComponentErrors, Point, Value};
+use schema::Schema;
+
+const EXTRA_DIMENSIONS: [&'static str; 5] = ["sigma_x",
+                                             "sigma_y",
+                                             "sigma_z",
+                                             "tpu_horizontal",
+                                             "tpu_vertical"];
+
+/// A filter that propagates component errors into per-point uncertainty.
+///
+/// Points without partials are passed through untouched.
+#[derive(Clone, Copy, Debug)]
+pub struct Propagate {
+    errors: ComponentErrors,
+}
+
+impl Propagate {
+    /// Creates a new propagation filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::propagate::Propagate;
+    /// use pabst::point::{Component, ComponentErrors};
+    /// let errors = ComponentErrors::new().std_dev(Component::Range, 0.02);
+    /// let filter = Propagate::new(errors);
+    /// ```
+    pub fn new(errors: ComponentErrors) -> Propagate {
+        Propagate { errors: errors }
+    }
+}
+
+impl Filter for Propagate {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        for point in &mut points {
+            let covariance = match point.partials {
+                Some(ref partials) => partials.propagate(&self.errors),
+                None => continue,
+            };
+            let std_devs = covariance.std_devs();
+            let values = [std_devs.x,
+                          std_devs.y,
+                          std_devs.z,
+                          covariance.horizontal(),
+                          covariance.vertical()];
+            for (name, &value) in EXTRA_DIMENSIONS.iter().zip(&values) {
+                let _ = point.extra.insert(name.to_string(), Value::F64(value));
+            }
+        }
+        Ok(points)
+    }
+
+    fn schema(&self, mut schema: Schema) -> Schema {
+        for name in &EXTRA_DIMENSIONS {
+            schema.insert(*name);
+        }
+        schema
+    }
+}
+
+impl ConfigurableFilter for Propagate {
+    type Config = PropagateConfig;
+
+    fn open_filter(config: PropagateConfig) -> Result<Box<Filter>> {
+        let mut errors = ComponentErrors::new();
+        for &(component, std_dev) in &[(Component::Range, config.range),
+                                       (Component::ScanAngle, config.scan_angle),
+                                       (Component::BoresightRoll, config.boresight_roll),
+                                       (Component::BoresightPitch, config.boresight_pitch),
+                                       (Component::BoresightYaw, config.boresight_yaw),
+                                       (Component::LeverArmX, config.lever_arm_x),
+                                       (Component::LeverArmY, config.lever_arm_y),
+                                       (Component::LeverArmZ, config.lever_arm_z),
+                                       (Component::Roll, config.roll),
+                                       (Component::Pitch, config.pitch),
+                                       (Component::Yaw, config.yaw),
+                                       (Component::GnssX, config.gnss_x),
+                                       (Component::GnssY, config.gnss_y),
+                                       (Component::GnssZ, config.gnss_z)] {
+            errors = errors.std_dev(component, std_dev.unwrap_or(0.0));
+        }
+        Ok(Box::new(Propagate::new(errors)))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// Each key is the standard deviation of one component, in meters or radians, and defaults to
+/// zero. Components are assumed to be uncorrelated; use `ComponentErrors` directly for anything
+/// fancier.
+#[derive(Clone, Copy, Debug, Default, RustcDecodable)]
+pub struct PropagateConfig {
+    range: Option<f64>,
+    scan_angle: Option<f64>,
+    boresight_roll: Option<f64>,
+    boresight_pitch: Option<f64>,
+    boresight_yaw: Option<f64>,
+    lever_arm_x: Option<f64>,
+    lever_arm_y: Option<f64>,
+    lever_arm_z: Option<f64>,
+    roll: Option<f64>,
+    pitch: Option<f64>,
+    yaw: Option<f64>,
+    gnss_x: Option<f64>,
+    gnss_y: Option<f64>,
+    gnss_z: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::{Filter, open_filter};
+    use point::{Component, Partials, Point, Xyz};
+    use toml;
+
+    #[test]
+    fn gnss_errors() {
+        let config: toml::Value = "gnss_x = 0.03\ngnss_y = 0.04\ngnss_z = 0.05".parse().unwrap();
+        let mut filter = open_filter("propagate", Some(config)).unwrap();
+        let partials = Partials::new()
+                           .with(Component::GnssX, Xyz::new(1.0, 0.0, 0.0))
+                           .with(Component::GnssY, Xyz::new(0.0, 1.0, 0.0))
+                           .with(Component::GnssZ, Xyz::new(0.0, 0.0, 1.0));
+        let point = Point { partials: Some(partials), ..Default::default() };
+        let points = filter.filter(vec![point, Point::default()]).unwrap();
+        let tpu_horizontal = points[0].extra["tpu_horizontal"].as_f64();
+        assert!((tpu_horizontal - 0.05).abs() < 1e-12);
+        assert!((points[0].extra["tpu_vertical"].as_f64() - 0.05).abs() < 1e-12);
+        assert!(points[1].extra.is_empty());
+    }
+}
@@ -0,0 +1,379 @@
+//! Compute each point's partials from the airborne LiDAR georeferencing equation.
+//!
+//! The georeferencing equation puts a laser return into the world:
+//!
+//! ```text
+//! p = g + T R(roll, pitch, yaw) (l + B(boresight) r(range, scan_angle))
+//! ```
+//!
+//! where `g` is the platform position from the trajectory, `R` is the platform attitude, `l` is the
+//! lever arm from the IMU to the scanner, `B` is the boresight rotation from the scanner to the
+//! IMU, and `r = range * (0, sin(scan_angle), cos(scan_angle))` is the laser vector in the scanner
+//! frame. The body frame is x forward, y right, z down, and `T` turns north-east-down into the
+//! points' east-north-up.
+//!
+//! The partial derivatives of `p` with respect to each of the fourteen components go into the
+//! point's `Partials`, so the `propagate` filter can turn them into per-point uncertainty.
+
+use std::path::Path;
+
+use {Error, Result};
+use filter::{ConfigurableFilter, Filter};
+use point::{Component, Partials, Point, Xyz};
+use trajectory::{Format, Pose, Trajectory};
+
+type Matrix3 = [[f64; 3]; 3];
+
+const NED_TO_ENU: Matrix3 = [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]];
+
+/// The mounting of a scanner on its platform.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Scanner {
+    /// The offset from the IMU to the scanner, in meters, in the body frame.
+    pub lever_arm: [f64; 3],
+    /// The roll, pitch, and yaw of the scanner relative to the IMU, in radians.
+    pub boresight: [f64; 3],
+}
+
+impl Scanner {
+    /// Returns the location of a return, given the platform's pose, the range, and the scan angle
+    /// in radians.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::tpu::Scanner;
+    /// use pabst::trajectory::Pose;
+    /// let pose = Pose { z: 1000.0, ..Default::default() };
+    /// let xyz = Scanner::default().georeference(&pose, 1000.0, 0.0);
+    /// assert_eq!(0.0, xyz.z);
+    /// ```
+    pub fn georeference(&self, pose: &Pose, range: f64, scan_angle: f64) -> Xyz {
+        let platform = multiply(&NED_TO_ENU, &attitude(pose.roll, pose.pitch, pose.yaw));
+        let beam = laser(range, scan_angle);
+        let body = add(self.lever_arm, apply(&self.boresight_matrix(), beam));
+        let offset = apply(&platform, body);
+        Xyz::new(pose.x + offset[0], pose.y + offset[1], pose.z + offset[2])
+    }
+
+    /// Returns the partial derivatives of a return's location with respect to each component.
+    ///
+    /// Angular partials are per radian.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::tpu::Scanner;
+    /// use pabst::point::Component;
+    /// use pabst::trajectory::Pose;
+    /// let pose = Pose { z: 1000.0, ..Default::default() };
+    /// let partials = Scanner::default().partials(&pose, 1000.0, 0.0);
+    /// assert_eq!(-1.0, partials.get(Component::Range).z);
+    /// ```
+    pub fn partials(&self, pose: &Pose, range: f64, scan_angle: f64) -> Partials {
+        let (roll, pitch, yaw) = (pose.roll, pose.pitch, pose.yaw);
+        let (b_roll, b_pitch, b_yaw) = (self.boresight[0], self.boresight[1], self.boresight[2]);
+        let platform = multiply(&NED_TO_ENU, &attitude(roll, pitch, yaw));
+        let boresight = self.boresight_matrix();
+        let beam = laser(range, scan_angle);
+        let body = add(self.lever_arm, apply(&boresight, beam));
+        let world = |m: &Matrix3, v: [f64; 3]| {
+            let v = apply(m, v);
+            Xyz::new(v[0], v[1], v[2])
+        };
+        let scanner = multiply(&platform, &boresight);
+        let d_boresight = |m: Matrix3| world(&multiply(&platform, &m), beam);
+        let d_attitude = |m: Matrix3| world(&multiply(&NED_TO_ENU, &m), body);
+        Partials::new()
+            .with(Component::Range,
+                  world(&scanner, [0.0, scan_angle.sin(), scan_angle.cos()]))
+            .with(Component::ScanAngle,
+                  world(&scanner, [0.0, range * scan_angle.cos(), -range * scan_angle.sin()]))
+            .with(Component::BoresightRoll,
+                  d_boresight(rotation(rz(b_yaw), ry(b_pitch), d_rx(b_roll))))
+            .with(Component::BoresightPitch,
+                  d_boresight(rotation(rz(b_yaw), d_ry(b_pitch), rx(b_roll))))
+            .with(Component::BoresightYaw,
+                  d_boresight(rotation(d_rz(b_yaw), ry(b_pitch), rx(b_roll))))
+            .with(Component::LeverArmX, world(&platform, [1.0, 0.0, 0.0]))
+            .with(Component::LeverArmY, world(&platform, [0.0, 1.0, 0.0]))
+            .with(Component::LeverArmZ, world(&platform, [0.0, 0.0, 1.0]))
+            .with(Component::Roll, d_attitude(rotation(rz(yaw), ry(pitch), d_rx(roll))))
+            .with(Component::Pitch, d_attitude(rotation(rz(yaw), d_ry(pitch), rx(roll))))
+            .with(Component::Yaw, d_attitude(rotation(d_rz(yaw), ry(pitch), rx(roll))))
+            .with(Component::GnssX, Xyz::new(1.0, 0.0, 0.0))
+            .with(Component::GnssY, Xyz::new(0.0, 1.0, 0.0))
+            .with(Component::GnssZ, Xyz::new(0.0, 0.0, 1.0))
+    }
+
+    fn boresight_matrix(&self) -> Matrix3 {
+        attitude(self.boresight[0], self.boresight[1], self.boresight[2])
+    }
+}
+
+/// A filter that fills in each point's partials from a trajectory and a scanner model.
+///
+/// Points need a gps time, a range, and a scan angle (in degrees, like las) that falls inside the
+/// trajectory. Points that don't have all three are passed through untouched.
+#[derive(Clone, Debug)]
+pub struct Tpu {
+    scanner: Scanner,
+    trajectory: Trajectory,
+}
+
+impl Tpu {
+    /// Creates a new tpu filter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::tpu::{Scanner, Tpu};
+    /// use pabst::trajectory::Trajectory;
+    /// let trajectory = Trajectory::from_path("data/trajectory.txt").unwrap();
+    /// let filter = Tpu::new(trajectory, Scanner::default());
+    /// ```
+    pub fn new(trajectory: Trajectory, scanner: Scanner) -> Tpu {
+        Tpu {
+            scanner: scanner,
+            trajectory: trajectory,
+        }
+    }
+}
+
+impl Filter for Tpu {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        for point in &mut points {
+            let (gps_time, range, scan_angle) = match (point.gps_time,
+                                                       point.range,
+                                                       point.scan_angle) {
+                (Some(gps_time), Some(range), Some(scan_angle)) => (gps_time, range, scan_angle),
+                _ => continue,
+            };
+            if let Some(pose) = self.trajectory.pose_at(gps_time) {
+                point.partials = Some(self.scanner.partials(&pose, range, scan_angle.to_radians()));
+            }
+        }
+        Ok(points)
+    }
+}
+
+impl ConfigurableFilter for Tpu {
+    type Config = TpuConfig;
+
+    fn open_filter(config: TpuConfig) -> Result<Box<Filter>> {
+        let trajectory = match config.trajectory {
+            Some(ref path) => {
+                let format = match config.trajectory_format {
+                    Some(ref name) => Some(try!(Format::from_name(name))),
+                    None => None,
+                };
+                try!(Trajectory::open(Path::new(path), format, config.utm_zone))
+            }
+            None => {
+                return Err(Error::Configuration("the tpu filter needs a trajectory".to_string()))
+            }
+        };
+        if trajectory.is_geographic() {
+            return Err(Error::Configuration("georeferencing from an sbet needs a utm_zone"
+                                                .to_string()));
+        }
+        let mut scanner = Scanner::default();
+        if let Some(lever_arm) = config.lever_arm {
+            scanner.lever_arm = try!(three("lever_arm", &lever_arm));
+        }
+        if let Some(boresight) = config.boresight {
+            let boresight = try!(three("boresight", &boresight));
+            for (angle, &degrees) in scanner.boresight.iter_mut().zip(boresight.iter()) {
+                *angle = degrees.to_radians();
+            }
+        }
+        Ok(Box::new(Tpu::new(trajectory, scanner)))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// `trajectory` is the path to a trajectory file, and is required. `trajectory_format` and
+/// `utm_zone` are as in the trajectory filter's `format` and `utm_zone`, and an SBET needs a
+/// `utm_zone`. `lever_arm` is the x, y, and z offset from the IMU to the scanner in meters, and
+/// `boresight` is the scanner's roll, pitch, and yaw relative to the IMU in degrees. Both default
+/// to zeros.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct TpuConfig {
+    trajectory: Option<String>,
+    trajectory_format: Option<String>,
+    utm_zone: Option<i32>,
+    lever_arm: Option<Vec<f64>>,
+    boresight: Option<Vec<f64>>,
+}
+
+fn three(name: &str, values: &[f64]) -> Result<[f64; 3]> {
+    if values.len() != 3 {
+        return Err(Error::Configuration(format!("{} needs three values, not {}",
+                                                name,
+                                                values.len())));
+    }
+    Ok([values[0], values[1], values[2]])
+}
+
+fn laser(range: f64, scan_angle: f64) -> [f64; 3] {
+    [0.0, range * scan_angle.sin(), range * scan_angle.cos()]
+}
+
+fn attitude(roll: f64, pitch: f64, yaw: f64) -> Matrix3 {
+    rotation(rz(yaw), ry(pitch), rx(roll))
+}
+
+fn rotation(z: Matrix3, y: Matrix3, x: Matrix3) -> Matrix3 {
+    multiply(&z, &multiply(&y, &x))
+}
+
+fn rx(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]]
+}
+
+fn ry(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]]
+}
+
+fn rz(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]]
+}
+
+fn d_rx(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[0.0, 0.0, 0.0], [0.0, -s, -c], [0.0, c, -s]]
+}
+
+fn d_ry(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[-s, 0.0, c], [0.0, 0.0, 0.0], [-c, 0.0, -s]]
+}
+
+fn d_rz(a: f64) -> Matrix3 {
+    let (s, c) = a.sin_cos();
+    [[-s, -c, 0.0], [c, -s, 0.0], [0.0, 0.0, 0.0]]
+}
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut product = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            product[i][j] = (0..3).fold(0.0, |sum, k| sum + a[i][k] * b[k][j]);
+        }
+    }
+    product
+}
+
+fn apply(m: &Matrix3, v: [f64; 3]) -> [f64; 3] {
+    let mut out = [0.0; 3];
+    for i in 0..3 {
+        out[i] = m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2];
+    }
+    out
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::{Filter, open_filter};
+    use point::{COMPONENTS, Component, Point, Xyz};
+    use toml;
+    use trajectory::Pose;
+    use super::*;
+
+    #[test]
+    fn level_flight_north() {
+        let pose = Pose { z: 1000.0, ..Default::default() };
+        let scanner = Scanner::default();
+        let partials = scanner.partials(&pose, 1000.0, 0.0);
+        // Rolling right wing down points the laser west, and scanning right points it east.
+        assert!((partials.get(Component::Roll).x + 1000.0).abs() < 1e-9);
+        assert!((partials.get(Component::ScanAngle).x - 1000.0).abs() < 1e-9);
+        assert!((partials.get(Component::Pitch).y - 1000.0).abs() < 1e-9);
+        assert_eq!(Xyz::new(0.0, 0.0, 1.0), partials.get(Component::GnssZ));
+    }
+
+    #[test]
+    fn matches_finite_differences() {
+        let pose = Pose {
+            x: 10.0,
+            y: 20.0,
+            z: 1200.0,
+            roll: 0.02,
+            pitch: -0.03,
+            yaw: 1.1,
+            ..Default::default()
+        };
+        let scanner = Scanner {
+            lever_arm: [0.2, -0.1, 0.5],
+            boresight: [0.001, -0.002, 0.003],
+        };
+        let (range, scan_angle) = (1300.0, 0.3);
+        let partials = scanner.partials(&pose, range, scan_angle);
+        let h = 1e-6;
+        for &component in &COMPONENTS {
+            let (mut pose, mut scanner) = (pose, scanner);
+            let (mut range, mut scan_angle) = (range, scan_angle);
+            let before = scanner.georeference(&pose, range, scan_angle);
+            {
+                let value = match component {
+                    Component::Range => &mut range,
+                    Component::ScanAngle => &mut scan_angle,
+                    Component::BoresightRoll => &mut scanner.boresight[0],
+                    Component::BoresightPitch => &mut scanner.boresight[1],
+                    Component::BoresightYaw => &mut scanner.boresight[2],
+                    Component::LeverArmX => &mut scanner.lever_arm[0],
+                    Component::LeverArmY => &mut scanner.lever_arm[1],
+                    Component::LeverArmZ => &mut scanner.lever_arm[2],
+                    Component::Roll => &mut pose.roll,
+                    Component::Pitch => &mut pose.pitch,
+                    Component::Yaw => &mut pose.yaw,
+                    Component::GnssX => &mut pose.x,
+                    Component::GnssY => &mut pose.y,
+                    Component::GnssZ => &mut pose.z,
+                };
+                *value += h;
+            }
+            let numeric = (scanner.georeference(&pose, range, scan_angle) - before) * (1.0 / h);
+            let analytic = partials.get(component);
+            let error = numeric - analytic;
+            assert!(error.x.abs() < 1e-3 && error.y.abs() < 1e-3 && error.z.abs() < 1e-3,
+                    "{}: {:?} != {:?}",
+                    component.name(),
+                    numeric,
+                    analytic);
+        }
+    }
+
+    #[test]
+    fn tpu_then_propagate() {
+        let config: toml::Value = r#"trajectory = "data/trajectory.txt""#.parse().unwrap();
+        let mut tpu = open_filter("tpu", Some(config)).unwrap();
+        let config: toml::Value = "range = 0.02\nroll = 0.001".parse().unwrap();
+        let mut propagate = open_filter("propagate", Some(config)).unwrap();
+        let point = Point {
+            gps_time: Some(100.5),
+            range: Some(1000.0),
+            scan_angle: Some(0.0),
+            ..Default::default()
+        };
+        let points = tpu.filter(vec![point, Point::default()]).unwrap();
+        assert!(points[0].partials.is_some());
+        assert!(points[1].partials.is_none());
+        let points = propagate.filter(points).unwrap();
+        assert!((points[0].extra["tpu_vertical"].as_f64() - 0.02).abs() < 1e-9);
+        assert!((points[0].extra["tpu_horizontal"].as_f64() - 1.0).abs() < 1e-9);
+
+        let config: toml::Value = "trajectory = \"data/trajectory.txt\"\nlever_arm = [0.0]"
+                                      .parse()
+                                      .unwrap();
+        assert!(open_filter("tpu", Some(config)).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Attach the platform's trajectory to each point.
+//!
+//! Each point with a gps time inside the trajectory gets the interpolated platform position and
+//! attitude as extra dimensions: `sensor_x`, `sensor_y`, `sensor_z`, and `sensor_roll`,
+//! `sensor_pitch`, and `sensor_yaw` in degrees. Points without a range, e.g. from las files, can
+//! also get one, as the distance from the platform to the point.
+
+use std::path::Path;
+
+use {Error, Result};
+use filter::{ConfigurableFilter, Filter};
+use point::{Dimension, Point, Value};
+use schema::Schema;
+use trajectory::{Format, Trajectory};
+
+const EXTRA_DIMENSIONS: [&'static str; 6] = ["sensor_x",
+                                             "sensor_y",
+                                             "sensor_z",
+                                             "sensor_roll",
+                                             "sensor_pitch",
+                                             "sensor_yaw"];
+
+/// A filter that attaches interpolated poses to points.
+#[derive(Clone, Debug)]
+pub struct Attach {
+    extra: bool,
+    range: bool,
+    trajectory: Trajectory,
+}
+
+impl Attach {
+    /// Creates a new filter that attaches poses as extra dimensions, and fills in missing ranges.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::trajectory::Attach;
+    /// use pabst::trajectory::Trajectory;
+    /// let trajectory = Trajectory::from_path("data/trajectory.txt").unwrap();
+    /// let filter = Attach::new(trajectory);
+    /// ```
+    pub fn new(trajectory: Trajectory) -> Attach {
+        Attach {
+            extra: true,
+            range: true,
+            trajectory: trajectory,
+        }
+    }
+
+    /// Sets whether poses are attached as extra dimensions.
+    pub fn extra(mut self, extra: bool) -> Attach {
+        self.extra = extra;
+        self
+    }
+
+    /// Sets whether missing ranges are computed.
+    pub fn range(mut self, range: bool) -> Attach {
+        self.range = range;
+        self
+    }
+}
+
+impl Filter for Attach {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        for point in &mut points {
+            let pose = match point.gps_time.and_then(|t| self.trajectory.pose_at(t)) {
+                Some(pose) => pose,
+                None => continue,
+            };
+            if self.range && point.range.is_none() {
+                let (dx, dy, dz) = (point.x - pose.x, point.y - pose.y, point.z - pose.z);
+                point.range = Some((dx * dx + dy * dy + dz * dz).sqrt());
+            }
+            if self.extra {
+                let values = [pose.x,
+                              pose.y,
+                              pose.z,
+                              pose.roll.to_degrees(),
+                              pose.pitch.to_degrees(),
+                              pose.yaw.to_degrees()];
+                for (name, &value) in EXTRA_DIMENSIONS.iter().zip(&values) {
+                    let _ = point.extra.insert(name.to_string(), Value::F64(value));
+                }
+            }
+        }
+        Ok(points)
+    }
+
+    fn schema(&self, mut schema: Schema) -> Schema {
+        if self.range {
+            schema.insert(Dimension::Range.name());
+        }
+        if self.extra {
+            for name in &EXTRA_DIMENSIONS {
+                schema.insert(*name);
+            }
+        }
+        schema
+    }
+}
+
+impl ConfigurableFilter for Attach {
+    type Config = TrajectoryConfig;
+
+    fn open_filter(config: TrajectoryConfig) -> Result<Box<Filter>> {
+        let trajectory = try!(config.open());
+        let range = config.range.unwrap_or(true);
+        if range && trajectory.is_geographic() {
+            return Err(Error::Configuration("computing ranges from an sbet needs a utm_zone, \
+                                             or range = false"
+                                                .to_string()));
+        }
+        Ok(Box::new(Attach::new(trajectory).extra(config.extra.unwrap_or(true)).range(range)))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// `path` is the trajectory file, and is required. `format` is "text" or "sbet", and is guessed
+/// from the extension if not given. `utm_zone` projects SBET positions, and is needed to compute
+/// ranges from an SBET. `extra` and `range` turn off attaching the pose and computing missing
+/// ranges.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct TrajectoryConfig {
+    path: Option<String>,
+    format: Option<String>,
+    utm_zone: Option<i32>,
+    extra: Option<bool>,
+    range: Option<bool>,
+}
+
+impl TrajectoryConfig {
+    fn open(&self) -> Result<Trajectory> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Err(Error::Configuration("a trajectory needs a path".to_string())),
+        };
+        let format = match self.format {
+            Some(ref name) => Some(try!(Format::from_name(name))),
+            None => None,
+        };
+        Trajectory::open(Path::new(path), format, self.utm_zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, remove_file};
+    use std::io::Write;
+
+    use filter::{Filter, open_filter};
+    use point::Point;
+    use toml;
+
+    #[test]
+    fn attach() {
+        let config: toml::Value = r#"path = "data/trajectory.txt""#.parse().unwrap();
+        let mut filter = open_filter("trajectory", Some(config)).unwrap();
+        let point = Point {
+            x: 500005.0,
+            y: 4000025.0,
+            gps_time: Some(100.5),
+            ..Default::default()
+        };
+        let ranged = Point { range: Some(1.0), ..point.clone() };
+        let points = filter.filter(vec![point, ranged, Point::default()]).unwrap();
+        assert_eq!(Some(1000.0), points[0].range);
+        assert_eq!(500005.0, points[0].extra["sensor_x"].as_f64());
+        assert_eq!(0.0, points[0].extra["sensor_yaw"].as_f64());
+        assert_eq!(Some(1.0), points[1].range);
+        assert!(points[2].extra.is_empty());
+
+        let config: toml::Value = "path = \"data/trajectory.txt\"\nextra = false\nrange = false"
+                                      .parse()
+                                      .unwrap();
+        let mut filter = open_filter("trajectory", Some(config)).unwrap();
+        let point = Point { gps_time: Some(101.0), ..Default::default() };
+        let points = filter.filter(vec![point]).unwrap();
+        assert_eq!(None, points[0].range);
+        assert!(points[0].extra.is_empty());
+        assert!(open_filter("trajectory", None).is_err());
+
+        let _ = File::create("attach.sbet").unwrap().write_all(&[0; 136]).unwrap();
+        let config: toml::Value = r#"path = "attach.sbet""#.parse().unwrap();
+        assert!(open_filter("trajectory", Some(config)).is_err());
+        let config: toml::Value = "path = \"attach.sbet\"\nrange = false".parse().unwrap();
+        assert!(open_filter("trajectory", Some(config)).is_ok());
+        let config: toml::Value = "path = \"attach.sbet\"\nutm_zone = 11".parse().unwrap();
+        assert!(open_filter("trajectory", Some(config)).is_ok());
+        remove_file("attach.sbet").unwrap();
+    }
+}
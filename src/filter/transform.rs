@@ -0,0 +1,222 @@
+//! Move points with 4x4 transformation matrices.
+//!
+//! Riegl's terrestrial scanners put each scan position into a project with a SOP (scanner's own
+//! position) matrix, and the project into the world with a POP (project's own position) matrix.
+//! Both are plain 4x4 matrices, so a transform takes a list of them and applies them in order.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use Result;
+use error::Error;
+use filter::{ConfigurableFilter, Filter};
+use metadata::Metadata;
+use point::Point;
+
+/// A 4x4 transformation matrix, row major.
+pub type Matrix = [[f64; 4]; 4];
+
+/// The identity matrix.
+pub const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0],
+                              [0.0, 1.0, 0.0, 0.0],
+                              [0.0, 0.0, 1.0, 0.0],
+                              [0.0, 0.0, 0.0, 1.0]];
+
+/// A filter that transforms each point's location, waveform, and partials.
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    matrix: Matrix,
+}
+
+impl Transform {
+    /// Creates a transform that applies each matrix in turn, e.g. a SOP and then a POP.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::filter::Filter;
+    /// use pabst::filter::transform::Transform;
+    /// use pabst::point::Point;
+    /// let shift = [[1.0, 0.0, 0.0, 1.0],
+    ///              [0.0, 1.0, 0.0, 0.0],
+    ///              [0.0, 0.0, 1.0, 0.0],
+    ///              [0.0, 0.0, 0.0, 1.0]];
+    /// let mut transform = Transform::new(&[shift, shift]);
+    /// let points = transform.filter(vec![Point::default()]).unwrap();
+    /// assert_eq!(2.0, points[0].x);
+    /// ```
+    pub fn new(matrices: &[Matrix]) -> Transform {
+        Transform { matrix: matrices.iter().fold(IDENTITY, |product, m| multiply(m, &product)) }
+    }
+
+    /// Returns the combined matrix.
+    pub fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    fn apply(&self, xyz: [f64; 3]) -> [f64; 3] {
+        let m = &self.matrix;
+        let mut out = [0.0; 3];
+        for i in 0..3 {
+            out[i] = m[i][0] * xyz[0] + m[i][1] * xyz[1] + m[i][2] * xyz[2] + m[i][3];
+        }
+        out
+    }
+
+    fn rotation(&self) -> [[f64; 3]; 3] {
+        let m = &self.matrix;
+        [[m[0][0], m[0][1], m[0][2]], [m[1][0], m[1][1], m[1][2]], [m[2][0], m[2][1], m[2][2]]]
+    }
+}
+
+impl Filter for Transform {
+    fn filter(&mut self, mut points: Vec<Point>) -> Result<Vec<Point>> {
+        let rotation = self.rotation();
+        for point in &mut points {
+            let xyz = self.apply([point.x, point.y, point.z]);
+            point.x = xyz[0];
+            point.y = xyz[1];
+            point.z = xyz[2];
+            if let Some(ref mut waveform) = point.waveform {
+                waveform.anchor = self.apply(waveform.anchor);
+                let d = waveform.direction;
+                for i in 0..3 {
+                    waveform.direction[i] = rotation[i][0] * d[0] + rotation[i][1] * d[1] +
+                                            rotation[i][2] * d[2];
+                }
+            }
+            if let Some(ref mut partials) = point.partials {
+                partials.rotate(&rotation);
+            }
+        }
+        Ok(points)
+    }
+
+    /// Drops the coordinate reference system, since the points have moved into another frame.
+    fn metadata(&self, mut metadata: Metadata) -> Metadata {
+        metadata.vlrs.retain(|vlr| !vlr.is_crs());
+        metadata
+    }
+}
+
+impl ConfigurableFilter for Transform {
+    type Config = TransformConfig;
+
+    fn open_filter(config: TransformConfig) -> Result<Box<Filter>> {
+        let mut matrices = Vec::new();
+        for matrix in config.matrices.unwrap_or(Vec::new()) {
+            matrices.push(match (matrix.path, matrix.values) {
+                (Some(path), None) => try!(read_matrix(path)),
+                (None, Some(values)) => try!(matrix_from_values(&values)),
+                _ => {
+                    return Err(Error::Configuration("each transform matrix needs either a path \
+                                                     or values"
+                                                        .to_string()))
+                }
+            });
+        }
+        Ok(Box::new(Transform::new(&matrices)))
+    }
+}
+
+/// Reads a matrix from a plain text file of sixteen numbers, row by row.
+///
+/// Any whitespace separates the numbers, so this reads Riegl's exported .dat matrices as well as
+/// one-line matrices.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::filter::transform::read_matrix;
+/// let matrix = read_matrix("data/sop.dat").unwrap();
+/// ```
+pub fn read_matrix<P: AsRef<Path>>(path: P) -> Result<Matrix> {
+    let mut s = String::new();
+    let _ = try!(try!(File::open(path)).read_to_string(&mut s));
+    let mut values = Vec::with_capacity(16);
+    for word in s.split_whitespace() {
+        values.push(try!(word.parse::<f64>()
+                             .map_err(|_| {
+                                 Error::Configuration(format!("invalid matrix value: {}", word))
+                             })));
+    }
+    matrix_from_values(&values)
+}
+
+fn matrix_from_values(values: &[f64]) -> Result<Matrix> {
+    if values.len() != 16 {
+        return Err(Error::Configuration(format!("a matrix needs 16 values, not {}",
+                                                values.len())));
+    }
+    let mut matrix = [[0.0; 4]; 4];
+    for (i, &value) in values.iter().enumerate() {
+        matrix[i / 4][i % 4] = value;
+    }
+    Ok(matrix)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            product[i][j] = (0..4).fold(0.0, |sum, k| sum + a[i][k] * b[k][j]);
+        }
+    }
+    product
+}
+
+/// Decodable configuration object.
+///
+/// `matrices` are applied in order. Each one is either a `path` to a plain text matrix file, or
+/// sixteen row-major `values`.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct TransformConfig {
+    matrices: Option<Vec<MatrixConfig>>,
+}
+
+/// One matrix in a `TransformConfig`.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct MatrixConfig {
+    path: Option<String>,
+    values: Option<Vec<f64>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use filter::{Filter, open_filter};
+    use point::Point;
+    use toml;
+    use super::*;
+
+    #[test]
+    fn sop_then_pop() {
+        let sop = read_matrix("data/sop.dat").unwrap();
+        let pop = [[1.0, 0.0, 0.0, 100.0],
+                   [0.0, 1.0, 0.0, 200.0],
+                   [0.0, 0.0, 1.0, 300.0],
+                   [0.0, 0.0, 0.0, 1.0]];
+        let mut transform = Transform::new(&[sop, pop]);
+        let points = transform.filter(vec![Point { x: 1.0, ..Default::default() }]).unwrap();
+        assert_eq!(110.0, points[0].x);
+        assert_eq!(203.0, points[0].y);
+        assert_eq!(300.0, points[0].z);
+    }
+
+    #[test]
+    fn from_config() {
+        let config: toml::Value = r#"
+        [[matrices]]
+        path = "data/sop.dat"
+        [[matrices]]
+        values = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        "#
+                                      .parse()
+                                      .unwrap();
+        let mut filter = open_filter("transform", Some(config)).unwrap();
+        let points = filter.filter(vec![Point::default()]).unwrap();
+        assert_eq!([10.0, 2.0, 1.0], [points[0].x, points[0].y, points[0].z]);
+        let config: toml::Value = "[[matrices]]\nvalues = [1.0]".parse().unwrap();
+        assert!(open_filter("transform", Some(config)).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Convert between GPS time standards.
+//!
+//! Different formats keep time differently. Las 1.0 through 1.2 files usually hold GPS week
+//! seconds, newer las files hold adjusted standard GPS time (GPS seconds since the GPS epoch, less
+//! one billion), PPS-synchronized rxp files hold seconds since the most recent PPS pulse (usually
+//! week seconds), and everyone else seems to use UTC. A `Converter` moves times between these.
+//!
+//! UTC times are seconds since the Unix epoch. GPS time doesn't have leap seconds and UTC does, so
+//! converting between the two uses the leap second table below, which has to be updated when the
+//! IERS announces a new leap second.
+
+use {Error, Result};
+
+/// The number of seconds in a GPS week.
+pub const SECONDS_PER_WEEK: f64 = 604800.0;
+
+const ADJUSTED_STANDARD_OFFSET: f64 = 1e9;
+const GPS_EPOCH_IN_UNIX_TIME: f64 = 315964800.0;
+
+/// The UTC times, as Unix seconds, when the difference between GPS time and UTC went up by one.
+const LEAP_SECONDS: [f64; 18] = [362793600.0, 394329600.0, 425865600.0, 489024000.0,
+                                 567993600.0, 631152000.0, 662688000.0, 709948800.0,
+                                 741484800.0, 773020800.0, 820454400.0, 867715200.0,
+                                 915148800.0, 1136073600.0, 1230768000.0, 1341100800.0,
+                                 1435708800.0, 1483228800.0];
+
+/// A way of keeping GPS time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Standard {
+    /// Seconds since the start of the GPS week.
+    WeekSeconds,
+    /// Seconds since the GPS epoch, midnight January 6, 1980.
+    Standard,
+    /// Standard GPS time less one billion, as used by las.
+    AdjustedStandard,
+    /// UTC, as seconds since the Unix epoch.
+    Utc,
+}
+
+impl Standard {
+    /// Returns the standard with the given name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::gps_time::Standard;
+    /// assert_eq!(Standard::AdjustedStandard, Standard::from_name("adjusted_standard").unwrap());
+    /// assert!(Standard::from_name("tai").is_err());
+    /// ```
+    pub fn from_name(name: &str) -> Result<Standard> {
+        match name {
+            "week_seconds" | "week" => Ok(Standard::WeekSeconds),
+            "standard" => Ok(Standard::Standard),
+            "adjusted_standard" | "adjusted" => Ok(Standard::AdjustedStandard),
+            "utc" => Ok(Standard::Utc),
+            _ => Err(Error::Configuration(format!("unknown gps time standard: {}", name))),
+        }
+    }
+
+    /// Returns this standard's name.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Standard::WeekSeconds => "week_seconds",
+            Standard::Standard => "standard",
+            Standard::AdjustedStandard => "adjusted_standard",
+            Standard::Utc => "utc",
+        }
+    }
+}
+
+/// Converts times from one standard to another.
+///
+/// Week seconds don't say which week they're in, so converting from week seconds needs a GPS
+/// week. Converting into week seconds uses the week, if given, so times that cross into the next
+/// week keep counting up rather than wrapping back to zero.
+#[derive(Clone, Copy, Debug)]
+pub struct Converter {
+    from: Standard,
+    to: Standard,
+    week: Option<u32>,
+}
+
+impl Converter {
+    /// Creates a new converter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::gps_time::{Converter, Standard};
+    /// let converter = Converter::new(Standard::WeekSeconds, Standard::Utc, Some(1721)).unwrap();
+    /// assert!(Converter::new(Standard::WeekSeconds, Standard::Utc, None).is_err());
+    /// ```
+    pub fn new(from: Standard, to: Standard, week: Option<u32>) -> Result<Converter> {
+        if from == Standard::WeekSeconds && to != Standard::WeekSeconds && week.is_none() {
+            return Err(Error::Configuration("converting from gps week seconds needs a gps week"
+                                                .to_string()));
+        }
+        Ok(Converter {
+            from: from,
+            to: to,
+            week: week,
+        })
+    }
+
+    /// Returns the standard that times are converted to.
+    pub fn to(&self) -> Standard {
+        self.to
+    }
+
+    /// Converts a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::gps_time::{Converter, Standard};
+    /// let converter = Converter::new(Standard::AdjustedStandard, Standard::Standard, None)
+    ///                     .unwrap();
+    /// assert_eq!(1e9 + 1.0, converter.convert(1.0));
+    /// ```
+    pub fn convert(&self, time: f64) -> f64 {
+        if self.from == self.to {
+            return time;
+        }
+        let standard = match self.from {
+            Standard::WeekSeconds => self.week.unwrap() as f64 * SECONDS_PER_WEEK + time,
+            Standard::Standard => time,
+            Standard::AdjustedStandard => time + ADJUSTED_STANDARD_OFFSET,
+            Standard::Utc => time - GPS_EPOCH_IN_UNIX_TIME + leap_seconds(time),
+        };
+        match self.to {
+            Standard::WeekSeconds => {
+                let week = match self.week {
+                    Some(week) => week as f64,
+                    None => (standard / SECONDS_PER_WEEK).floor(),
+                };
+                standard - week * SECONDS_PER_WEEK
+            }
+            Standard::Standard => standard,
+            Standard::AdjustedStandard => standard - ADJUSTED_STANDARD_OFFSET,
+            Standard::Utc => {
+                let utc = standard + GPS_EPOCH_IN_UNIX_TIME;
+                let mut leap_seconds = 0.0;
+                for (n, &leap) in LEAP_SECONDS.iter().enumerate() {
+                    if utc - (n + 1) as f64 >= leap {
+                        leap_seconds = (n + 1) as f64;
+                    }
+                }
+                utc - leap_seconds
+            }
+        }
+    }
+}
+
+/// Returns the number of leap seconds between GPS time and UTC at a UTC time, in Unix seconds.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::gps_time::leap_seconds;
+/// assert_eq!(0.0, leap_seconds(315964800.0));
+/// assert_eq!(18.0, leap_seconds(1500000000.0));
+/// ```
+pub fn leap_seconds(utc: f64) -> f64 {
+    LEAP_SECONDS.iter().filter(|&&leap| utc >= leap).count() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn week_seconds_to_adjusted_standard() {
+        let converter = Converter::new(Standard::WeekSeconds,
+                                       Standard::AdjustedStandard,
+                                       Some(1721))
+                            .unwrap();
+        assert_eq!(1721.0 * 604800.0 + 100.0 - 1e9, converter.convert(100.0));
+        let converter = Converter::new(Standard::AdjustedStandard, Standard::WeekSeconds, None)
+                            .unwrap();
+        assert_eq!(100.0, converter.convert(1721.0 * 604800.0 + 100.0 - 1e9));
+        let converter = Converter::new(Standard::AdjustedStandard,
+                                       Standard::WeekSeconds,
+                                       Some(1720))
+                            .unwrap();
+        assert_eq!(604900.0, converter.convert(1721.0 * 604800.0 + 100.0 - 1e9));
+    }
+
+    #[test]
+    fn utc_round_trip() {
+        // 2017-01-01T00:00:00Z, the most recent leap second.
+        let utc = 1483228800.0;
+        let to_gps = Converter::new(Standard::Utc, Standard::Standard, None).unwrap();
+        let to_utc = Converter::new(Standard::Standard, Standard::Utc, None).unwrap();
+        let gps = to_gps.convert(utc);
+        assert_eq!(utc - 315964800.0 + 18.0, gps);
+        assert_eq!(utc, to_utc.convert(gps));
+        assert_eq!(utc - 1.0, to_utc.convert(to_gps.convert(utc - 1.0)));
+        assert_eq!(2.0, gps - to_gps.convert(utc - 1.0));
+    }
+}
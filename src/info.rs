@@ -0,0 +1,223 @@
+//! Summarize the points in a source.
+//!
+//! This is what backs `pabst info`, but it is just as useful for checking a source from code.
+
+use std::collections::BTreeMap;
+use std::f64;
+use std::fmt;
+
+use rustc_serialize::json::Json;
+
+use Result;
+use point::{DIMENSIONS, Point};
+use source::Source;
+
+/// A summary of all the points in a source.
+#[derive(Clone, Debug, Default)]
+pub struct Info {
+    /// The name of the source's format, if known.
+    pub format: Option<String>,
+    /// The source's guess at how many points it holds.
+    pub source_len: Option<usize>,
+    /// The number of points that were actually read.
+    pub npoints: usize,
+    /// The dimensions that were present on at least one point, keyed by name.
+    ///
+    /// Extra dimensions are included.
+    pub dimensions: BTreeMap<String, DimensionInfo>,
+}
+
+/// A summary of one dimension.
+#[derive(Clone, Copy, Debug)]
+pub struct DimensionInfo {
+    /// The number of points that had this dimension.
+    pub count: usize,
+    /// The minimum value, as an `f64`.
+    pub min: f64,
+    /// The maximum value, as an `f64`.
+    pub max: f64,
+}
+
+impl Default for DimensionInfo {
+    fn default() -> DimensionInfo {
+        DimensionInfo {
+            count: 0,
+            min: f64::MAX,
+            max: f64::MIN,
+        }
+    }
+}
+
+impl DimensionInfo {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        let _ = object.insert("count".to_string(), Json::U64(self.count as u64));
+        let _ = object.insert("min".to_string(), Json::F64(self.min));
+        let _ = object.insert("max".to_string(), Json::F64(self.max));
+        Json::Object(object)
+    }
+}
+
+impl Info {
+    /// Reads every point from a source and summarizes them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::info::Info;
+    /// use pabst::source::open_file_source;
+    /// let mut source = open_file_source("data/1.0_0.las", None).unwrap();
+    /// let info = Info::from_source(&mut source, 1000).unwrap();
+    /// assert_eq!(1, info.npoints);
+    /// ```
+    pub fn from_source<S: Source>(source: &mut S, chunk_size: usize) -> Result<Info> {
+        let mut info = Info { source_len: source.source_len(), ..Default::default() };
+        while let Some(points) = try!(source.source(chunk_size)) {
+            for point in &points {
+                info.add(point);
+            }
+        }
+        Ok(info)
+    }
+
+    /// Adds one point to this summary.
+    pub fn add(&mut self, point: &Point) {
+        self.npoints += 1;
+        for &dimension in DIMENSIONS.iter() {
+            if let Some(value) = point.get(dimension) {
+                self.dimensions
+                    .entry(dimension.name().to_string())
+                    .or_insert_with(DimensionInfo::default)
+                    .add(value.as_f64());
+            }
+        }
+        for (name, value) in &point.extra {
+            self.dimensions
+                .entry(name.clone())
+                .or_insert_with(DimensionInfo::default)
+                .add(value.as_f64());
+        }
+    }
+
+    /// Returns the minimum and maximum x, y, and z, or `None` if there weren't any points.
+    pub fn bounds(&self) -> Option<([f64; 3], [f64; 3])> {
+        match (self.dimensions.get("x"), self.dimensions.get("y"), self.dimensions.get("z")) {
+            (Some(x), Some(y), Some(z)) => Some(([x.min, y.min, z.min], [x.max, y.max, z.max])),
+            _ => None,
+        }
+    }
+
+    /// Returns the minimum and maximum GPS time, or `None` if no points had one.
+    pub fn gps_time(&self) -> Option<(f64, f64)> {
+        self.dimensions.get("gps_time").map(|d| (d.min, d.max))
+    }
+
+    /// Returns this summary as a JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::info::Info;
+    /// let json = Info::default().to_json();
+    /// assert_eq!(0, json.find("npoints").unwrap().as_u64().unwrap());
+    /// ```
+    pub fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        let _ = object.insert("format".to_string(),
+                              self.format.as_ref().map_or(Json::Null, |f| Json::String(f.clone())));
+        let _ = object.insert("source_len".to_string(),
+                              self.source_len.map_or(Json::Null, |n| Json::U64(n as u64)));
+        let _ = object.insert("npoints".to_string(), Json::U64(self.npoints as u64));
+        let _ = object.insert("bounds".to_string(),
+                              self.bounds().map_or(Json::Null, |(min, max)| {
+                                  let mut bounds = BTreeMap::new();
+                                  let _ = bounds.insert("min".to_string(), xyz_to_json(&min));
+                                  let _ = bounds.insert("max".to_string(), xyz_to_json(&max));
+                                  Json::Object(bounds)
+                              }));
+        let _ = object.insert("gps_time".to_string(),
+                              self.gps_time().map_or(Json::Null, |(min, max)| {
+                                  Json::Array(vec![Json::F64(min), Json::F64(max)])
+                              }));
+        let _ = object.insert("dimensions".to_string(),
+                              Json::Object(self.dimensions
+                                               .iter()
+                                               .map(|(k, v)| (k.clone(), v.to_json()))
+                                               .collect()));
+        Json::Object(object)
+    }
+}
+
+fn xyz_to_json(xyz: &[f64; 3]) -> Json {
+    Json::Array(xyz.iter().map(|&n| Json::F64(n)).collect())
+}
+
+fn format_option<T: fmt::Display>(option: Option<T>) -> String {
+    option.map_or("unknown".to_string(), |t| t.to_string())
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "format: {}", format_option(self.format.as_ref())));
+        try!(writeln!(f, "source_len: {}", format_option(self.source_len)));
+        try!(writeln!(f, "npoints: {}", self.npoints));
+        match self.bounds() {
+            Some((min, max)) => {
+                try!(writeln!(f, "bounds:"));
+                try!(writeln!(f, "  min: {} {} {}", min[0], min[1], min[2]));
+                try!(writeln!(f, "  max: {} {} {}", max[0], max[1], max[2]));
+            }
+            None => try!(writeln!(f, "bounds: none")),
+        }
+        match self.gps_time() {
+            Some((min, max)) => try!(writeln!(f, "gps_time: {} to {}", min, max)),
+            None => try!(writeln!(f, "gps_time: none")),
+        }
+        try!(writeln!(f, "dimensions:"));
+        for (name, dimension) in &self.dimensions {
+            try!(writeln!(f,
+                          "  {}: {} of {} points, {} to {}",
+                          name,
+                          dimension.count,
+                          self.npoints,
+                          dimension.min,
+                          dimension.max));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use source::open_file_source;
+    use super::*;
+
+    #[test]
+    fn sdc() {
+        let mut source = open_file_source("data/4-points.sdc", None).unwrap();
+        let info = Info::from_source(&mut source, 3).unwrap();
+        assert_eq!(4, info.npoints);
+        assert!(info.bounds().is_some());
+        assert_eq!(4, info.dimensions["gps_time"].count);
+        assert!(!info.dimensions.contains_key("point_source_id"));
+    }
+
+    #[test]
+    fn json() {
+        let mut source = open_file_source("data/4-points.xyz", None).unwrap();
+        let info = Info::from_source(&mut source, 100).unwrap();
+        let json = info.to_json();
+        assert_eq!(Some(4), json.find("npoints").and_then(|n| n.as_u64()));
+        assert!(json.find("source_len").unwrap().is_null());
+        assert_eq!(Some(4.0),
+                   json.find_path(&["bounds", "max"])
+                       .and_then(|b| b.as_array())
+                       .and_then(|b| b[0].as_f64()));
+    }
+}
@@ -0,0 +1,322 @@
+//! Patch the las header on its way out of the las library.
+//!
+//! Our las library only knows about point formats zero through three and the las 1.2 header, but
+//! we want to write extra bytes and waveform packets. Both of those only change a few header
+//! fields (and make the header longer, for the newer las versions), so we let the las library
+//! write what it knows and fix up the header as it goes by. The library also always stamps the
+//! header with itself and today's date, so we patch in the source file's provenance, too.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use metadata::Metadata;
+
+/// The length of the las 1.2 header, which is what the las library writes.
+const HEADER_LENGTH: u64 = 227;
+
+const FILE_SOURCE_ID_OFFSET: u64 = 4;
+/// The offset of the global encoding, for when it changes after the fact.
+pub const GLOBAL_ENCODING_OFFSET: u64 = 6;
+const GUID_OFFSET: u64 = 8;
+/// The GUID is written as a u32, two u16s, and eight bytes.
+const GUID_FIELDS: [(u64, u64); 4] = [(0, 4), (4, 2), (6, 2), (8, 8)];
+const SYSTEM_IDENTIFIER_OFFSET: u64 = 26;
+const GENERATING_SOFTWARE_OFFSET: u64 = 58;
+const FILE_CREATION_DAY_OF_YEAR_OFFSET: u64 = 90;
+const FILE_CREATION_YEAR_OFFSET: u64 = 92;
+const VERSION_MINOR_OFFSET: u64 = 25;
+const HEADER_SIZE_OFFSET: u64 = 94;
+const OFFSET_TO_POINT_DATA_OFFSET: u64 = 96;
+/// The offset of the number of variable length records, for when they change after the fact.
+pub const NUMBER_OF_VLRS_OFFSET: u64 = 100;
+const POINT_FORMAT_OFFSET: u64 = 104;
+const RECORD_LENGTH_OFFSET: u64 = 105;
+const NUMBER_OF_POINT_RECORDS_OFFSET: u64 = 107;
+const NUMBER_OF_POINTS_BY_RETURN_OFFSET: u64 = 111;
+
+/// The global encoding bit that says waveform packets are in an external .wdp file.
+pub const EXTERNAL_WAVE_PACKETS: u16 = 0x04;
+
+/// The global encoding bit that says the coordinate reference system is well known text.
+pub const WKT: u16 = 0x10;
+
+/// The length of the waveform packet fields in point formats four and five.
+pub const WAVE_PACKET_LENGTH: u16 = 29;
+
+/// A wrapper that patches the header written by the las library.
+///
+/// The las library writes each header field with its own call to `write`, so we don't worry about
+/// fields being split across writes. It also only ever seeks to the start of the file, to rewrite
+/// the header when it closes.
+#[derive(Debug)]
+pub struct HeaderPatch<W: Write + Seek> {
+    extra_bytes: u16,
+    metadata: Metadata,
+    number_of_point_records: u32,
+    number_of_points_by_return: [u32; 5],
+    position: u64,
+    version_minor: Option<u8>,
+    writer: W,
+}
+
+impl<W: Write + Seek> HeaderPatch<W> {
+    /// Wraps a writer, passing the header through untouched.
+    pub fn new(writer: W) -> HeaderPatch<W> {
+        HeaderPatch {
+            extra_bytes: 0,
+            metadata: Metadata::new(),
+            number_of_point_records: 0,
+            number_of_points_by_return: [0; 5],
+            position: 0,
+            version_minor: None,
+            writer: writer,
+        }
+    }
+
+    /// Adds `extra_bytes` to the point data record length.
+    pub fn extra_bytes(mut self, extra_bytes: u16) -> HeaderPatch<W> {
+        self.extra_bytes = extra_bytes;
+        self
+    }
+
+    /// Writes the metadata's provenance into the header: its file source id, GUID, system
+    /// identifier, generating software, and creation date. If the metadata's coordinate reference
+    /// system is well known text, the global encoding says so.
+    pub fn metadata(mut self, metadata: &Metadata) -> HeaderPatch<W> {
+        self.metadata = metadata.clone();
+        self
+    }
+
+    /// Turns point formats one and three into four and five, with external waveform packets.
+    ///
+    /// The version's minor number must be three or four, and the header is lengthened to match.
+    pub fn wave_packets(mut self, version_minor: u8) -> HeaderPatch<W> {
+        self.version_minor = Some(version_minor);
+        self
+    }
+
+    /// Returns the global encoding bits that we add to what the las library writes.
+    pub fn global_encoding(&self) -> u16 {
+        let mut global_encoding = 0;
+        if self.version_minor.is_some() {
+            global_encoding |= EXTERNAL_WAVE_PACKETS;
+        }
+        if self.metadata.vlrs.iter().any(|vlr| vlr.is_wkt()) {
+            global_encoding |= WKT;
+        }
+        global_encoding
+    }
+
+    /// Returns the length of the header that is actually written.
+    pub fn header_size(&self) -> u64 {
+        HEADER_LENGTH + self.inserted()
+    }
+
+    /// Returns the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn inserted(&self) -> u64 {
+        match self.version_minor {
+            Some(3) => 8,
+            Some(4) => 148,
+            _ => 0,
+        }
+    }
+
+    fn record_length(&self) -> u16 {
+        self.extra_bytes +
+        if self.version_minor.is_some() {
+            WAVE_PACKET_LENGTH
+        } else {
+            0
+        }
+    }
+
+    fn patch(&mut self, buf: &mut [u8], start: u64) {
+        let end = start + buf.len() as u64;
+        let field = |offset: u64, len: u64| {
+            if start <= offset && end >= offset + len {
+                Some((offset - start) as usize)
+            } else {
+                None
+            }
+        };
+        if let Some(i) = field(RECORD_LENGTH_OFFSET, 2) {
+            let record_length = LittleEndian::read_u16(&buf[i..i + 2]) + self.record_length();
+            LittleEndian::write_u16(&mut buf[i..i + 2], record_length);
+        }
+        if let Some(i) = field(NUMBER_OF_POINT_RECORDS_OFFSET, 4) {
+            self.number_of_point_records = LittleEndian::read_u32(&buf[i..i + 4]);
+        }
+        for n in 0..5 {
+            if let Some(i) = field(NUMBER_OF_POINTS_BY_RETURN_OFFSET + 4 * n, 4) {
+                let count = LittleEndian::read_u32(&buf[i..i + 4]);
+                self.number_of_points_by_return[n as usize] = count;
+            }
+        }
+        if let (Some(i), Some(id)) = (field(FILE_SOURCE_ID_OFFSET, 2),
+                                      self.metadata.file_source_id) {
+            LittleEndian::write_u16(&mut buf[i..i + 2], id);
+        }
+        if let Some(guid) = self.metadata.guid {
+            for &(offset, len) in &GUID_FIELDS {
+                if let Some(i) = field(GUID_OFFSET + offset, len) {
+                    let (offset, len) = (offset as usize, len as usize);
+                    buf[i..i + len].copy_from_slice(&guid[offset..offset + len]);
+                }
+            }
+        }
+        if let (Some(i), Some(s)) = (field(SYSTEM_IDENTIFIER_OFFSET, 32),
+                                     self.metadata.system_identifier.as_ref()) {
+            write_padded(s, &mut buf[i..i + 32]);
+        }
+        if let (Some(i), Some(s)) = (field(GENERATING_SOFTWARE_OFFSET, 32),
+                                     self.metadata.generating_software.as_ref()) {
+            write_padded(s, &mut buf[i..i + 32]);
+        }
+        if let Some(date) = self.metadata.creation_date {
+            if let Some(i) = field(FILE_CREATION_DAY_OF_YEAR_OFFSET, 2) {
+                LittleEndian::write_u16(&mut buf[i..i + 2], date.day_of_year);
+            }
+            if let Some(i) = field(FILE_CREATION_YEAR_OFFSET, 2) {
+                LittleEndian::write_u16(&mut buf[i..i + 2], date.year);
+            }
+        }
+        if let Some(i) = field(GLOBAL_ENCODING_OFFSET, 2) {
+            let global_encoding = LittleEndian::read_u16(&buf[i..i + 2]) | self.global_encoding();
+            LittleEndian::write_u16(&mut buf[i..i + 2], global_encoding);
+        }
+        let version_minor = match self.version_minor {
+            Some(version_minor) => version_minor,
+            None => return,
+        };
+        if let Some(i) = field(VERSION_MINOR_OFFSET, 1) {
+            buf[i] = version_minor;
+        }
+        if let Some(i) = field(HEADER_SIZE_OFFSET, 2) {
+            let header_size = LittleEndian::read_u16(&buf[i..i + 2]) + self.inserted() as u16;
+            LittleEndian::write_u16(&mut buf[i..i + 2], header_size);
+        }
+        if let Some(i) = field(OFFSET_TO_POINT_DATA_OFFSET, 4) {
+            let offset = LittleEndian::read_u32(&buf[i..i + 4]) + self.inserted() as u32;
+            LittleEndian::write_u32(&mut buf[i..i + 4], offset);
+        }
+        if let Some(i) = field(POINT_FORMAT_OFFSET, 1) {
+            buf[i] += 3;
+        }
+    }
+
+    /// The header fields that come after the las 1.2 header.
+    ///
+    /// We write our waveform packets to an external file, and we don't write any extended
+    /// variable length records, so the only interesting values are the 64-bit point counts.
+    fn header_extension(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.inserted() as usize];
+        if bytes.len() > 8 {
+            LittleEndian::write_u64(&mut bytes[20..28], self.number_of_point_records as u64);
+            for (n, &count) in self.number_of_points_by_return.iter().enumerate() {
+                LittleEndian::write_u64(&mut bytes[28 + 8 * n..36 + 8 * n], count as u64);
+            }
+        }
+        bytes
+    }
+}
+
+/// Reads a fixed-length, null-padded las string.
+pub fn read_padded(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).trim_right().to_string()
+}
+
+/// Writes a string into a fixed-length, null-padded las field, cutting it off if it's too long.
+pub fn write_padded(s: &str, bytes: &mut [u8]) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = s.as_bytes().get(i).map_or(0, |&b| b);
+    }
+}
+
+impl<W: Write + Seek> Write for HeaderPatch<W> {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        let start = self.position;
+        let end = start + buf.len() as u64;
+        if start < HEADER_LENGTH {
+            let mut patched = buf.to_vec();
+            self.patch(&mut patched, start);
+            try!(self.writer.write_all(&patched));
+            if end == HEADER_LENGTH {
+                let extension = self.header_extension();
+                try!(self.writer.write_all(&extension));
+            }
+        } else {
+            try!(self.writer.write_all(buf));
+        }
+        self.position = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Seek for HeaderPatch<W> {
+    fn seek(&mut self, pos: SeekFrom) -> ::std::io::Result<u64> {
+        let inserted = self.inserted();
+        self.position = match pos {
+            SeekFrom::Start(n) if n >= HEADER_LENGTH => {
+                try!(self.writer.seek(SeekFrom::Start(n + inserted))) - inserted
+            }
+            pos => {
+                let n = try!(self.writer.seek(pos));
+                if n >= HEADER_LENGTH + inserted {
+                    n - inserted
+                } else {
+                    n
+                }
+            }
+        };
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LittleEndian};
+    use las;
+
+    use super::*;
+
+    fn write(patch: HeaderPatch<Cursor<Vec<u8>>>) -> Vec<u8> {
+        let mut writer = las::Writer::new(patch)
+                             .point_format(las::PointFormat::from_u8(1).unwrap())
+                             .open()
+                             .unwrap();
+        writer.write_point(&las::Point { gps_time: Some(1.0), ..las::Point::new() }).unwrap();
+        writer.close().unwrap().into_inner().into_inner().into_inner()
+    }
+
+    #[test]
+    fn untouched() {
+        let bytes = write(HeaderPatch::new(Cursor::new(Vec::new())));
+        assert_eq!(227 + 28, bytes.len());
+        assert_eq!(28, LittleEndian::read_u16(&bytes[105..107]));
+    }
+
+    #[test]
+    fn wave_packets_1_4() {
+        let patch = HeaderPatch::new(Cursor::new(Vec::new())).extra_bytes(8).wave_packets(4);
+        let bytes = write(patch);
+        assert_eq!(375 + 28, bytes.len());
+        assert_eq!(4, bytes[25]);
+        assert_eq!(375, LittleEndian::read_u16(&bytes[94..96]));
+        assert_eq!(375, LittleEndian::read_u32(&bytes[96..100]));
+        assert_eq!(4, bytes[104]);
+        assert_eq!(28 + 29 + 8, LittleEndian::read_u16(&bytes[105..107]));
+        assert_eq!(1, LittleEndian::read_u64(&bytes[247..255]));
+    }
+}
@@ -0,0 +1,361 @@
+//! Laszip compression and decompression, presented as plain las streams.
+//!
+//! The las library only knows about uncompressed las files. Rather than teaching it about laszip,
+//! we wrap compressed files in `Read + Seek` and `Write + Seek` adapters that look like plain las
+//! files from the outside. The header and VLRs are kept in memory, the laszip VLR is hidden from
+//! (or added behind the back of) the las library, and point records are run through the laz
+//! codec one at a time.
+
+use std::cmp;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+use laz;
+
+use {Error, Result};
+
+const OFFSET_TO_POINT_DATA: usize = 96;
+const NUMBER_OF_VLRS: usize = 100;
+const POINT_DATA_FORMAT: usize = 104;
+const POINT_DATA_RECORD_LENGTH: usize = 105;
+const HEADER_SIZE: usize = 94;
+const VLR_HEADER_LENGTH: usize = 54;
+const LASZIP_USER_ID: &'static [u8] = b"laszip encoded";
+const LASZIP_RECORD_ID: u16 = 22204;
+const COMPRESSED_BIT: u8 = 0b10000000;
+const COMPRESSED_BITS: u8 = 0b11000000;
+
+fn laz_error(err: laz::LasZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+fn not_compressed() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, "file is not laszip-compressed"))
+}
+
+fn invalid_header(what: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, format!("invalid las header: {}", what)))
+}
+
+/// Reads a laszip-compressed file as if it were an uncompressed las file.
+pub struct Decompressor<R: Read + Seek + Send + 'static> {
+    decompressor: laz::LasZipDecompressor<'static, R>,
+    head: Vec<u8>,
+    next_index: u64,
+    position: u64,
+    record: Vec<u8>,
+    record_index: Option<u64>,
+}
+
+impl<R: Read + Seek + Send + 'static> Decompressor<R> {
+    /// Reads the header and VLRs of a laszip file and readies the decompressor.
+    pub fn new(mut reader: R) -> Result<Decompressor<R>> {
+        let mut head = vec![0; HEADER_SIZE + 2];
+        try!(reader.read_exact(&mut head));
+        let header_size = LittleEndian::read_u16(&head[HEADER_SIZE..]) as usize;
+        if header_size < POINT_DATA_RECORD_LENGTH + 2 {
+            return Err(invalid_header(&format!("header size {} is too small", header_size)));
+        }
+        head.resize(header_size, 0);
+        try!(reader.read_exact(&mut head[HEADER_SIZE + 2..]));
+        let offset_to_point_data = LittleEndian::read_u32(&head[OFFSET_TO_POINT_DATA..]) as usize;
+        let number_of_vlrs = LittleEndian::read_u32(&head[NUMBER_OF_VLRS..]);
+        if head[POINT_DATA_FORMAT] & COMPRESSED_BITS == 0 {
+            return Err(not_compressed());
+        }
+        if offset_to_point_data < header_size {
+            return Err(invalid_header(&format!("offset to point data {} is inside the header",
+                                               offset_to_point_data)));
+        }
+        let mut rest = vec![0; offset_to_point_data - header_size];
+        try!(reader.read_exact(&mut rest));
+
+        let mut vlr = None;
+        let mut start = 0;
+        for i in 0..number_of_vlrs {
+            if start + VLR_HEADER_LENGTH > rest.len() {
+                return Err(Error::InvalidVlr(format!("vlr {} starts after the point data", i)));
+            }
+            let length = LittleEndian::read_u16(&rest[start + 20..]) as usize;
+            let end = start + VLR_HEADER_LENGTH + length;
+            if end > rest.len() {
+                return Err(Error::InvalidVlr(format!("vlr {} runs into the point data", i)));
+            }
+            if rest[start + 2..start + 18].starts_with(LASZIP_USER_ID) &&
+               LittleEndian::read_u16(&rest[start + 18..]) == LASZIP_RECORD_ID {
+                vlr = Some(try!(laz::LazVlr::from_buffer(&rest[start + VLR_HEADER_LENGTH..end])
+                                    .map_err(Error::Laz)));
+                let _ = rest.drain(start..end);
+                head[POINT_DATA_FORMAT] &= !COMPRESSED_BITS;
+                LittleEndian::write_u32(&mut head[NUMBER_OF_VLRS..], number_of_vlrs - 1);
+                LittleEndian::write_u32(&mut head[OFFSET_TO_POINT_DATA..],
+                                        (offset_to_point_data - (end - start)) as u32);
+                break;
+            }
+            start = end;
+        }
+        let vlr = try!(vlr.ok_or_else(not_compressed));
+        head.extend_from_slice(&rest);
+        let record_length = LittleEndian::read_u16(&head[POINT_DATA_RECORD_LENGTH..]) as usize;
+        Ok(Decompressor {
+            decompressor: try!(laz::LasZipDecompressor::new(reader, vlr).map_err(Error::Laz)),
+            head: head,
+            next_index: 0,
+            position: 0,
+            record: vec![0; record_length],
+            record_index: None,
+        })
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let head_length = self.head.len() as u64;
+        if self.position < head_length {
+            let start = self.position as usize;
+            let n = cmp::min(buf.len(), self.head.len() - start);
+            buf[..n].copy_from_slice(&self.head[start..start + n]);
+            self.position += n as u64;
+            return Ok(n);
+        }
+        let record_length = self.record.len() as u64;
+        let index = (self.position - head_length) / record_length;
+        let offset = ((self.position - head_length) % record_length) as usize;
+        if self.record_index != Some(index) {
+            if index != self.next_index {
+                try!(self.decompressor.seek(index).map_err(laz_error));
+            }
+            try!(self.decompressor.decompress_one(&mut self.record));
+            self.record_index = Some(index);
+            self.next_index = index + 1;
+        }
+        let n = cmp::min(buf.len(), self.record.len() - offset);
+        buf[..n].copy_from_slice(&self.record[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Seek for Decompressor<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Start(n) => self.position = n,
+            SeekFrom::Current(n) => self.position = (self.position as i64 + n) as u64,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "cannot seek from the end of a laszip stream"))
+            }
+        }
+        Ok(self.position)
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> fmt::Debug for Decompressor<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decompressor {{ position: {} }}", self.position)
+    }
+}
+
+/// Writes a laszip-compressed file while looking like an uncompressed las file.
+///
+/// The las library writes the header and VLRs, which we keep in memory, and then point records,
+/// which we compress as they come in. Once the las library is done (including rewriting the
+/// header), call `finish` to flush the compressor and write the real header and VLRs.
+pub struct Compressor<W: Write + Seek + Send + 'static> {
+    compressor: Option<laz::LasZipCompressor<'static, W>>,
+    head: Vec<u8>,
+    position: u64,
+    record: Vec<u8>,
+    record_length: usize,
+    vlr: Option<Vec<u8>>,
+    writer: Option<W>,
+}
+
+impl<W: Write + Seek + Send + 'static> Compressor<W> {
+    /// Creates a new compressor that will write to the given `Write`.
+    pub fn new(writer: W) -> Compressor<W> {
+        Compressor {
+            compressor: None,
+            head: Vec::new(),
+            position: 0,
+            record: Vec::new(),
+            record_length: 0,
+            vlr: None,
+            writer: Some(writer),
+        }
+    }
+
+    fn offset_to_point_data(&self) -> Option<u64> {
+        if self.head.len() >= OFFSET_TO_POINT_DATA + 4 {
+            Some(LittleEndian::read_u32(&self.head[OFFSET_TO_POINT_DATA..]) as u64)
+        } else {
+            None
+        }
+    }
+
+    fn start_compressing(&mut self) -> io::Result<()> {
+        let point_format = self.head[POINT_DATA_FORMAT];
+        let record_length = LittleEndian::read_u16(&self.head[POINT_DATA_RECORD_LENGTH..]);
+        let items = try!(laz::LazItemRecordBuilder::default_for_point_format_id(point_format, 0)
+                             .map_err(laz_error));
+        let extra_bytes = record_length - items.iter().fold(0, |a, i| a + i.size());
+        let items = try!(laz::LazItemRecordBuilder::default_for_point_format_id(point_format,
+                                                                                extra_bytes)
+                             .map_err(laz_error));
+        let vlr = laz::LazVlr::from_laz_items(items);
+        let mut record = Vec::new();
+        try!(vlr.write_to(&mut record));
+        let offset = self.head.len() + VLR_HEADER_LENGTH + record.len();
+        let mut writer = self.writer.take().expect("compressor already started");
+        let _ = try!(writer.seek(SeekFrom::Start(offset as u64)));
+        self.compressor = Some(try!(laz::LasZipCompressor::new(writer, vlr).map_err(laz_error)));
+        self.record = Vec::with_capacity(record_length as usize);
+        self.record_length = record_length as usize;
+        self.vlr = Some(record);
+        Ok(())
+    }
+
+    /// Finishes compression and writes the real header and VLRs.
+    pub fn finish(mut self) -> Result<()> {
+        if self.compressor.is_none() {
+            try!(self.start_compressing());
+        }
+        if !self.record.is_empty() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidData,
+                                                "incomplete point record at end of laz stream")));
+        }
+        let mut compressor = self.compressor.take().unwrap();
+        try!(compressor.done());
+        let mut writer = compressor.into_inner();
+
+        let vlr = self.vlr.take().unwrap();
+        let offset_to_point_data = LittleEndian::read_u32(&self.head[OFFSET_TO_POINT_DATA..]);
+        let number_of_vlrs = LittleEndian::read_u32(&self.head[NUMBER_OF_VLRS..]);
+        LittleEndian::write_u32(&mut self.head[OFFSET_TO_POINT_DATA..],
+                                offset_to_point_data + (VLR_HEADER_LENGTH + vlr.len()) as u32);
+        LittleEndian::write_u32(&mut self.head[NUMBER_OF_VLRS..], number_of_vlrs + 1);
+        self.head[POINT_DATA_FORMAT] |= COMPRESSED_BIT;
+
+        let _ = try!(writer.seek(SeekFrom::Start(0)));
+        try!(writer.write_all(&self.head));
+        let mut vlr_header = [0; VLR_HEADER_LENGTH];
+        vlr_header[2..2 + LASZIP_USER_ID.len()].copy_from_slice(LASZIP_USER_ID);
+        LittleEndian::write_u16(&mut vlr_header[18..], LASZIP_RECORD_ID);
+        LittleEndian::write_u16(&mut vlr_header[20..], vlr.len() as u16);
+        let description = b"pabst laszip";
+        vlr_header[22..22 + description.len()].copy_from_slice(description);
+        try!(writer.write_all(&vlr_header));
+        try!(writer.write_all(&vlr));
+        try!(writer.flush());
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek + Send + 'static> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.offset_to_point_data() {
+            Some(offset) if self.position >= offset => {
+                if self.compressor.is_none() {
+                    try!(self.start_compressing());
+                }
+                let n = cmp::min(buf.len(), self.record_length - self.record.len());
+                self.record.extend_from_slice(&buf[..n]);
+                if self.record.len() == self.record_length {
+                    try!(self.compressor.as_mut().unwrap().compress_one(&self.record));
+                    self.record.clear();
+                }
+                self.position += n as u64;
+                Ok(n)
+            }
+            offset => {
+                let start = self.position as usize;
+                let n = match offset {
+                    Some(offset) => cmp::min(buf.len(), offset as usize - start),
+                    None => buf.len(),
+                };
+                if self.head.len() < start + n {
+                    self.head.resize(start + n, 0);
+                }
+                self.head[start..start + n].copy_from_slice(&buf[..n]);
+                self.position += n as u64;
+                Ok(n)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek + Send + 'static> Seek for Compressor<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(n) => n,
+            SeekFrom::Current(n) => (self.position as i64 + n) as u64,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "cannot seek from the end of a laszip stream"))
+            }
+        };
+        if self.compressor.is_some() && position > self.head.len() as u64 &&
+           position != self.position {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "laszip point records must be written sequentially"));
+        }
+        self.position = position;
+        Ok(self.position)
+    }
+}
+
+impl<W: Write + Seek + Send + 'static> fmt::Debug for Compressor<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Compressor {{ position: {} }}", self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use Error;
+    use super::*;
+
+    fn head() -> Vec<u8> {
+        let mut head = vec![0; 227];
+        LittleEndian::write_u16(&mut head[HEADER_SIZE..], 227);
+        LittleEndian::write_u32(&mut head[OFFSET_TO_POINT_DATA..], 227 + 54);
+        LittleEndian::write_u32(&mut head[NUMBER_OF_VLRS..], 1);
+        head[POINT_DATA_FORMAT] = COMPRESSED_BIT | 1;
+        head
+    }
+
+    #[test]
+    fn corrupt_header() {
+        let mut bytes = head();
+        LittleEndian::write_u16(&mut bytes[HEADER_SIZE..], 50);
+        assert!(Decompressor::new(Cursor::new(bytes)).is_err());
+
+        let mut bytes = head();
+        LittleEndian::write_u32(&mut bytes[OFFSET_TO_POINT_DATA..], 100);
+        assert!(Decompressor::new(Cursor::new(bytes)).is_err());
+
+        let mut bytes = head();
+        bytes.extend_from_slice(&[0; 54]);
+        LittleEndian::write_u16(&mut bytes[227 + 20..], 1000);
+        match Decompressor::new(Cursor::new(bytes.clone())) {
+            Err(Error::InvalidVlr(_)) => {}
+            r => panic!("unexpected result: {:?}", r.err()),
+        }
+
+        LittleEndian::write_u16(&mut bytes[227 + 20..], 0);
+        LittleEndian::write_u32(&mut bytes[NUMBER_OF_VLRS..], 2);
+        match Decompressor::new(Cursor::new(bytes)) {
+            Err(Error::InvalidVlr(_)) => {}
+            r => panic!("unexpected result: {:?}", r.err()),
+        }
+    }
+}
@@ -7,19 +7,52 @@
 
 #![deny(fat_ptr_transmutes, missing_copy_implementations, missing_debug_implementations, missing_docs, trivial_casts, trivial_numeric_casts, unsafe_code, unused_extern_crates, unused_import_braces, unused_qualifications, unused_results, variant_size_differences)]
 
+extern crate byteorder;
 extern crate las;
+#[cfg(feature = "laz")]
+extern crate laz;
+#[cfg(feature = "sdf-source")]
+extern crate peakbag;
 #[cfg(feature = "rxp-source")]
 extern crate rivlib;
 extern crate sdc;
+#[cfg(feature = "sdf-source")]
+extern crate sdf;
 extern crate rustc_serialize;
 extern crate toml;
 
+/// Decodes a configuration object for a source, sink, or filter type, or uses its default if
+/// there's no configuration.
+macro_rules! decode_or_default {
+    ($trait_:path, $klass:path, $decoder:expr) => {{
+        match $decoder {
+            Some(ref mut decoder) => try!(<$klass as $trait_>::Config::decode(decoder)),
+            None => Default::default()
+        }
+    }}
+}
+
 pub mod error;
+mod extra_bytes;
+pub mod filter;
+pub mod gps_time;
+pub mod info;
+mod las_header;
+#[cfg(feature = "laz")]
+mod laszip;
+pub mod metadata;
+pub mod pipeline;
 pub mod point;
+mod ply;
+pub mod schema;
 pub mod source;
 pub mod sink;
+pub mod trajectory;
+mod wave_packet;
+pub mod warning;
 
 pub use error::Error;
+pub use filter::Filter;
 pub use point::Point;
 pub use source::{open_file_source, FileSource, Source};
 pub use sink::{open_file_sink, FileSink, Sink};
@@ -28,3 +61,28 @@ use std::result;
 
 /// Our custom result type.
 pub type Result<T> = result::Result<T, Error>;
+
+/// Takes the `driver` key out of a source or sink configuration.
+///
+/// The configuration is dropped entirely if the driver was its only key, so the source or sink
+/// gets its default configuration rather than an empty one.
+fn remove_driver(config: Option<toml::Value>) -> Result<(Option<String>, Option<toml::Value>)> {
+    match config {
+        Some(toml::Value::Table(mut table)) => {
+            let driver = match table.remove("driver") {
+                Some(toml::Value::String(driver)) => Some(driver),
+                Some(_) => {
+                    return Err(Error::Configuration("driver must be a string".to_string()))
+                }
+                None => None,
+            };
+            let config = if table.is_empty() {
+                None
+            } else {
+                Some(toml::Value::Table(table))
+            };
+            Ok((driver, config))
+        }
+        config => Ok((None, config)),
+    }
+}
@@ -1,22 +1,30 @@
 //! Simple executable.
 
 extern crate docopt;
+extern crate las;
 extern crate rustc_serialize;
 extern crate pabst;
+extern crate sdc;
 extern crate toml;
 
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Write};
 use std::process::exit;
 
 use docopt::Docopt;
-use pabst::{open_file_source, open_file_sink};
+use pabst::{Error, open_file_source};
+use pabst::info::Info;
+use pabst::pipeline::{Pipeline, Report};
+use pabst::source::{file_format, open_file_source_as};
 
 const USAGE: &'static str = "
 Use pabst on point cloud data.
 
 Usage:
-    pabst convert <infile> <outfile> [--config=<config-file>]
+    pabst convert <infile> <outfile> [--config=<config-file>] [--reader=<driver>] [--writer=<driver>]
+    pabst info <infile> [--json] [--config=<config-file>] [--reader=<driver>]
+    pabst pipeline <pipeline-file>
     pabst --version
     pabst (-h | --help)
 
@@ -24,79 +32,200 @@ Options:
     -h --help                   Print this message.
     --version                   Print the version.
     --config=<config-file>      TOML configuration file.
+    --json                      Print info as JSON.
+    --reader=<driver>           Read the input with this driver, e.g. las, rather than guessing.
+    --writer=<driver>           Write the output with this driver, rather than guessing.
+
+Exit status:
+    0   Success.
+    2   Bad configuration.
+    3   Input or output error.
+    4   Bad or unsupported file format.
+    5   A point is missing a dimension that the output needs.
 ";
 
+const EXIT_CONFIGURATION: i32 = 2;
+const EXIT_IO: i32 = 3;
+const EXIT_FORMAT: i32 = 4;
+const EXIT_MISSING_DIMENSION: i32 = 5;
+
 #[derive(Debug, RustcDecodable)]
 struct Args {
     cmd_convert: bool,
+    cmd_info: bool,
+    cmd_pipeline: bool,
     arg_infile: String,
     arg_outfile: String,
+    arg_pipeline_file: String,
     flag_config: Option<String>,
+    flag_json: bool,
+    flag_reader: Option<String>,
+    flag_writer: Option<String>,
 }
 
 const DEFAULT_CHUNK_SIZE: usize = 10000;
 
+/// An error, and what we were doing when it happened.
+#[derive(Debug)]
+struct Failure {
+    doing: String,
+    error: Error,
+}
+
+impl Failure {
+    fn new<S: Into<String>>(doing: S, error: Error) -> Failure {
+        Failure {
+            doing: doing.into(),
+            error: error,
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match *self.error.root() {
+            Error::Configuration(_) |
+            Error::Decode(_) |
+            Error::UnknownDimension(_) => EXIT_CONFIGURATION,
+            Error::Io(_) |
+            Error::Las(las::Error::Io(_)) |
+            Error::Sdc(sdc::Error::Io(_)) => EXIT_IO,
+            Error::MissingDimension(_) => EXIT_MISSING_DIMENSION,
+            _ => EXIT_FORMAT,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.doing, self.error)
+    }
+}
+
 fn main() {
     let args: Args = Docopt::new(USAGE)
                          .and_then(|d| {
                              d.version(Some(env!("CARGO_PKG_VERSION").to_string())).decode()
                          })
                          .unwrap_or_else(|e| e.exit());
+    if let Err(failure) = run(args) {
+        let _ = writeln!(io::stderr(), "ERROR: {}", failure);
+        exit(failure.exit_code());
+    }
+}
 
+fn run(args: Args) -> Result<(), Failure> {
     if args.cmd_convert {
         let mut source_config = None;
         let mut sink_config = None;
-        let mut limit = None;
-        let mut chunk_size = DEFAULT_CHUNK_SIZE;
+        let mut pipeline = Pipeline::new();
 
         if let Some(config_file) = args.flag_config {
-            let mut file = File::open(config_file).unwrap_or_else(|e| {
-                println!("ERROR: unable to open configuration file: {}", e);
-                exit(1);
-            });
-            let ref mut config = String::new();
-            file.read_to_string(config).unwrap_or_else(|e| {
-                println!("ERROR: could not read file into string: {}", e);
-                exit(1);
-            });
-            let mut parser = toml::Parser::new(config);
-            if let Some(mut table) = parser.parse() {
-                if let Some(v) = table.get("limit") {
-                    limit = v.as_integer();
-                    println!("Limit: {}", limit.unwrap());
+            let mut table = try!(read_config(&config_file));
+            source_config = table.remove("source");
+            sink_config = table.remove("sink");
+            let mut pipeline_table = toml::Table::new();
+            for key in &["chunk_size", "limit", "stages", "on_error", "rejected", "metadata"] {
+                if let Some(value) = table.remove(*key) {
+                    let _ = pipeline_table.insert(key.to_string(), value);
                 }
-                if let Some(n) = table.get("chunk_size") {
-                    if let Some(n) = n.as_integer() {
-                        chunk_size = n as usize;
-                    }
-                }
-                source_config = table.remove("source");
-                sink_config = table.remove("sink");
-            } else {
-                println!("ERROR: unable to parse TOML configuration file: {:?}", parser.errors);
-                exit(1);
             }
+            pipeline = try!(Pipeline::from_table(pipeline_table).map_err(|e| {
+                Failure::new(format!("invalid pipeline settings in {}", config_file), e)
+            }));
         }
 
-        let mut source = open_file_source(args.arg_infile, source_config).unwrap();
-        let mut sink = open_file_sink(args.arg_outfile, sink_config).unwrap();
-        let mut nread = 0;
-        'outer: loop {
-            let points = source.source(chunk_size).unwrap();
-            if let Some(points) = points {
-                for ref point in points {
-                    sink.sink(point).unwrap();
-                    nread += 1;
-                    if let Some(limit) = limit {
-                        if nread >= limit {
-                            break 'outer;
-                        }
-                    }
-                }
-            } else {
-                break;
+        let doing = format!("unable to convert {} to {}", args.arg_infile, args.arg_outfile);
+        pipeline = match args.flag_reader {
+            Some(driver) => pipeline.input_as(args.arg_infile, driver, source_config),
+            None => pipeline.input(args.arg_infile, source_config),
+        };
+        pipeline = match args.flag_writer {
+            Some(driver) => pipeline.output_as(args.arg_outfile, driver, sink_config),
+            None => pipeline.output(args.arg_outfile, sink_config),
+        };
+        let report = try!(pipeline.execute().map_err(|e| Failure::new(doing, e)));
+        print_report(&report);
+    } else if args.cmd_info {
+        let mut source_config = None;
+        if let Some(config_file) = args.flag_config {
+            source_config = try!(read_config(&config_file)).remove("source");
+        }
+
+        let infile = args.arg_infile;
+        let fail = |e| Failure::new(format!("unable to read {}", infile), e);
+        let (mut source, format) = match args.flag_reader {
+            Some(driver) => {
+                (try!(open_file_source_as(&infile, &driver, source_config).map_err(&fail)),
+                 driver)
+            }
+            None => {
+                (try!(open_file_source(&infile, source_config).map_err(&fail)),
+                 try!(file_format(&infile).map_err(&fail)).to_string())
             }
+        };
+        let mut info = try!(Info::from_source(&mut source, DEFAULT_CHUNK_SIZE).map_err(&fail));
+        info.format = Some(format);
+        if args.flag_json {
+            println!("{}", info.to_json().pretty());
+        } else {
+            print!("{}", info);
+        }
+    } else if args.cmd_pipeline {
+        let path = args.arg_pipeline_file;
+        let fail = |e| Failure::new(format!("unable to run pipeline {}", path), e);
+        let report = try!(Pipeline::from_path(&path).and_then(|p| p.execute()).map_err(&fail));
+        println!("Wrote {} points", report.npoints);
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &Report) {
+    let mut stderr = io::stderr();
+    for (path, dropped) in &report.dropped {
+        let names = dropped.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let _ = writeln!(stderr, "WARNING: {}: not writing {}", path, names.join(", "));
+    }
+    for (path, warnings) in &report.warnings {
+        for (what, warning) in warnings.iter() {
+            let _ = match warning.max_error {
+                Some(max_error) => {
+                    writeln!(stderr,
+                             "WARNING: {}: {} ({} points, max error {})",
+                             path,
+                             what,
+                             warning.count,
+                             max_error)
+                }
+                None => writeln!(stderr, "WARNING: {}: {} ({} points)", path, what, warning.count),
+            };
+        }
+    }
+    if !report.skipped.is_empty() {
+        let _ = writeln!(stderr, "Skipped {} points:", report.nskipped());
+        for (reason, n) in &report.skipped {
+            let _ = writeln!(stderr, "    {}: {}", reason, n);
+        }
+    }
+}
+
+fn read_config(path: &str) -> Result<toml::Table, Failure> {
+    let fail = |e| Failure::new(format!("unable to read configuration file {}", path), e);
+    let mut config = String::new();
+    let _ = try!(File::open(path)
+                     .and_then(|mut file| file.read_to_string(&mut config))
+                     .map_err(|e| fail(Error::from(e))));
+    let mut parser = toml::Parser::new(&config);
+    match parser.parse() {
+        Some(table) => Ok(table),
+        None => {
+            let errors = parser.errors
+                               .iter()
+                               .map(|e| {
+                                   let (line, col) = parser.to_linecol(e.lo);
+                                   format!("{}:{}: {}", line + 1, col + 1, e.desc)
+                               })
+                               .collect::<Vec<_>>();
+            Err(fail(Error::Configuration(errors.join(", "))))
         }
-        sink.close_sink().unwrap();
     }
 }
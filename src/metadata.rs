@@ -0,0 +1,164 @@
+//! File-level information that isn't about any one point.
+//!
+//! Sources describe where their points came from with `Metadata`: the system and software that
+//! made the file, when it was made, how it keeps GPS time, and its variable length records, which
+//! is where las keeps the coordinate reference system. Sinks that can hold some of this write it
+//! out again, so converting a file doesn't lose its provenance or its CRS.
+
+use std::collections::BTreeMap;
+
+use las;
+
+use {Error, Result};
+use gps_time::Standard;
+use las_header::{read_padded, write_padded};
+
+/// The las VLR user id for coordinate reference system records.
+pub const PROJECTION_USER_ID: &'static str = "LASF_Projection";
+
+/// The record id of a coordinate reference system written as OGC well known text.
+pub const WKT_RECORD_ID: u16 = 2112;
+
+/// The las VLR user id for records defined by the las specification itself.
+pub const SPEC_USER_ID: &'static str = "LASF_Spec";
+
+/// Information about a file of points.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// The hardware system, or the process, that made the points.
+    pub system_identifier: Option<String>,
+    /// The software that wrote the file.
+    pub generating_software: Option<String>,
+    /// The day the file was made.
+    pub creation_date: Option<CreationDate>,
+    /// The flight line, or other source, that the file came from.
+    pub file_source_id: Option<u16>,
+    /// The project id.
+    pub guid: Option<[u8; 16]>,
+    /// How the points' GPS times are kept.
+    pub gps_time: Option<Standard>,
+    /// Variable length records, including any coordinate reference system.
+    pub vlrs: Vec<Vlr>,
+    /// Anything else a source wants to say about itself, e.g. a format version, by name.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Creates new, empty metadata.
+    pub fn new() -> Metadata {
+        Default::default()
+    }
+
+    /// Returns the variable length records that hold the coordinate reference system.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::metadata::{Metadata, Vlr};
+    /// let mut metadata = Metadata::new();
+    /// metadata.vlrs.push(Vlr::new("LASF_Projection", 34735, Vec::new()));
+    /// metadata.vlrs.push(Vlr::new("LASF_Spec", 4, Vec::new()));
+    /// assert_eq!(1, metadata.crs().len());
+    /// ```
+    pub fn crs(&self) -> Vec<&Vlr> {
+        self.vlrs.iter().filter(|vlr| vlr.is_crs()).collect()
+    }
+}
+
+/// The day a file was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CreationDate {
+    /// The year, e.g. 2016.
+    pub year: u16,
+    /// The day of the year, where January first is day one.
+    pub day_of_year: u16,
+}
+
+/// A variable length record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vlr {
+    /// Who defined the record, e.g. "LASF_Projection".
+    pub user_id: String,
+    /// What kind of record it is, as defined by the user id.
+    pub record_id: u16,
+    /// A description of the record.
+    pub description: String,
+    /// The record itself.
+    pub data: Vec<u8>,
+}
+
+impl Vlr {
+    /// Creates a new variable length record without a description.
+    pub fn new<S: Into<String>>(user_id: S, record_id: u16, data: Vec<u8>) -> Vlr {
+        Vlr {
+            user_id: user_id.into(),
+            record_id: record_id,
+            description: String::new(),
+            data: data,
+        }
+    }
+
+    /// Returns true if this record is part of a coordinate reference system, e.g. GeoKeys or WKT.
+    pub fn is_crs(&self) -> bool {
+        self.user_id == PROJECTION_USER_ID
+    }
+
+    /// Returns true if this record is a coordinate reference system in well known text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::metadata::Vlr;
+    /// assert!(Vlr::new("LASF_Projection", 2112, Vec::new()).is_wkt());
+    /// assert!(!Vlr::new("LASF_Projection", 34735, Vec::new()).is_wkt());
+    /// ```
+    pub fn is_wkt(&self) -> bool {
+        self.is_crs() && self.record_id == WKT_RECORD_ID
+    }
+
+    /// Converts this record to a las record.
+    ///
+    /// Returns an error if the user id or the data are too long for las. Descriptions are cut
+    /// short if they have to be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::metadata::Vlr;
+    /// let vlr = Vlr::new("LASF_Projection", 2112, b"GEOGCS[]".to_vec());
+    /// assert_eq!(8, vlr.to_las().unwrap().record_length_after_header);
+    /// assert!(Vlr::new("LASF_Projection", 2112, vec![0; 65536]).to_las().is_err());
+    /// ```
+    pub fn to_las(&self) -> Result<las::Vlr> {
+        if self.user_id.len() > 16 {
+            return Err(Error::InvalidVlr(format!("user id is longer than 16 bytes: {}",
+                                                 self.user_id)));
+        }
+        if self.data.len() > u16::max_value() as usize {
+            return Err(Error::InvalidVlr(format!("{} record {} is {} bytes, and las records \
+                                                  can only be {}",
+                                                 self.user_id,
+                                                 self.record_id,
+                                                 self.data.len(),
+                                                 u16::max_value())));
+        }
+        let mut vlr = las::Vlr::new();
+        write_padded(&self.user_id, &mut vlr.user_id);
+        vlr.record_id = self.record_id;
+        vlr.record_length_after_header = self.data.len() as u16;
+        write_padded(&self.description, &mut vlr.description);
+        vlr.record = self.data.clone();
+        Ok(vlr)
+    }
+}
+
+impl<'a> From<&'a las::Vlr> for Vlr {
+    fn from(vlr: &'a las::Vlr) -> Vlr {
+        Vlr {
+            user_id: read_padded(&vlr.user_id),
+            record_id: vlr.record_id,
+            description: read_padded(&vlr.description),
+            data: vlr.record.clone(),
+        }
+    }
+}
@@ -0,0 +1,891 @@
+//! Pipelines of sources, filters, and sinks.
+//!
+//! A pipeline reads points from one or more inputs, in order, runs them through a chain of
+//! filters, and writes every resulting point to each of its outputs. Pipelines can be built up in
+//! code, or described by a TOML document:
+//!
+//! ```toml
+//! chunk_size = 10000
+//! limit = 1000000
+//!
+//! [[inputs]]
+//! path = "data/1.0_0.las"
+//!
+//! [[stages]]
+//! type = "decimate"
+//! step = 10
+//!
+//! [[outputs]]
+//! path = "points.csv"
+//! type = "text"
+//! dimensions = ["x", "y", "z", "gps_time"]
+//! ```
+//!
+//! Inputs and outputs need a `path`, and can name their format with `type` if it can't be guessed
+//! from the path's extension. Stages always need a `type`. Every other key is decoded into the
+//! configuration object of the source, filter, or sink, as if it was a `source` or `sink` table in
+//! a `pabst convert` configuration file.
+//!
+//! By default, the first bad point stops the pipeline. `on_error = "skip"` skips bad points and
+//! counts them by reason instead, and `on_error = "default"` first tries to fix the point by
+//! filling in defaults, e.g. a zero for a missing dimension or an unclassified classification.
+//! Points that can't be read are skipped too, but only from sources that can carry on past them,
+//! i.e. text, las, and ascii ply; a read error from any other source stops the pipeline. Before
+//! any points are read from an input, its dimensions are checked against what each output needs.
+//! If an output needs a dimension that the input, after the stages, doesn't have, the pipeline
+//! fails right away, unless the error policy says otherwise. Dimensions that an output won't write
+//! are listed in the pipeline's `Report`.
+//!
+//! Points that an output won't take can be written somewhere else with a `rejected` table, which
+//! has a `path` and an optional `type` just like an output:
+//!
+//! ```toml
+//! on_error = "skip"
+//!
+//! [rejected]
+//! path = "rejected.txt"
+//! ```
+//!
+//! The first input's metadata, e.g. its las header and coordinate reference system, is passed
+//! through the stages to every output before any points are written. `metadata = false` turns
+//! this off.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use las;
+use sdc;
+use toml;
+
+use {Error, Result};
+use error::Context;
+use filter::{Chain, Filter, open_filter};
+use point::{Dimension, Point, Value};
+use schema::Schema;
+use sink::{open_file_sink, open_file_sink_as, Sink};
+use source::{open_file_source, open_file_source_as, Source};
+use warning::Warnings;
+
+const DEFAULT_CHUNK_SIZE: usize = 10000;
+const MAX_CONSECUTIVE_SOURCE_ERRORS: usize = 1000;
+const MAX_SUBSTITUTIONS: usize = 8;
+
+/// A pipeline.
+#[derive(Debug)]
+pub struct Pipeline {
+    chunk_size: usize,
+    inputs: Vec<Endpoint>,
+    limit: Option<usize>,
+    metadata: bool,
+    on_error: Policy,
+    outputs: Vec<Endpoint>,
+    rejected: Option<Endpoint>,
+    stages: Chain,
+}
+
+/// What to do with a point that can't be read or written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Stop the pipeline and return the error.
+    Fail,
+    /// Skip the point, and count it.
+    Skip,
+    /// Fill in defaults for the point's bad or missing dimensions and try again, skipping the
+    /// point if that doesn't help.
+    ///
+    /// Points that can't be read are always skipped, since there's nothing to fill in.
+    Default,
+}
+
+impl Policy {
+    /// Returns the policy with the given name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::pipeline::Policy;
+    /// assert_eq!(Policy::Skip, Policy::from_name("skip").unwrap());
+    /// assert!(Policy::from_name("ignore").is_err());
+    /// ```
+    pub fn from_name(name: &str) -> Result<Policy> {
+        match name {
+            "fail" => Ok(Policy::Fail),
+            "skip" => Ok(Policy::Skip),
+            "default" => Ok(Policy::Default),
+            _ => Err(Error::Configuration(format!("unknown error policy: {}", name))),
+        }
+    }
+}
+
+/// What happened when a pipeline ran.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// The number of points written to each output.
+    pub npoints: usize,
+    /// The number of points skipped, by reason.
+    pub skipped: BTreeMap<String, usize>,
+    /// The values that outputs couldn't write exactly, by output path.
+    ///
+    /// Outputs that didn't lose anything aren't included.
+    pub warnings: BTreeMap<String, Warnings>,
+    /// The dimensions that the inputs have but outputs won't write, by output path.
+    ///
+    /// Outputs that write everything aren't included.
+    pub dropped: BTreeMap<String, Schema>,
+}
+
+impl Report {
+    /// Returns the total number of skipped points.
+    pub fn nskipped(&self) -> usize {
+        self.skipped.values().fold(0, |sum, &n| sum + n)
+    }
+
+    fn skip(&mut self, err: &Error) {
+        *self.skipped.entry(reason(err)).or_insert(0) += 1;
+    }
+}
+
+/// A path, with an optional format and configuration.
+#[derive(Clone, Debug)]
+struct Endpoint {
+    config: Option<toml::Value>,
+    format: Option<String>,
+    path: String,
+}
+
+impl Endpoint {
+    fn from_table(mut table: toml::Table, what: &str) -> Result<Endpoint> {
+        let path = match table.remove("path") {
+            Some(toml::Value::String(path)) => path,
+            Some(_) => {
+                return Err(Error::Configuration(format!("{} path must be a string", what)))
+            }
+            None => return Err(Error::Configuration(format!("{} is missing a path", what))),
+        };
+        Ok(Endpoint {
+            format: try!(remove_type(&mut table, what)),
+            config: if table.is_empty() {
+                None
+            } else {
+                Some(toml::Value::Table(table))
+            },
+            path: path,
+        })
+    }
+
+    fn open_source(&self) -> Result<Box<Source>> {
+        match self.format {
+            Some(ref format) => open_file_source_as(&self.path, format, self.config.clone()),
+            None => open_file_source(&self.path, self.config.clone()),
+        }
+    }
+
+    fn open_sink(&self) -> Result<Box<Sink>> {
+        match self.format {
+            Some(ref format) => open_file_sink_as(&self.path, format, self.config.clone()),
+            None => open_file_sink(&self.path, self.config.clone()),
+        }
+    }
+}
+
+impl Pipeline {
+    /// Creates a new, empty pipeline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::remove_file;
+    /// use pabst::pipeline::Pipeline;
+    /// let npoints = Pipeline::new()
+    ///                   .input("data/1.0_0.las", None)
+    ///                   .output("pipeline-new.txt", None)
+    ///                   .run()
+    ///                   .unwrap();
+    /// assert_eq!(1, npoints);
+    /// # remove_file("pipeline-new.txt").unwrap();
+    /// ```
+    pub fn new() -> Pipeline {
+        Pipeline {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            inputs: Vec::new(),
+            limit: None,
+            metadata: true,
+            on_error: Policy::Fail,
+            outputs: Vec::new(),
+            rejected: None,
+            stages: Chain::new(),
+        }
+    }
+
+    /// Reads a pipeline from a TOML file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::pipeline::Pipeline;
+    /// let pipeline = Pipeline::from_path("data/pipeline.toml").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Pipeline> {
+        let mut s = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut s));
+        s.parse()
+    }
+
+    /// Creates a pipeline from a parsed TOML table.
+    pub fn from_table(mut table: toml::Table) -> Result<Pipeline> {
+        let mut pipeline = Pipeline::new();
+        if let Some(value) = table.remove("chunk_size") {
+            pipeline.chunk_size = try!(as_usize(value, "chunk_size"));
+            if pipeline.chunk_size == 0 {
+                return Err(Error::Configuration("chunk_size must be at least one".to_string()));
+            }
+        }
+        if let Some(value) = table.remove("limit") {
+            pipeline.limit = Some(try!(as_usize(value, "limit")));
+        }
+        match table.remove("metadata") {
+            Some(toml::Value::Boolean(metadata)) => pipeline.metadata = metadata,
+            Some(_) => return Err(Error::Configuration("metadata must be a boolean".to_string())),
+            None => {}
+        }
+        match table.remove("on_error") {
+            Some(toml::Value::String(name)) => pipeline.on_error = try!(Policy::from_name(&name)),
+            Some(_) => return Err(Error::Configuration("on_error must be a string".to_string())),
+            None => {}
+        }
+        match table.remove("rejected") {
+            Some(toml::Value::Table(table)) => {
+                pipeline.rejected = Some(try!(Endpoint::from_table(table, "rejected")))
+            }
+            Some(_) => return Err(Error::Configuration("rejected must be a table".to_string())),
+            None => {}
+        }
+        for table in try!(remove_tables(&mut table, "inputs")) {
+            pipeline.inputs.push(try!(Endpoint::from_table(table, "input")));
+        }
+        for mut table in try!(remove_tables(&mut table, "stages")) {
+            let name = match try!(remove_type(&mut table, "stage")) {
+                Some(name) => name,
+                None => return Err(Error::Configuration("stage is missing a type".to_string())),
+            };
+            let config = if table.is_empty() {
+                None
+            } else {
+                Some(toml::Value::Table(table))
+            };
+            pipeline.stages.push(try!(open_filter(&name, config)));
+        }
+        for table in try!(remove_tables(&mut table, "outputs")) {
+            pipeline.outputs.push(try!(Endpoint::from_table(table, "output")));
+        }
+        if let Some(key) = table.keys().next() {
+            return Err(Error::Configuration(format!("unknown pipeline key: {}", key)));
+        }
+        Ok(pipeline)
+    }
+
+    /// Sets the number of points read from the inputs at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero, since nothing would ever be read.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Pipeline {
+        assert!(chunk_size > 0, "chunk_size must be at least one");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets the maximum number of points to write to each output.
+    pub fn limit(mut self, limit: Option<usize>) -> Pipeline {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets whether the first input's metadata is passed on to the outputs.
+    pub fn metadata(mut self, metadata: bool) -> Pipeline {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Sets what to do with points that can't be read or written.
+    pub fn on_error(mut self, on_error: Policy) -> Pipeline {
+        self.on_error = on_error;
+        self
+    }
+
+    /// Writes the points that an output won't take to another file.
+    ///
+    /// Rejected points are only collected if the policy isn't `Policy::Fail`.
+    pub fn rejected<P: Into<String>>(mut self, path: P, config: Option<toml::Value>) -> Pipeline {
+        self.rejected = Some(Endpoint {
+            config: config,
+            format: None,
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds an input, whose format is guessed from its extension.
+    pub fn input<P: Into<String>>(mut self, path: P, config: Option<toml::Value>) -> Pipeline {
+        self.inputs.push(Endpoint {
+            config: config,
+            format: None,
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds an input with a named format.
+    pub fn input_as<P, F>(mut self, path: P, format: F, config: Option<toml::Value>) -> Pipeline
+        where P: Into<String>,
+              F: Into<String>
+    {
+        self.inputs.push(Endpoint {
+            config: config,
+            format: Some(format.into()),
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds a filter to the end of the stages.
+    pub fn stage(mut self, filter: Box<Filter>) -> Pipeline {
+        self.stages.push(filter);
+        self
+    }
+
+    /// Adds an output, whose format is guessed from its extension.
+    pub fn output<P: Into<String>>(mut self, path: P, config: Option<toml::Value>) -> Pipeline {
+        self.outputs.push(Endpoint {
+            config: config,
+            format: None,
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds an output with a named format.
+    pub fn output_as<P, F>(mut self, path: P, format: F, config: Option<toml::Value>) -> Pipeline
+        where P: Into<String>,
+              F: Into<String>
+    {
+        self.outputs.push(Endpoint {
+            config: config,
+            format: Some(format.into()),
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Runs this pipeline, returning the number of points written to each output.
+    ///
+    /// All of the outputs are opened before any of the inputs, and the inputs are opened one at a
+    /// time as they are needed.
+    pub fn run(self) -> Result<usize> {
+        self.execute().map(|report| report.npoints)
+    }
+
+    /// Runs this pipeline, returning a report of the points written and skipped.
+    ///
+    /// A point that any output won't take is skipped, and isn't counted as written, even if the
+    /// other outputs took it. Errors reading the input files themselves, and errors from the
+    /// stages, always stop the pipeline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::remove_file;
+    /// use pabst::pipeline::{Pipeline, Policy};
+    /// let report = Pipeline::new()
+    ///                  .input("data/1.0_0.las", None)
+    ///                  .output("pipeline-execute.txt", None)
+    ///                  .on_error(Policy::Skip)
+    ///                  .execute()
+    ///                  .unwrap();
+    /// assert_eq!(1, report.npoints);
+    /// assert_eq!(0, report.nskipped());
+    /// # remove_file("pipeline-execute.txt").unwrap();
+    /// ```
+    pub fn execute(mut self) -> Result<Report> {
+        if self.inputs.is_empty() {
+            return Err(Error::Configuration("pipeline has no inputs".to_string()));
+        }
+        if self.outputs.is_empty() {
+            return Err(Error::Configuration("pipeline has no outputs".to_string()));
+        }
+        let mut sinks = Vec::with_capacity(self.outputs.len());
+        for output in &self.outputs {
+            sinks.push(try!(output.open_sink()));
+        }
+        let mut rejected = match self.rejected {
+            Some(ref rejected) if self.on_error != Policy::Fail => Some(try!(rejected.open_sink())),
+            _ => None,
+        };
+        let mut report = Report::default();
+        'inputs: for (n, input) in self.inputs.iter().enumerate() {
+            let mut source = try!(input.open_source());
+            if let (0, true, Some(metadata)) = (n, self.metadata, source.metadata()) {
+                let metadata = self.stages.metadata(metadata);
+                for sink in sinks.iter_mut().chain(rejected.as_mut()) {
+                    try!(sink.set_metadata(&metadata));
+                }
+            }
+            if let Some(schema) = source.schema() {
+                let schema = self.stages.schema(schema);
+                for (output, sink) in self.outputs.iter().zip(&mut sinks) {
+                    sink.set_schema(&schema);
+                    try!(negotiate(&schema, output, &**sink, self.on_error, &mut report));
+                }
+                if let Some(ref mut rejected) = rejected {
+                    rejected.set_schema(&schema);
+                }
+            }
+            let mut consecutive_errors = 0;
+            loop {
+                let points = match source.source(self.chunk_size) {
+                    Ok(Some(points)) => points,
+                    Ok(None) => break,
+                    Err(err) => {
+                        if self.on_error == Policy::Fail || is_io(&err) || !source.resumable() ||
+                           consecutive_errors >= MAX_CONSECUTIVE_SOURCE_ERRORS {
+                            return Err(err);
+                        }
+                        consecutive_errors += 1;
+                        report.skip(&err);
+                        continue;
+                    }
+                };
+                consecutive_errors = 0;
+                let mut points = try!(self.stages.filter(points));
+                if let Some(limit) = self.limit {
+                    if report.npoints + points.len() > limit {
+                        points.truncate(limit - report.npoints);
+                    }
+                }
+                for point in &points {
+                    let mut accepted = true;
+                    for sink in &mut sinks {
+                        if let Err(err) = sink_point(&mut **sink, point, self.on_error) {
+                            if self.on_error == Policy::Fail {
+                                return Err(err);
+                            }
+                            report.skip(&err);
+                            accepted = false;
+                        }
+                    }
+                    if accepted {
+                        report.npoints += 1;
+                    } else if let Some(ref mut rejected) = rejected {
+                        try!(rejected.sink(point));
+                    }
+                }
+                if self.limit.map_or(false, |limit| report.npoints >= limit) {
+                    break 'inputs;
+                }
+            }
+        }
+        for (output, sink) in self.outputs.iter().zip(sinks) {
+            if let Some(warnings) = sink.warnings() {
+                if !warnings.is_empty() {
+                    let _ = report.warnings.insert(output.path.clone(), warnings.clone());
+                }
+            }
+            try!(sink.close_sink());
+        }
+        if let Some(rejected) = rejected {
+            try!(rejected.close_sink());
+        }
+        Ok(report)
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Pipeline> {
+        let mut parser = toml::Parser::new(s);
+        match parser.parse() {
+            Some(table) => Pipeline::from_table(table),
+            None => {
+                let errors = parser.errors
+                                   .iter()
+                                   .map(|e| {
+                                       let (line, col) = parser.to_linecol(e.lo);
+                                       format!("{}:{}: {}", line + 1, col + 1, e.desc)
+                                   })
+                                   .collect::<Vec<_>>();
+                Err(Error::Configuration(format!("invalid pipeline: {}", errors.join(", "))))
+            }
+        }
+    }
+}
+
+/// Checks an input's dimensions against what an output needs, before any points are read.
+fn negotiate(schema: &Schema,
+             output: &Endpoint,
+             sink: &Sink,
+             policy: Policy,
+             report: &mut Report)
+             -> Result<()> {
+    let compatibility = match sink.requirements() {
+        Some(requirements) => requirements.check(schema),
+        None => return Ok(()),
+    };
+    if !compatibility.missing.is_empty() && policy == Policy::Fail {
+        return Err(Error::MissingDimension(compatibility.missing.join(", "))
+                       .context(Context::new("output").path(&output.path)));
+    }
+    if !compatibility.dropped.is_empty() {
+        let dropped = report.dropped.entry(output.path.clone()).or_insert_with(Schema::new);
+        for name in compatibility.dropped {
+            dropped.insert(name);
+        }
+    }
+    Ok(())
+}
+
+/// Sinks a point, filling in defaults and trying again if the policy says so.
+fn sink_point(sink: &mut Sink, point: &Point, policy: Policy) -> Result<()> {
+    let err = match sink.sink(point) {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    if policy != Policy::Default {
+        return Err(err);
+    }
+    let mut point = point.clone();
+    let mut err = err;
+    for _ in 0..MAX_SUBSTITUTIONS {
+        if !substitute_default(&mut point, &err) {
+            return Err(err);
+        }
+        err = match sink.sink(&point) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+    }
+    Err(err)
+}
+
+/// Fixes the part of the point that caused the error, returning false if we don't know how.
+fn substitute_default(point: &mut Point, err: &Error) -> bool {
+    match *err.root() {
+        Error::MissingDimension(ref name) => {
+            match name.parse::<Dimension>() {
+                Ok(dimension) => {
+                    if point.get(dimension).is_some() {
+                        return false;
+                    }
+                    point.set(dimension, Value::F64(0.0));
+                }
+                Err(_) => {
+                    if point.extra.contains_key(name) {
+                        return false;
+                    }
+                    let _ = point.extra.insert(name.clone(), Value::F64(0.0));
+                }
+            }
+        }
+        Error::Las(las::Error::InvalidClassification(_)) => point.classification = 0,
+        Error::Las(las::Error::InvalidReturnNumber(_)) => point.return_number = None,
+        Error::Las(las::Error::InvalidNumberOfReturns(_)) => point.number_of_returns = None,
+        _ => return false,
+    }
+    true
+}
+
+/// Returns a short reason for skipping a point, without the point index or line number, so that
+/// skipped points can be counted by reason.
+fn reason(err: &Error) -> String {
+    match *err.root() {
+        Error::MissingDimension(ref name) => format!("missing dimension {}", name),
+        Error::ParseInt(ref err) => format!("parse int error: {}", err),
+        Error::ParseFloat(ref err) => format!("parse float error: {}", err),
+        ref err => err.to_string(),
+    }
+}
+
+fn is_io(err: &Error) -> bool {
+    match *err.root() {
+        Error::Io(_) |
+        Error::Las(las::Error::Io(_)) |
+        Error::Sdc(sdc::Error::Io(_)) => true,
+        _ => false,
+    }
+}
+
+fn as_usize(value: toml::Value, key: &str) -> Result<usize> {
+    match value {
+        toml::Value::Integer(n) if n >= 0 => Ok(n as usize),
+        _ => Err(Error::Configuration(format!("{} must be a non-negative integer", key))),
+    }
+}
+
+fn remove_type(table: &mut toml::Table, what: &str) -> Result<Option<String>> {
+    match table.remove("type") {
+        Some(toml::Value::String(name)) => Ok(Some(name)),
+        Some(_) => Err(Error::Configuration(format!("{} type must be a string", what))),
+        None => Ok(None),
+    }
+}
+
+fn remove_tables(table: &mut toml::Table, key: &str) -> Result<Vec<toml::Table>> {
+    match table.remove(key) {
+        Some(toml::Value::Array(values)) => {
+            let mut tables = Vec::with_capacity(values.len());
+            for value in values {
+                match value {
+                    toml::Value::Table(table) => tables.push(table),
+                    _ => {
+                        return Err(Error::Configuration(format!("{} must be an array of tables",
+                                                                key)))
+                    }
+                }
+            }
+            Ok(tables)
+        }
+        Some(_) => Err(Error::Configuration(format!("{} must be an array of tables", key))),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as StdError;
+    use std::fs::{File, remove_file};
+    use std::io::Write;
+
+    use Error;
+    use las;
+    use point::{Point, Value};
+    use source::{open_file_source, Source};
+    use super::*;
+
+    #[test]
+    fn from_path() {
+        let pipeline = Pipeline::from_path("data/pipeline.toml").unwrap();
+        assert_eq!(2, pipeline.inputs.len());
+        assert_eq!(1, pipeline.stages.len());
+        assert_eq!(2, pipeline.outputs.len());
+    }
+
+    #[test]
+    fn run() {
+        let pipeline: Pipeline = r#"
+        [[inputs]]
+        path = "data/4-points.xyz"
+        [[inputs]]
+        path = "data/4-points.csv"
+        [[stages]]
+        type = "decimate"
+        step = 2
+        [[outputs]]
+        path = "pipeline-run.points"
+        type = "text"
+        dimensions = ["x", "y"]
+        [[outputs]]
+        path = "pipeline-run.las"
+        "#
+                                     .parse()
+                                     .unwrap();
+        assert_eq!(4, pipeline.run().unwrap());
+        let mut source = open_file_source("pipeline-run.las", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(4, points.len());
+        assert_eq!(3.0, points[1].x);
+        let mut source = open_file_source_as("pipeline-run.points", "text", None).unwrap();
+        assert_eq!(4, source.source_to_end(100).unwrap().len());
+        remove_file("pipeline-run.points").unwrap();
+        remove_file("pipeline-run.las").unwrap();
+    }
+
+    #[test]
+    fn limit() {
+        let npoints = Pipeline::new()
+                          .input("data/4-points.xyz", None)
+                          .output("pipeline-limit.txt", None)
+                          .chunk_size(3)
+                          .limit(Some(2))
+                          .run()
+                          .unwrap();
+        assert_eq!(2, npoints);
+        remove_file("pipeline-limit.txt").unwrap();
+    }
+
+    #[test]
+    fn on_error() {
+        let pipeline = || {
+            Pipeline::new()
+                .input("data/4-points.xyz", None)
+                .output("pipeline-on-error.sdc", None)
+        };
+        match pipeline().run() {
+            Err(err) => {
+                assert_eq!("output pipeline-on-error.sdc: Missing dimension: gps_time, scan_angle",
+                           err.to_string())
+            }
+            Ok(_) => panic!("sdc points need a gps time"),
+        }
+
+        let report = pipeline().on_error(Policy::Skip)
+                               .rejected("pipeline-on-error.txt", None)
+                               .execute()
+                               .unwrap();
+        assert_eq!(0, report.npoints);
+        assert_eq!(Some(&4), report.skipped.get("missing dimension gps_time"));
+        let mut source = open_file_source("pipeline-on-error.txt", None).unwrap();
+        assert_eq!(4, source.source_to_end(100).unwrap().len());
+
+        let report = pipeline().on_error(Policy::Default).execute().unwrap();
+        assert_eq!(4, report.npoints);
+        assert_eq!(0, report.nskipped());
+        let mut source = open_file_source("pipeline-on-error.sdc", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(Some(0.0), points[0].gps_time);
+        assert_eq!(Some(0.0), points[0].scan_angle);
+        remove_file("pipeline-on-error.sdc").unwrap();
+        remove_file("pipeline-on-error.txt").unwrap();
+    }
+
+    #[test]
+    fn warnings() {
+        let report = Pipeline::new()
+                         .input("data/4-points.csv", None)
+                         .output("pipeline-warnings.las", None)
+                         .output("pipeline-warnings.txt", None)
+                         .execute()
+                         .unwrap();
+        assert_eq!(1, report.warnings.len());
+        let warnings = &report.warnings["pipeline-warnings.las"];
+        assert_eq!(4, warnings.get("unknown scan_direction written as forward").unwrap().count);
+
+        let config: toml::Value = "strict = true".parse().unwrap();
+        let pipeline = Pipeline::new()
+                           .input("data/4-points.csv", None)
+                           .output("pipeline-warnings.las", Some(config));
+        match pipeline.run() {
+            Err(ref err) => assert_eq!("lossy conversion", err.description()),
+            Ok(_) => panic!("strict las output should fail"),
+        }
+        remove_file("pipeline-warnings.las").unwrap();
+        remove_file("pipeline-warnings.txt").unwrap();
+    }
+
+    #[test]
+    fn dropped() {
+        let config: toml::Value = r#"dimensions = ["x", "y", "z", "range"]"#.parse().unwrap();
+        let report = Pipeline::new()
+                         .input("data/4-points.sdc", None)
+                         .output("pipeline-dropped.txt", Some(config))
+                         .output("pipeline-dropped.sdc", None)
+                         .execute()
+                         .unwrap();
+        assert_eq!(1, report.dropped.len());
+        let dropped = &report.dropped["pipeline-dropped.txt"];
+        assert!(dropped.contains("gps_time"));
+        assert!(!dropped.contains("range"));
+
+        let config: toml::Value = r#"dimensions = ["x", "range"]"#.parse().unwrap();
+        let pipeline = Pipeline::new()
+                           .input("data/4-points.csv", None)
+                           .output("pipeline-dropped.txt", Some(config.clone()));
+        assert!(pipeline.run().is_err());
+        let pipeline = Pipeline::new()
+                           .input("data/4-points.csv", None)
+                           .stage(open_filter("trajectory",
+                                              Some(r#"path = "data/trajectory.txt""#
+                                                       .parse()
+                                                       .unwrap()))
+                                      .unwrap())
+                           .output("pipeline-dropped.txt", Some(config))
+                           .on_error(Policy::Skip);
+        assert_eq!(4, pipeline.execute().unwrap().nskipped());
+        remove_file("pipeline-dropped.txt").unwrap();
+        remove_file("pipeline-dropped.sdc").unwrap();
+    }
+
+    #[test]
+    fn metadata() {
+        let _ = Pipeline::new()
+                    .input("data/1.0_0.las", None)
+                    .output("pipeline-metadata.las", None)
+                    .run()
+                    .unwrap();
+        let source = open_file_source("pipeline-metadata.las", None).unwrap();
+        let metadata = source.metadata().unwrap();
+        assert_eq!(Some("libLAS".to_string()), metadata.system_identifier);
+        assert_eq!(Some("libLAS 1.2".to_string()), metadata.generating_software);
+        assert_eq!(2008, metadata.creation_date.unwrap().year);
+        assert_eq!(2, metadata.vlrs.len());
+
+        let _ = Pipeline::new()
+                    .input("data/1.0_0.las", None)
+                    .output("pipeline-metadata.las", None)
+                    .metadata(false)
+                    .run()
+                    .unwrap();
+        let source = open_file_source("pipeline-metadata.las", None).unwrap();
+        let metadata = source.metadata().unwrap();
+        assert_eq!(None, metadata.system_identifier);
+        assert_eq!(0, metadata.vlrs.len());
+        assert!("metadata = \"yes\"".parse::<Pipeline>().is_err());
+        remove_file("pipeline-metadata.las").unwrap();
+    }
+
+    #[test]
+    fn skip_bad_lines() {
+        let _ = File::create("pipeline-skip-bad-lines.xyz")
+                    .unwrap()
+                    .write_all(b"1 2 3\n4 5 foo\n7 8 9\n")
+                    .unwrap();
+        let pipeline = r#"
+        on_error = "skip"
+        [[inputs]]
+        path = "pipeline-skip-bad-lines.xyz"
+        [[outputs]]
+        path = "pipeline-skip-bad-lines.txt"
+        "#;
+        let report = pipeline.parse::<Pipeline>().unwrap().execute().unwrap();
+        assert_eq!(2, report.npoints);
+        assert_eq!(1, report.nskipped());
+        remove_file("pipeline-skip-bad-lines.xyz").unwrap();
+        remove_file("pipeline-skip-bad-lines.txt").unwrap();
+    }
+
+    #[test]
+    fn substitute_defaults() {
+        let mut point = Point { classification: 40, ..Default::default() };
+        assert!(substitute_default(&mut point,
+                                   &Error::Las(las::Error::InvalidClassification(40))));
+        assert_eq!(0, point.classification);
+        assert!(substitute_default(&mut point, &Error::MissingDimension("range".to_string())));
+        assert_eq!(Some(0.0), point.range);
+        assert!(!substitute_default(&mut point, &Error::MissingDimension("range".to_string())));
+        assert!(substitute_default(&mut point, &Error::MissingDimension("amp".to_string())));
+        assert_eq!(Some(&Value::F64(0.0)), point.extra.get("amp"));
+        assert!(!substitute_default(&mut point, &Error::Configuration("bad".to_string())));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_chunk_size() {
+        let _ = Pipeline::new().chunk_size(0);
+    }
+
+    #[test]
+    fn bad_pipelines() {
+        assert!("[[inputs]]\ntype = \"las\"".parse::<Pipeline>().is_err());
+        assert!("[[stages]]\nstep = 2".parse::<Pipeline>().is_err());
+        assert!("[[stages]]\ntype = \"wat\"".parse::<Pipeline>().is_err());
+        assert!("input = 1".parse::<Pipeline>().is_err());
+        assert!("[[inputs]\n".parse::<Pipeline>().is_err());
+        assert!("on_error = \"ignore\"".parse::<Pipeline>().is_err());
+        assert!("chunk_size = 0".parse::<Pipeline>().is_err());
+        assert!("chunk_size = -1".parse::<Pipeline>().is_err());
+        assert!("rejected = \"rejected.txt\"".parse::<Pipeline>().is_err());
+        assert!(Pipeline::new().output("/dev/null", None).run().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! The bits of the PLY format that are shared by the PLY source and sink.
+//!
+//! There isn't an upstream PLY library that does what we need, so unlike our other formats we do
+//! the header parsing and the (very simple) data encoding ourselves.
+
+use std::io::{Read, Write};
+
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
+
+use {Error, Result};
+use point::Dimension;
+
+/// Eight-bit PLY colors are scaled by this to fill our sixteen-bit colors.
+const COLOR_SCALE: f64 = 257.0;
+
+/// How the PLY body is encoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Whitespace-delimited text, one element per line.
+    Ascii,
+    /// Binary, little endian.
+    BinaryLittleEndian,
+    /// Binary, big endian.
+    BinaryBigEndian,
+}
+
+impl Encoding {
+    /// Parses an encoding from the name used in the PLY `format` line.
+    pub fn from_str(s: &str) -> Result<Encoding> {
+        match s {
+            "ascii" => Ok(Encoding::Ascii),
+            "binary_little_endian" => Ok(Encoding::BinaryLittleEndian),
+            "binary_big_endian" => Ok(Encoding::BinaryBigEndian),
+            _ => Err(Error::Ply(format!("unknown encoding: {}", s))),
+        }
+    }
+
+    /// Returns the name of this encoding, as used in the PLY `format` line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Ascii => "ascii",
+            Encoding::BinaryLittleEndian => "binary_little_endian",
+            Encoding::BinaryBigEndian => "binary_big_endian",
+        }
+    }
+}
+
+/// The data type of a PLY property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataType {
+    /// i8.
+    Char,
+    /// u8.
+    UChar,
+    /// i16.
+    Short,
+    /// u16.
+    UShort,
+    /// i32.
+    Int,
+    /// u32.
+    UInt,
+    /// f32.
+    Float,
+    /// f64.
+    Double,
+}
+
+impl DataType {
+    /// Parses a data type from either the original or the sized PLY type names.
+    pub fn from_str(s: &str) -> Result<DataType> {
+        match s {
+            "char" | "int8" => Ok(DataType::Char),
+            "uchar" | "uint8" => Ok(DataType::UChar),
+            "short" | "int16" => Ok(DataType::Short),
+            "ushort" | "uint16" => Ok(DataType::UShort),
+            "int" | "int32" => Ok(DataType::Int),
+            "uint" | "uint32" => Ok(DataType::UInt),
+            "float" | "float32" => Ok(DataType::Float),
+            "double" | "float64" => Ok(DataType::Double),
+            _ => Err(Error::Ply(format!("unknown data type: {}", s))),
+        }
+    }
+
+    /// Returns the name of this data type.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DataType::Char => "char",
+            DataType::UChar => "uchar",
+            DataType::Short => "short",
+            DataType::UShort => "ushort",
+            DataType::Int => "int",
+            DataType::UInt => "uint",
+            DataType::Float => "float",
+            DataType::Double => "double",
+        }
+    }
+
+    /// Returns the data type we use to write a dimension.
+    pub fn for_dimension(dimension: Dimension) -> DataType {
+        match dimension {
+            Dimension::X | Dimension::Y | Dimension::Z | Dimension::ScanAngle |
+            Dimension::GpsTime | Dimension::Range | Dimension::Width | Dimension::RgIndex => {
+                DataType::Double
+            }
+            Dimension::Intensity | Dimension::PointSourceId | Dimension::Nir => DataType::UShort,
+            Dimension::Red | Dimension::Green | Dimension::Blue |
+            Dimension::ReturnNumber | Dimension::NumberOfReturns | Dimension::ScanDirection |
+            Dimension::EdgeOfFlightLine | Dimension::Classification | Dimension::Synthetic |
+            Dimension::KeyPoint | Dimension::Withheld | Dimension::UserData |
+            Dimension::FacetNumber | Dimension::TargetType | Dimension::HighChannel => {
+                DataType::UChar
+            }
+        }
+    }
+
+    /// Converts a value read as this type into the units of a dimension.
+    ///
+    /// PLY colors are usually eight bits, so `uchar` colors are scaled up to sixteen bits.
+    pub fn to_dimension(&self, dimension: Dimension, value: f64) -> f64 {
+        if *self == DataType::UChar && is_color(dimension) {
+            value * COLOR_SCALE
+        } else {
+            value
+        }
+    }
+
+    /// Converts a dimension's value into a value to be written as this type.
+    pub fn from_dimension(&self, dimension: Dimension, value: f64) -> f64 {
+        if *self == DataType::UChar && is_color(dimension) {
+            (value / COLOR_SCALE).round()
+        } else {
+            value
+        }
+    }
+
+    /// Is this a floating point type?
+    pub fn is_float(&self) -> bool {
+        match *self {
+            DataType::Float | DataType::Double => true,
+            _ => false,
+        }
+    }
+
+    /// Reads one binary value of this type.
+    pub fn read<R: Read, B: ByteOrder>(&self, reader: &mut R) -> Result<f64> {
+        Ok(match *self {
+            DataType::Char => try!(reader.read_i8()) as f64,
+            DataType::UChar => try!(reader.read_u8()) as f64,
+            DataType::Short => try!(reader.read_i16::<B>()) as f64,
+            DataType::UShort => try!(reader.read_u16::<B>()) as f64,
+            DataType::Int => try!(reader.read_i32::<B>()) as f64,
+            DataType::UInt => try!(reader.read_u32::<B>()) as f64,
+            DataType::Float => try!(reader.read_f32::<B>()) as f64,
+            DataType::Double => try!(reader.read_f64::<B>()),
+        })
+    }
+
+    /// Writes one binary value of this type.
+    pub fn write<W: Write, B: ByteOrder>(&self, writer: &mut W, value: f64) -> Result<()> {
+        match *self {
+            DataType::Char => try!(writer.write_i8(value as i8)),
+            DataType::UChar => try!(writer.write_u8(value as u8)),
+            DataType::Short => try!(writer.write_i16::<B>(value as i16)),
+            DataType::UShort => try!(writer.write_u16::<B>(value as u16)),
+            DataType::Int => try!(writer.write_i32::<B>(value as i32)),
+            DataType::UInt => try!(writer.write_u32::<B>(value as u32)),
+            DataType::Float => try!(writer.write_f32::<B>(value as f32)),
+            DataType::Double => try!(writer.write_f64::<B>(value)),
+        }
+        Ok(())
+    }
+}
+
+fn is_color(dimension: Dimension) -> bool {
+    dimension == Dimension::Red || dimension == Dimension::Green || dimension == Dimension::Blue
+}
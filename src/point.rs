@@ -1,9 +1,15 @@
 //! A catchall LiDAR point.
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
 use std::u16;
 
+use {Error, Result};
+
 /// A point.
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Point {
     /// The x dimension of a point. All points must have these.
     pub x: f64,
@@ -82,15 +88,373 @@ pub struct Point {
     ///
     /// TODO this field should be turned into a newtype or enum
     pub high_channel: Option<bool>,
+    /// The color of this point, e.g. from a colorized terrestrial scan or a photogrammetric cloud.
+    pub color: Option<Color>,
+    /// The near infrared value of this point. This goes along with color in the newest las point
+    /// formats, which our las library doesn't know about yet, so for now only the text and PLY
+    /// sinks can write it.
+    pub nir: Option<u16>,
+    /// The sampled waveform that this point was detected in, if the source kept it.
+    pub waveform: Option<Waveform>,
     /// When doing error propagation, it can be useful to carry along a point's partial derivatives
     /// with respect to each of the fourteen degrees of freedom in the LiDAR georeferencing
     /// equation. If we have the partials, we can combine them with compoment errors to get final
     /// propagated error.
     pub partials: Option<Partials>,
+    /// Any other named dimensions that don't have a field of their own, like las extra bytes or
+    /// Riegl's deviation. Names shouldn't collide with the names of `Dimension`s.
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Point {
+    /// Returns the value of a dimension of this point, or `None` if the point doesn't have it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::{Dimension, Point, Value};
+    /// let point = Point { x: 1.0, ..Default::default() };
+    /// assert_eq!(Some(Value::F64(1.0)), point.get(Dimension::X));
+    /// assert_eq!(None, point.get(Dimension::GpsTime));
+    /// ```
+    pub fn get(&self, dimension: Dimension) -> Option<Value> {
+        match dimension {
+            Dimension::X => Some(Value::F64(self.x)),
+            Dimension::Y => Some(Value::F64(self.y)),
+            Dimension::Z => Some(Value::F64(self.z)),
+            Dimension::Intensity => Some(Value::U16(self.intensity.as_u16())),
+            Dimension::ReturnNumber => self.return_number.map(Value::Usize),
+            Dimension::NumberOfReturns => self.number_of_returns.map(Value::Usize),
+            Dimension::ScanDirection => {
+                match self.scan_direction {
+                    ScanDirection::Unknown => None,
+                    scan_direction => Some(Value::ScanDirection(scan_direction)),
+                }
+            }
+            Dimension::EdgeOfFlightLine => Some(Value::Bool(self.edge_of_flight_line)),
+            Dimension::Classification => Some(Value::U8(self.classification)),
+            Dimension::Synthetic => Some(Value::Bool(self.synthetic)),
+            Dimension::KeyPoint => Some(Value::Bool(self.key_point)),
+            Dimension::Withheld => Some(Value::Bool(self.withheld)),
+            Dimension::ScanAngle => self.scan_angle.map(Value::F64),
+            Dimension::PointSourceId => self.point_source_id.map(Value::U16),
+            Dimension::UserData => self.user_data.map(Value::U8),
+            Dimension::GpsTime => self.gps_time.map(Value::F64),
+            Dimension::Range => self.range.map(Value::F64),
+            Dimension::Width => self.width.map(Value::F64),
+            Dimension::RgIndex => self.rg_index.map(Value::F64),
+            Dimension::FacetNumber => self.facet_number.map(Value::U8),
+            Dimension::TargetType => self.target_type.map(Value::U8),
+            Dimension::HighChannel => self.high_channel.map(Value::Bool),
+            Dimension::Red => self.color.map(|c| Value::U16(c.red)),
+            Dimension::Green => self.color.map(|c| Value::U16(c.green)),
+            Dimension::Blue => self.color.map(|c| Value::U16(c.blue)),
+            Dimension::Nir => self.nir.map(Value::U16),
+        }
+    }
+
+    /// Sets the value of a dimension of this point.
+    ///
+    /// Values are converted to the type of the field, so sources that only know about numbers can
+    /// use `Value::F64` for everything. Non-zero numbers are `true` flags and forward scan
+    /// directions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::{Dimension, Point, Value};
+    /// let mut point = Point::default();
+    /// point.set(Dimension::ReturnNumber, Value::F64(2.0));
+    /// assert_eq!(Some(2), point.return_number);
+    /// ```
+    pub fn set(&mut self, dimension: Dimension, value: Value) {
+        let n = value.as_f64();
+        match dimension {
+            Dimension::X => self.x = n,
+            Dimension::Y => self.y = n,
+            Dimension::Z => self.z = n,
+            Dimension::Intensity => self.intensity = Intensity::from_u16(n as u16),
+            Dimension::ReturnNumber => self.return_number = Some(n as usize),
+            Dimension::NumberOfReturns => self.number_of_returns = Some(n as usize),
+            Dimension::ScanDirection => {
+                self.scan_direction = match value {
+                    Value::ScanDirection(scan_direction) => scan_direction,
+                    _ => {
+                        if n != 0.0 {
+                            ScanDirection::Forward
+                        } else {
+                            ScanDirection::Backward
+                        }
+                    }
+                }
+            }
+            Dimension::EdgeOfFlightLine => self.edge_of_flight_line = n != 0.0,
+            Dimension::Classification => self.classification = n as u8,
+            Dimension::Synthetic => self.synthetic = n != 0.0,
+            Dimension::KeyPoint => self.key_point = n != 0.0,
+            Dimension::Withheld => self.withheld = n != 0.0,
+            Dimension::ScanAngle => self.scan_angle = Some(n),
+            Dimension::PointSourceId => self.point_source_id = Some(n as u16),
+            Dimension::UserData => self.user_data = Some(n as u8),
+            Dimension::GpsTime => self.gps_time = Some(n),
+            Dimension::Range => self.range = Some(n),
+            Dimension::Width => self.width = Some(n),
+            Dimension::RgIndex => self.rg_index = Some(n),
+            Dimension::FacetNumber => self.facet_number = Some(n as u8),
+            Dimension::TargetType => self.target_type = Some(n as u8),
+            Dimension::HighChannel => self.high_channel = Some(n != 0.0),
+            Dimension::Red => self.color_mut().red = n as u16,
+            Dimension::Green => self.color_mut().green = n as u16,
+            Dimension::Blue => self.color_mut().blue = n as u16,
+            Dimension::Nir => self.nir = Some(n as u16),
+        }
+    }
+
+    fn color_mut(&mut self) -> &mut Color {
+        if self.color.is_none() {
+            self.color = Some(Color::default());
+        }
+        self.color.as_mut().unwrap()
+    }
+}
+
+/// A named dimension of a `Point`.
+///
+/// Sinks and sources use these instead of matching on field names themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dimension {
+    /// The x coordinate.
+    X,
+    /// The y coordinate.
+    Y,
+    /// The z coordinate.
+    Z,
+    /// The intensity, as a u16.
+    Intensity,
+    /// The return number.
+    ReturnNumber,
+    /// The number of returns.
+    NumberOfReturns,
+    /// The scan direction.
+    ScanDirection,
+    /// The edge of flight line flag.
+    EdgeOfFlightLine,
+    /// The classification.
+    Classification,
+    /// The synthetic flag.
+    Synthetic,
+    /// The key point flag.
+    KeyPoint,
+    /// The withheld flag.
+    Withheld,
+    /// The scan angle.
+    ScanAngle,
+    /// The point source id.
+    PointSourceId,
+    /// The user data.
+    UserData,
+    /// The GNSS time.
+    GpsTime,
+    /// The range.
+    Range,
+    /// The pulse width.
+    Width,
+    /// The range index.
+    RgIndex,
+    /// The mirror facet number.
+    FacetNumber,
+    /// The target type.
+    TargetType,
+    /// The high channel flag.
+    HighChannel,
+    /// The red channel of the color.
+    Red,
+    /// The green channel of the color.
+    Green,
+    /// The blue channel of the color.
+    Blue,
+    /// The near infrared channel.
+    Nir,
+}
+
+/// All the dimensions, in the order they appear in `Point`.
+pub const DIMENSIONS: [Dimension; 26] = [Dimension::X,
+                                         Dimension::Y,
+                                         Dimension::Z,
+                                         Dimension::Intensity,
+                                         Dimension::ReturnNumber,
+                                         Dimension::NumberOfReturns,
+                                         Dimension::ScanDirection,
+                                         Dimension::EdgeOfFlightLine,
+                                         Dimension::Classification,
+                                         Dimension::Synthetic,
+                                         Dimension::KeyPoint,
+                                         Dimension::Withheld,
+                                         Dimension::ScanAngle,
+                                         Dimension::PointSourceId,
+                                         Dimension::UserData,
+                                         Dimension::GpsTime,
+                                         Dimension::Range,
+                                         Dimension::Width,
+                                         Dimension::RgIndex,
+                                         Dimension::FacetNumber,
+                                         Dimension::TargetType,
+                                         Dimension::HighChannel,
+                                         Dimension::Red,
+                                         Dimension::Green,
+                                         Dimension::Blue,
+                                         Dimension::Nir];
+
+impl Dimension {
+    /// Returns true if points can be missing this dimension, i.e. `Point::get` can return `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Dimension;
+    /// assert!(!Dimension::X.is_optional());
+    /// assert!(Dimension::GpsTime.is_optional());
+    /// ```
+    pub fn is_optional(&self) -> bool {
+        match *self {
+            Dimension::X | Dimension::Y | Dimension::Z | Dimension::Intensity |
+            Dimension::EdgeOfFlightLine | Dimension::Classification | Dimension::Synthetic |
+            Dimension::KeyPoint | Dimension::Withheld => false,
+            _ => true,
+        }
+    }
+
+    /// Returns the name of this dimension, which is the same as the `Point` field name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Dimension;
+    /// assert_eq!("gps_time", Dimension::GpsTime.name());
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Dimension::X => "x",
+            Dimension::Y => "y",
+            Dimension::Z => "z",
+            Dimension::Intensity => "intensity",
+            Dimension::ReturnNumber => "return_number",
+            Dimension::NumberOfReturns => "number_of_returns",
+            Dimension::ScanDirection => "scan_direction",
+            Dimension::EdgeOfFlightLine => "edge_of_flight_line",
+            Dimension::Classification => "classification",
+            Dimension::Synthetic => "synthetic",
+            Dimension::KeyPoint => "key_point",
+            Dimension::Withheld => "withheld",
+            Dimension::ScanAngle => "scan_angle",
+            Dimension::PointSourceId => "point_source_id",
+            Dimension::UserData => "user_data",
+            Dimension::GpsTime => "gps_time",
+            Dimension::Range => "range",
+            Dimension::Width => "width",
+            Dimension::RgIndex => "rg_index",
+            Dimension::FacetNumber => "facet_number",
+            Dimension::TargetType => "target_type",
+            Dimension::HighChannel => "high_channel",
+            Dimension::Red => "red",
+            Dimension::Green => "green",
+            Dimension::Blue => "blue",
+            Dimension::Nir => "nir",
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = Error;
+
+    /// Parses a dimension from its name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Dimension;
+    /// assert_eq!(Dimension::ScanAngle, "scan_angle".parse().unwrap());
+    /// assert!("not_a_dimension".parse::<Dimension>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Dimension> {
+        DIMENSIONS.iter()
+                  .find(|d| d.name() == s)
+                  .map(|&d| d)
+                  .ok_or_else(|| Error::UnknownDimension(s.to_string()))
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The typed value of one of a point's dimensions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    /// A floating point value.
+    F64(f64),
+    /// A byte.
+    U8(u8),
+    /// A short.
+    U16(u16),
+    /// A count, e.g. a return number.
+    Usize(usize),
+    /// A large unsigned integer.
+    U64(u64),
+    /// A signed integer.
+    I64(i64),
+    /// A flag.
+    Bool(bool),
+    /// A scan direction.
+    ScanDirection(ScanDirection),
+}
+
+impl Value {
+    /// Returns this value as an `f64`, for sinks that only know about numbers.
+    ///
+    /// Flags are zero or one, and scan directions are one for forward and zero for backward, like
+    /// in las.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Value;
+    /// assert_eq!(1.0, Value::Bool(true).as_f64());
+    /// ```
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Value::F64(n) => n,
+            Value::U8(n) => n as f64,
+            Value::U16(n) => n as f64,
+            Value::Usize(n) => n as f64,
+            Value::U64(n) => n as f64,
+            Value::I64(n) => n as f64,
+            Value::Bool(b) => if b { 1.0 } else { 0.0 },
+            Value::ScanDirection(ScanDirection::Forward) => 1.0,
+            Value::ScanDirection(_) => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::F64(n) => write!(f, "{}", n),
+            Value::U8(n) => write!(f, "{}", n),
+            Value::U16(n) => write!(f, "{}", n),
+            Value::Usize(n) => write!(f, "{}", n),
+            Value::U64(n) => write!(f, "{}", n),
+            Value::I64(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::ScanDirection(_) => write!(f, "{}", self.as_f64()),
+        }
+    }
 }
 
 /// The direction that the scanner mirror was moving when the pulse was emitted.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ScanDirection {
     /// Forward.
     Forward,
@@ -106,6 +470,57 @@ impl Default for ScanDirection {
     }
 }
 
+/// A 16-bit rgb color, like in las.
+///
+/// Eight-bit colors should be scaled up to fill the 16-bit range, as the las specification asks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Color {
+    /// The red channel.
+    pub red: u16,
+    /// The green channel.
+    pub green: u16,
+    /// The blue channel.
+    pub blue: u16,
+}
+
+/// A digitized waveform, with enough geometry to put each of its samples in space.
+///
+/// The sample at index `i` was digitized `i * sampling_interval` seconds after the first one, and
+/// sits at `anchor + direction * i * sampling_interval`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Waveform {
+    /// The digitized samples.
+    pub samples: Vec<u16>,
+    /// The time between two samples, in seconds.
+    pub sampling_interval: f64,
+    /// The location of the first sample.
+    pub anchor: [f64; 3],
+    /// The distance the pulse travels along each axis in one second.
+    pub direction: [f64; 3],
+}
+
+impl Waveform {
+    /// Returns the time, in seconds after the first sample, at which the pulse passed a location.
+    ///
+    /// The location is projected onto the waveform's line, so it doesn't have to be exactly on
+    /// it. Returns zero if the waveform has no direction.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Waveform;
+    /// let waveform = Waveform { direction: [2.0, 0.0, 0.0], ..Default::default() };
+    /// assert_eq!(1.5, waveform.time_at([3.0, 1.0, 0.0]));
+    /// ```
+    pub fn time_at(&self, location: [f64; 3]) -> f64 {
+        let norm = self.direction.iter().fold(0.0, |sum, d| sum + d * d);
+        if norm == 0.0 {
+            return 0.0;
+        }
+        (0..3).fold(0.0, |sum, i| sum + (location[i] - self.anchor[i]) * self.direction[i]) / norm
+    }
+}
+
 /// An intensity value, with information about its minimum and maximum possible values.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Intensity {
@@ -161,31 +576,326 @@ impl Intensity {
     pub fn as_u16(&self) -> u16 {
         (u16::MAX as f64 * (self.value - self.min) / (self.max - self.min)) as u16
     }
+
+    /// Returns how far `as_u16` is from this intensity, once it's scaled back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Intensity;
+    /// assert_eq!(0.0, Intensity::from_u16(10).u16_error());
+    /// assert!(Intensity::new(1.0, 0.0, 2.0).u16_error() > 0.0);
+    /// ```
+    pub fn u16_error(&self) -> f64 {
+        let value = if self.max > self.min {
+            self.min + self.as_u16() as f64 * (self.max - self.min) / u16::MAX as f64
+        } else {
+            self.as_u16() as f64
+        };
+        (value - self.value).abs()
+    }
 }
 
 /// A 3x14 collection of partial derivates for the x, y, and z components of a LiDAR point.
-#[derive(Clone, Copy, Debug)]
+///
+/// Partials are built up one component at a time:
+///
+/// ```
+/// use pabst::point::{Component, Partials, Xyz};
+/// let partials = Partials::new()
+///                    .with(Component::Range, Xyz::new(0.0, 0.0, -1.0))
+///                    .with(Component::GnssZ, Xyz::new(0.0, 0.0, 1.0));
+/// assert_eq!(-1.0, partials.get(Component::Range).z);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Partials {
-    range: Xyz,
-    scan_angle: Xyz,
-    boresight_roll: Xyz,
-    boresight_pitch: Xyz,
-    boresight_yaw: Xyz,
-    lever_arm_x: Xyz,
-    lever_arm_y: Xyz,
-    lever_arm_z: Xyz,
-    roll: Xyz,
-    pitch: Xyz,
-    yaw: Xyz,
-    gnss_x: Xyz,
-    gnss_y: Xyz,
-    gnss_z: Xyz,
+    partials: [Xyz; 14],
+}
+
+/// One of the fourteen components of the LiDAR georeferencing equation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    /// The laser range.
+    Range,
+    /// The scan angle.
+    ScanAngle,
+    /// The roll of the scanner relative to the IMU.
+    BoresightRoll,
+    /// The pitch of the scanner relative to the IMU.
+    BoresightPitch,
+    /// The yaw of the scanner relative to the IMU.
+    BoresightYaw,
+    /// The x offset from the IMU to the scanner.
+    LeverArmX,
+    /// The y offset from the IMU to the scanner.
+    LeverArmY,
+    /// The z offset from the IMU to the scanner.
+    LeverArmZ,
+    /// The platform roll.
+    Roll,
+    /// The platform pitch.
+    Pitch,
+    /// The platform yaw.
+    Yaw,
+    /// The x position of the GNSS antenna.
+    GnssX,
+    /// The y position of the GNSS antenna.
+    GnssY,
+    /// The z position of the GNSS antenna.
+    GnssZ,
+}
+
+/// All the components, in order.
+pub const COMPONENTS: [Component; 14] = [Component::Range,
+                                         Component::ScanAngle,
+                                         Component::BoresightRoll,
+                                         Component::BoresightPitch,
+                                         Component::BoresightYaw,
+                                         Component::LeverArmX,
+                                         Component::LeverArmY,
+                                         Component::LeverArmZ,
+                                         Component::Roll,
+                                         Component::Pitch,
+                                         Component::Yaw,
+                                         Component::GnssX,
+                                         Component::GnssY,
+                                         Component::GnssZ];
+
+impl Component {
+    /// Returns this component's snake case name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Component;
+    /// assert_eq!("boresight_roll", Component::BoresightRoll.name());
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Component::Range => "range",
+            Component::ScanAngle => "scan_angle",
+            Component::BoresightRoll => "boresight_roll",
+            Component::BoresightPitch => "boresight_pitch",
+            Component::BoresightYaw => "boresight_yaw",
+            Component::LeverArmX => "lever_arm_x",
+            Component::LeverArmY => "lever_arm_y",
+            Component::LeverArmZ => "lever_arm_z",
+            Component::Roll => "roll",
+            Component::Pitch => "pitch",
+            Component::Yaw => "yaw",
+            Component::GnssX => "gnss_x",
+            Component::GnssY => "gnss_y",
+            Component::GnssZ => "gnss_z",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl Partials {
+    /// Creates partials that are all zero.
+    pub fn new() -> Partials {
+        Default::default()
+    }
+
+    /// Sets the partial derivatives with respect to one component, builder style.
+    pub fn with(mut self, component: Component, xyz: Xyz) -> Partials {
+        self.set(component, xyz);
+        self
+    }
+
+    /// Returns the partial derivatives of x, y, and z with respect to one component.
+    pub fn get(&self, component: Component) -> Xyz {
+        self.partials[component.index()]
+    }
+
+    /// Sets the partial derivatives with respect to one component.
+    pub fn set(&mut self, component: Component, xyz: Xyz) {
+        self.partials[component.index()] = xyz;
+    }
+
+    /// Rotates every partial derivative by a 3x3 rotation matrix.
+    ///
+    /// If a point is moved by `p' = R p + t`, its partials move by `R` alone.
+    pub fn rotate(&mut self, rotation: &[[f64; 3]; 3]) {
+        for xyz in &mut self.partials {
+            *xyz = xyz.rotate(rotation);
+        }
+    }
+
+    /// Propagates component errors through these partials into the point's covariance.
+    ///
+    /// This is `J Σ Jᵀ`, where `J` is the 3x14 matrix of partials and `Σ` is the component
+    /// covariance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::{Component, ComponentErrors, Partials, Xyz};
+    /// let partials = Partials::new().with(Component::Range, Xyz::new(0.0, 0.6, 0.8));
+    /// let errors = ComponentErrors::new().std_dev(Component::Range, 0.05);
+    /// let covariance = partials.propagate(&errors);
+    /// assert!((covariance.vertical() - 0.04).abs() < 1e-12);
+    /// ```
+    pub fn propagate(&self, errors: &ComponentErrors) -> Covariance {
+        let mut covariance = [[0.0; 3]; 3];
+        for (i, a) in self.partials.iter().enumerate() {
+            for (j, b) in self.partials.iter().enumerate() {
+                let sigma = errors.covariance[i][j];
+                if sigma == 0.0 {
+                    continue;
+                }
+                let (a, b) = (a.to_array(), b.to_array());
+                for r in 0..3 {
+                    for c in 0..3 {
+                        covariance[r][c] += a[r] * sigma * b[c];
+                    }
+                }
+            }
+        }
+        Covariance(covariance)
+    }
+}
+
+impl Add for Partials {
+    type Output = Partials;
+    fn add(mut self, other: Partials) -> Partials {
+        for (a, &b) in self.partials.iter_mut().zip(other.partials.iter()) {
+            *a = *a + b;
+        }
+        self
+    }
+}
+
+impl Sub for Partials {
+    type Output = Partials;
+    fn sub(self, other: Partials) -> Partials {
+        self + other * -1.0
+    }
+}
+
+impl Mul<f64> for Partials {
+    type Output = Partials;
+    fn mul(mut self, scalar: f64) -> Partials {
+        for xyz in &mut self.partials {
+            *xyz = *xyz * scalar;
+        }
+        self
+    }
 }
 
 /// A dumb structure of xyz f64s.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Xyz {
-    x: f64,
-    y: f64,
-    z: f64,
+    /// The x value.
+    pub x: f64,
+    /// The y value.
+    pub y: f64,
+    /// The z value.
+    pub z: f64,
+}
+
+impl Xyz {
+    /// Creates a new xyz.
+    pub fn new(x: f64, y: f64, z: f64) -> Xyz {
+        Xyz { x: x, y: y, z: z }
+    }
+
+    fn rotate(&self, rotation: &[[f64; 3]; 3]) -> Xyz {
+        let r = rotation;
+        Xyz {
+            x: r[0][0] * self.x + r[0][1] * self.y + r[0][2] * self.z,
+            y: r[1][0] * self.x + r[1][1] * self.y + r[1][2] * self.z,
+            z: r[2][0] * self.x + r[2][1] * self.y + r[2][2] * self.z,
+        }
+    }
+
+    fn to_array(&self) -> [f64; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl Add for Xyz {
+    type Output = Xyz;
+    fn add(self, other: Xyz) -> Xyz {
+        Xyz::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Xyz {
+    type Output = Xyz;
+    fn sub(self, other: Xyz) -> Xyz {
+        Xyz::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Xyz {
+    type Output = Xyz;
+    fn mul(self, scalar: f64) -> Xyz {
+        Xyz::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+/// The covariance of the fourteen georeferencing components.
+///
+/// Ranges, lever arms, and GNSS positions are in meters, and angles are in radians.
+#[derive(Clone, Copy)]
+pub struct ComponentErrors {
+    covariance: [[f64; 14]; 14],
+}
+
+impl fmt::Debug for ComponentErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<&[f64]> = self.covariance.iter().map(|row| &row[..]).collect();
+        f.debug_struct("ComponentErrors").field("covariance", &rows).finish()
+    }
+}
+
+impl Default for ComponentErrors {
+    fn default() -> ComponentErrors {
+        ComponentErrors { covariance: [[0.0; 14]; 14] }
+    }
+}
+
+impl ComponentErrors {
+    /// Creates component errors that are all zero.
+    pub fn new() -> ComponentErrors {
+        Default::default()
+    }
+
+    /// Sets the standard deviation of one component, builder style.
+    pub fn std_dev(mut self, component: Component, std_dev: f64) -> ComponentErrors {
+        self.covariance[component.index()][component.index()] = std_dev * std_dev;
+        self
+    }
+
+    /// Sets the covariance between two components, which is kept symmetric.
+    pub fn covariance(mut self, a: Component, b: Component, covariance: f64) -> ComponentErrors {
+        self.covariance[a.index()][b.index()] = covariance;
+        self.covariance[b.index()][a.index()] = covariance;
+        self
+    }
+}
+
+/// The 3x3 covariance of a point's x, y, and z.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Covariance(pub [[f64; 3]; 3]);
+
+impl Covariance {
+    /// Returns the standard deviations of x, y, and z.
+    pub fn std_devs(&self) -> Xyz {
+        Xyz::new(self.0[0][0].sqrt(), self.0[1][1].sqrt(), self.0[2][2].sqrt())
+    }
+
+    /// Returns the horizontal total propagated uncertainty, `sqrt(σx² + σy²)`.
+    pub fn horizontal(&self) -> f64 {
+        (self.0[0][0] + self.0[1][1]).sqrt()
+    }
+
+    /// Returns the vertical total propagated uncertainty, `σz`.
+    pub fn vertical(&self) -> f64 {
+        self.0[2][2].sqrt()
+    }
 }
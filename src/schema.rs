@@ -0,0 +1,146 @@
+//! Which dimensions sources fill in, and which dimensions sinks need.
+//!
+//! Sources advertise a `Schema` of the dimensions their points carry, filters say how they change
+//! it, and sinks declare `Requirements`: the dimensions they can't write without, and the
+//! dimensions they can write at all. Checking the two against each other before any points are
+//! read finds a missing dimension up front, rather than at the first point, and says which
+//! dimensions won't make it into the output.
+//!
+//! Dimensions are named the same way everywhere else: `Dimension` names for the fixed dimensions,
+//! and the key in `Point::extra` for extra dimensions.
+
+use std::collections::BTreeSet;
+use std::collections::btree_set::Iter;
+
+use point::Dimension;
+
+/// A set of dimension names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    names: BTreeSet<String>,
+}
+
+impl Schema {
+    /// Creates a new, empty schema.
+    pub fn new() -> Schema {
+        Schema { names: BTreeSet::new() }
+    }
+
+    /// Creates a schema from some dimensions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Dimension;
+    /// use pabst::schema::Schema;
+    /// let schema = Schema::from_dimensions(&[Dimension::X, Dimension::GpsTime]);
+    /// assert!(schema.contains("gps_time"));
+    /// ```
+    pub fn from_dimensions(dimensions: &[Dimension]) -> Schema {
+        let mut schema = Schema::new();
+        for dimension in dimensions {
+            schema.insert(dimension.name());
+        }
+        schema
+    }
+
+    /// Adds a dimension or extra dimension by name, builder style.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::schema::Schema;
+    /// let schema = Schema::new().with("x").with("deviation");
+    /// assert_eq!(2, schema.len());
+    /// ```
+    pub fn with<S: Into<String>>(mut self, name: S) -> Schema {
+        self.insert(name);
+        self
+    }
+
+    /// Adds a dimension or extra dimension by name.
+    pub fn insert<S: Into<String>>(&mut self, name: S) {
+        let _ = self.names.insert(name.into());
+    }
+
+    /// Adds every dimension in another schema to this one.
+    pub fn extend(&mut self, other: &Schema) {
+        self.names.extend(other.names.iter().cloned());
+    }
+
+    /// Returns true if this schema has a dimension with this name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    /// Returns the number of dimensions in this schema.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true if this schema has no dimensions.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns an iterator over the dimension names, in order.
+    pub fn iter(&self) -> Iter<String> {
+        self.names.iter()
+    }
+}
+
+/// The dimensions a sink needs, and the dimensions it can write.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Requirements {
+    /// Dimensions that every point needs, or the sink fails.
+    pub required: Schema,
+    /// Dimensions that the sink writes. Required dimensions are always usable.
+    pub usable: Schema,
+}
+
+impl Requirements {
+    /// Creates new requirements, adding the required dimensions to the usable ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::schema::{Requirements, Schema};
+    /// let requirements = Requirements::new(Schema::new().with("gps_time"), Schema::new());
+    /// assert!(requirements.usable.contains("gps_time"));
+    /// ```
+    pub fn new(required: Schema, mut usable: Schema) -> Requirements {
+        usable.extend(&required);
+        Requirements {
+            required: required,
+            usable: usable,
+        }
+    }
+
+    /// Checks a source's dimensions against these requirements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::schema::{Requirements, Schema};
+    /// let requirements = Requirements::new(Schema::new().with("gps_time"),
+    ///                                      Schema::new().with("x"));
+    /// let compatibility = requirements.check(&Schema::new().with("x").with("range"));
+    /// assert_eq!(vec!["gps_time".to_string()], compatibility.missing);
+    /// assert_eq!(vec!["range".to_string()], compatibility.dropped);
+    /// ```
+    pub fn check(&self, schema: &Schema) -> Compatibility {
+        Compatibility {
+            missing: self.required.iter().filter(|n| !schema.contains(n)).cloned().collect(),
+            dropped: schema.iter().filter(|n| !self.usable.contains(n)).cloned().collect(),
+        }
+    }
+}
+
+/// How well a source's dimensions fit a sink.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compatibility {
+    /// Required dimensions that the source doesn't have.
+    pub missing: Vec<String>,
+    /// Dimensions that the source has, but the sink won't write.
+    pub dropped: Vec<String>,
+}
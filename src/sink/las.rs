@@ -1,18 +1,34 @@
 //! Sink points into a las file.
 
-use std::io::{Write, Seek};
+use std::{cmp, u8};
+use std::fs::File;
+use std::io::{BufWriter, Write, Seek, SeekFrom};
 use std::path::Path;
 
+use byteorder::{LittleEndian, WriteBytesExt};
 use las;
 
 use Result;
 use error::Error;
+use extra_bytes::ExtraBytes;
+use gps_time::{SECONDS_PER_WEEK, Standard};
+use las_header::{GLOBAL_ENCODING_OFFSET, HeaderPatch, NUMBER_OF_VLRS_OFFSET};
+#[cfg(feature = "laz")]
+use laszip::Compressor;
+use metadata::{Metadata, SPEC_USER_ID};
 use point::{Point, ScanDirection};
+use schema::{Requirements, Schema};
 use sink::{FileSink, Sink};
+use source::las::point_format_schema;
+use warning::Warnings;
+use wave_packet::WavePackets;
+
+const DEFAULT_POINT_FORMAT: u8 = 1;
+const DEFAULT_WAVE_PACKET_DESCRIPTORS: usize = 16;
 
 impl<W: Write + Seek> Sink for las::writer::OpenWriter<W> {
     fn sink(&mut self, point: &Point) -> Result<()> {
-        try!(self.write_point(&try!(from_point(point))));
+        try!(self.write_point(&try!(from_point(point, &[], &mut Warnings::new(false)))));
         Ok(())
     }
 
@@ -21,73 +37,498 @@ impl<W: Write + Seek> Sink for las::writer::OpenWriter<W> {
     }
 }
 
-fn from_point(point: &Point) -> Result<las::Point> {
+/// A las writer that can also write a point's extra dimensions and waveforms.
+///
+/// If the configuration doesn't name a point format, the format is picked from the source's
+/// schema, if we're told it before the first point arrives: sources with color get format 3 (or
+/// 2, if they don't have GPS times), and sources without color get format 1 (or 0). Otherwise,
+/// the format is 1. If we're writing waveforms, formats 1 and 3 become formats 4 and 5. Colors
+/// and GPS times that the format can't hold are counted in the writer's `Warnings`.
+///
+/// Las 1.2 and later say whether GPS times are week seconds or adjusted standard time. If the
+/// configuration doesn't say, we guess from the first GPS time: anything outside of a single week
+/// is adjusted standard time.
+///
+/// Scan angles, intensities, scan directions, and return numbers that las can't hold exactly are
+/// counted in the writer's `Warnings`.
+///
+/// Metadata, e.g. from the source las file, fills in the header's provenance and adds the
+/// source's variable length records, so the coordinate reference system comes along. The extra
+/// bytes and waveform records are left out, since they describe the source's points, not ours.
+/// Of the global encoding, the GPS time type and the well known text flag are carried over.
+#[derive(Debug)]
+pub struct Writer<W: Write + Seek> {
+    config: LasConfig,
+    extra_dimensions: Vec<String>,
+    gps_time_type: Option<las::GpsTimeType>,
+    has_gps_time_type: bool,
+    point_format: Option<u8>,
+    pending: Option<HeaderPatch<W>>,
+    vlrs: Vec<las::Vlr>,
+    warnings: Warnings,
+    wave_packets: Option<WavePackets<BufWriter<File>>>,
+    writer: Option<las::writer::OpenWriter<HeaderPatch<W>>>,
+}
+
+impl<W: Write + Seek> Writer<W> {
+    /// Configures a las writer.
+    ///
+    /// The header isn't written until the first point arrives, or the writer is closed. Waveforms
+    /// need somewhere to go, so use `with_wave_packets` to write them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::sink::las::Writer;
+    /// let writer = Writer::new(Cursor::new(Vec::new()), Default::default()).unwrap();
+    /// ```
+    pub fn new(writer: W, config: LasConfig) -> Result<Writer<W>> {
+        if config.waveforms() {
+            return Err(Error::Configuration("las waveforms need a .wdp file".to_string()));
+        }
+        Writer::build(writer, config, None)
+    }
+
+    /// Configures a las writer that writes point waveforms to a .wdp file.
+    ///
+    /// Waveforms are written to las 1.3 files, or to las 1.4 files if that version is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::remove_file;
+    /// use std::io::Cursor;
+    /// use pabst::sink::las::Writer;
+    /// let writer = Writer::with_wave_packets(Cursor::new(Vec::new()),
+    ///                                        Default::default(),
+    ///                                        "with_wave_packets.wdp")
+    ///                  .unwrap();
+    /// # remove_file("with_wave_packets.wdp").unwrap();
+    /// ```
+    pub fn with_wave_packets<P: AsRef<Path>>(writer: W,
+                                             config: LasConfig,
+                                             path: P)
+                                             -> Result<Writer<W>> {
+        let max_descriptors = config.wave_packet_descriptors
+                                    .unwrap_or(DEFAULT_WAVE_PACKET_DESCRIPTORS);
+        let wave_packets = try!(WavePackets::new(BufWriter::new(try!(File::create(path))),
+                                                 max_descriptors));
+        Writer::build(writer, config, Some(wave_packets))
+    }
+
+    fn build(writer: W,
+             mut config: LasConfig,
+             wave_packets: Option<WavePackets<BufWriter<File>>>)
+             -> Result<Writer<W>> {
+        let extra_dimensions = config.extra_dimensions.clone().unwrap_or(Vec::new());
+        let mut writer = HeaderPatch::new(writer).extra_bytes(8 * extra_dimensions.len() as u16);
+        let mut vlrs = Vec::new();
+        if let Some(ref wave_packets) = wave_packets {
+            let version_minor = match config.version {
+                Some(Version { major: 1, minor }) if minor <= 3 => 3,
+                None => 3,
+                Some(Version { major: 1, minor: 4 }) => 4,
+                Some(Version { major, minor }) => {
+                    return Err(Error::Configuration(format!("las {}.{} files can't hold \
+                                                             waveforms",
+                                                            major,
+                                                            minor)))
+                }
+            };
+            writer = writer.wave_packets(version_minor);
+            config.version = Some(Version {
+                major: 1,
+                minor: 2,
+            });
+            config.point_format = match config.point_format {
+                Some(4) => Some(1),
+                Some(5) => Some(3),
+                None => None,
+                Some(n) => {
+                    return Err(Error::Configuration(format!("las point format {} can't hold \
+                                                             waveforms",
+                                                            n)))
+                }
+            };
+            vlrs.push(wave_packets.placeholder());
+        }
+        if let Some(point_format) = config.point_format {
+            let _ = try!(las::PointFormat::from_u8(point_format));
+        }
+        if !extra_dimensions.is_empty() {
+            vlrs.push(try!(ExtraBytes::vlr(&extra_dimensions)));
+        }
+        let point_format = config.point_format;
+        let warnings = Warnings::new(config.strict.unwrap_or(false));
+        let gps_time_type = match config.gps_time_type.as_ref().map(|s| s.as_str()) {
+            Some("week") | Some("week_seconds") => Some(las::GpsTimeType::Week),
+            Some("adjusted_standard") => Some(las::GpsTimeType::AdjustedStandard),
+            Some(s) => {
+                return Err(Error::Configuration(format!("unknown las gps time type: {}", s)))
+            }
+            None => None,
+        };
+        let has_gps_time_type = wave_packets.is_some() ||
+                                config.version.map_or(false, |v| {
+            las::Version::new(v.major, v.minor).has_gps_time_type()
+        });
+        Ok(Writer {
+            config: config,
+            extra_dimensions: extra_dimensions,
+            gps_time_type: gps_time_type,
+            has_gps_time_type: has_gps_time_type,
+            point_format: point_format,
+            pending: Some(writer),
+            vlrs: vlrs,
+            warnings: warnings,
+            wave_packets: wave_packets,
+            writer: None,
+        })
+    }
+
+    /// Returns the values that couldn't be written exactly, so far.
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+
+    /// Rewrites the header, fills in any waveform descriptors, and returns the underlying `Write`.
+    pub fn close(mut self) -> Result<W> {
+        try!(self.open());
+        let writer = try!(self.writer.take().unwrap().close()).into_inner();
+        let header_size = writer.header_size();
+        let global_encoding = writer.global_encoding();
+        let mut writer = writer.into_inner();
+        // Laz streams can't seek from the end, so we come back to where we left off instead.
+        let end = try!(writer.seek(SeekFrom::Current(0)));
+        if self.has_gps_time_type &&
+           self.gps_time_type == Some(las::GpsTimeType::AdjustedStandard) {
+            let global_encoding = global_encoding | las::GpsTimeType::AdjustedStandard.as_mask();
+            let _ = try!(writer.seek(SeekFrom::Start(GLOBAL_ENCODING_OFFSET)));
+            try!(writer.write_u16::<LittleEndian>(global_encoding));
+            let _ = try!(writer.seek(SeekFrom::Start(end)));
+        }
+        if let Some(wave_packets) = self.wave_packets.take() {
+            let (descriptors, count) = wave_packets.descriptors();
+            // The placeholder is replaced by the descriptors.
+            let count = count + self.vlrs.len() as u32 - 1;
+            let _ = try!(writer.seek(SeekFrom::Start(NUMBER_OF_VLRS_OFFSET)));
+            try!(writer.write_u32::<LittleEndian>(count));
+            let _ = try!(writer.seek(SeekFrom::Start(header_size)));
+            try!(writer.write_all(&descriptors));
+            let _ = try!(writer.seek(SeekFrom::Start(end)));
+            try!(try!(wave_packets.finish()).flush());
+        }
+        Ok(writer)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        if let Some(writer) = self.pending.take() {
+            let point_format = self.point_format.unwrap_or(DEFAULT_POINT_FORMAT);
+            self.point_format = Some(point_format);
+            let writer = try!(configure(las::Writer::new(writer), self.config.clone()))
+                             .vlrs(self.vlrs.clone())
+                             .point_format(try!(las::PointFormat::from_u8(point_format)));
+            self.writer = Some(try!(writer.open()));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Sink for Writer<W> {
+    fn sink(&mut self, point: &Point) -> Result<()> {
+        try!(self.open());
+        if let (None, Some(gps_time)) = (self.gps_time_type, point.gps_time) {
+            self.gps_time_type = Some(if gps_time < 0.0 || gps_time > SECONDS_PER_WEEK {
+                las::GpsTimeType::AdjustedStandard
+            } else {
+                las::GpsTimeType::Week
+            });
+        }
+        let mut las_point = try!(from_point(point, &self.extra_dimensions, &mut self.warnings));
+        try!(fit_point_format(&mut las_point,
+                              las::PointFormat(self.point_format.unwrap()),
+                              &mut self.warnings));
+        if let Some(ref mut wave_packets) = self.wave_packets {
+            let mut bytes = try!(wave_packets.write(point));
+            bytes.extend(las_point.extra_bytes.unwrap_or(Vec::new()));
+            las_point.extra_bytes = Some(bytes);
+        }
+        try!(self.writer.as_mut().unwrap().write_point(&las_point));
+        Ok(())
+    }
+
+    fn close_sink(self: Box<Self>) -> Result<()> {
+        try!(try!(self.close()).flush());
+        Ok(())
+    }
+
+    fn warnings(&self) -> Option<&Warnings> {
+        Some(&self.warnings)
+    }
+
+    /// Extra dimensions are required, and the point format's dimensions are usable. Call
+    /// `set_schema` first, so the format fits the points; otherwise it's the default format.
+    fn requirements(&self) -> Option<Requirements> {
+        let mut required = Schema::new();
+        for name in &self.extra_dimensions {
+            required.insert(name.clone());
+        }
+        let point_format = self.point_format.unwrap_or(DEFAULT_POINT_FORMAT);
+        Some(Requirements::new(required, point_format_schema(point_format)))
+    }
+
+    /// Picks the point format, unless it's configured or the writer has already been opened.
+    fn set_schema(&mut self, schema: &Schema) {
+        if self.pending.is_none() || self.config.point_format.is_some() {
+            return;
+        }
+        let has_color = ["red", "green", "blue"].iter().all(|name| schema.contains(name));
+        let has_gps_time = schema.contains("gps_time");
+        self.point_format = Some(match (has_color, has_gps_time) {
+            (true, _) if self.wave_packets.is_some() => 3,
+            (false, _) if self.wave_packets.is_some() => 1,
+            (true, true) => 3,
+            (true, false) => 2,
+            (false, true) => 1,
+            (false, false) => 0,
+        });
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        if self.pending.is_none() {
+            return Err(Error::Configuration("las metadata has to be set before any points are \
+                                             written"
+                                                .to_string()));
+        }
+        let mut vlrs = Vec::new();
+        for vlr in &metadata.vlrs {
+            let describes_points = vlr.user_id == SPEC_USER_ID &&
+                                   (vlr.record_id == 4 ||
+                                    (vlr.record_id >= 100 && vlr.record_id < 355));
+            if !describes_points {
+                vlrs.push(try!(vlr.to_las()));
+            }
+        }
+        let writer = self.pending.take().unwrap();
+        self.pending = Some(writer.metadata(metadata));
+        self.vlrs.extend(vlrs);
+        if self.gps_time_type.is_none() {
+            self.gps_time_type = match metadata.gps_time {
+                Some(Standard::WeekSeconds) => Some(las::GpsTimeType::Week),
+                Some(Standard::AdjustedStandard) => Some(las::GpsTimeType::AdjustedStandard),
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+}
+
+fn from_point(point: &Point,
+              extra_dimensions: &[String],
+              warnings: &mut Warnings)
+              -> Result<las::Point> {
+    let return_number = cmp::min(point.return_number.unwrap_or(0), u8::MAX as usize) as u8;
+    let return_number = try!(las::point::ReturnNumber::from_u8(return_number));
+    let number_of_returns = cmp::min(point.number_of_returns.unwrap_or(0), u8::MAX as usize) as u8;
+    let number_of_returns = try!(las::point::NumberOfReturns::from_u8(number_of_returns));
+    let classification = try!(las::point::Classification::from_u8(point.classification));
+    let extra_bytes = if extra_dimensions.is_empty() {
+        None
+    } else {
+        Some(try!(ExtraBytes::encode(extra_dimensions, point)))
+    };
+
+    // Only count warnings once the point is sure to be converted.
+    let scan_angle = point.scan_angle.unwrap_or(0.0);
+    let scan_angle_rank = scan_angle as i8;
+    try!(warnings.warn_error("scan_angle truncated to a whole degree",
+                             scan_angle - scan_angle_rank as f64));
+    try!(warnings.warn_error("intensity rescaled to a u16", point.intensity.u16_error()));
+    if point.scan_direction == ScanDirection::Unknown {
+        try!(warnings.warn("unknown scan_direction written as forward"));
+    }
+    if point.return_number.is_none() {
+        try!(warnings.warn("unknown return_number written as 0"));
+    }
+    if point.number_of_returns.is_none() {
+        try!(warnings.warn("unknown number_of_returns written as 0"));
+    }
     Ok(las::Point {
         x: point.x,
         y: point.y,
         z: point.z,
         intensity: point.intensity.as_u16(),
-        return_number: try!(las::point::ReturnNumber::from_u8(point.return_number.unwrap_or(0) as u8)),
-        number_of_returns: try!(las::point::NumberOfReturns::from_u8(point.number_of_returns.unwrap_or(0) as u8)),
+        return_number: return_number,
+        number_of_returns: number_of_returns,
         scan_direction: match point.scan_direction {
             ScanDirection::Forward | ScanDirection::Unknown => las::point::ScanDirection::Forward,
-            ScanDirection::Backward => las::point::ScanDirection::Backward
+            ScanDirection::Backward => las::point::ScanDirection::Backward,
         },
         edge_of_flight_line: point.edge_of_flight_line,
-        classification: try!(las::point::Classification::from_u8(point.classification)),
+        classification: classification,
         synthetic: point.synthetic,
         key_point: point.key_point,
         withheld: point.withheld,
-        scan_angle_rank: point.scan_angle.unwrap_or(0.0) as i8,
+        scan_angle_rank: scan_angle_rank,
         user_data: point.user_data.unwrap_or(0),
         point_source_id: point.point_source_id.unwrap_or(0),
         gps_time: point.gps_time,
-        // FIXME these should be properties too
-        red: None,
-        green: None,
-        blue: None,
-        extra_bytes: None,
+        red: point.color.map(|c| c.red),
+        green: point.color.map(|c| c.green),
+        blue: point.color.map(|c| c.blue),
+        extra_bytes: extra_bytes,
     })
 }
 
+/// Fills in or throws away a point's GPS time and color, so it fits the point format.
+fn fit_point_format(point: &mut las::Point,
+                    point_format: las::PointFormat,
+                    warnings: &mut Warnings)
+                    -> Result<()> {
+    if point_format.has_time() {
+        if point.gps_time.is_none() {
+            try!(warnings.warn("unknown gps_time written as 0"));
+            point.gps_time = Some(0.0);
+        }
+    } else if point.gps_time.take().is_some() {
+        try!(warnings.warn(&format!("gps_time not written by point format {}", point_format.0)));
+    }
+    let has_color = point.red.is_some();
+    if point_format.has_color() {
+        if !has_color {
+            try!(warnings.warn("unknown color written as black"));
+            point.red = Some(0);
+            point.green = Some(0);
+            point.blue = Some(0);
+        }
+    } else if has_color {
+        try!(warnings.warn(&format!("color not written by point format {}", point_format.0)));
+        point.red = None;
+        point.green = None;
+        point.blue = None;
+    }
+    Ok(())
+}
+
 impl<W: Write + Seek> FileSink for las::Writer<W> {
     type Config = LasConfig;
 
     fn open_file_sink<P: AsRef<Path>>(path: P, config: LasConfig) -> Result<Box<Sink>> {
-        let mut writer = try!(las::Writer::from_path(path));
-        if let Some(s) = config.scale_factors {
-            writer = writer.scale_factors(s.x, s.y, s.z);
+        let writer = BufWriter::new(try!(File::create(&path)));
+        if config.waveforms() {
+            let wdp = path.as_ref().with_extension("wdp");
+            Ok(Box::new(try!(Writer::with_wave_packets(writer, config, wdp))))
+        } else {
+            Ok(Box::new(try!(Writer::new(writer, config))))
         }
-        if let Some(a) = config.auto_offsets {
-            writer = writer.auto_offsets(a);
-        }
-        if let Some(p) = config.point_format {
-            writer = writer.point_format(try!(las::PointFormat::from_u8(p)));
-        }
-        if let Some(v) = config.version {
-            writer = writer.version(v.major, v.minor);
-        }
-        Ok(Box::new(try!(writer.open())))
     }
 }
 
-/// Decodable configuration
-#[derive(Clone, Copy, Debug, RustcDecodable)]
+/// A las writer that compresses its points with laszip.
+#[cfg(feature = "laz")]
+#[derive(Debug)]
+pub struct LazWriter<W: Write + Seek + Send + 'static>(Writer<Compressor<W>>);
+
+#[cfg(feature = "laz")]
+impl<W: Write + Seek + Send + 'static> Sink for LazWriter<W> {
+    fn sink(&mut self, point: &Point) -> Result<()> {
+        self.0.sink(point)
+    }
+
+    fn close_sink(self: Box<Self>) -> Result<()> {
+        try!(self.0.close()).finish()
+    }
+
+    fn warnings(&self) -> Option<&Warnings> {
+        Some(self.0.warnings())
+    }
+
+    fn requirements(&self) -> Option<Requirements> {
+        self.0.requirements()
+    }
+
+    fn set_schema(&mut self, schema: &Schema) {
+        self.0.set_schema(schema)
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        self.0.set_metadata(metadata)
+    }
+}
+
+#[cfg(feature = "laz")]
+impl<W: Write + Seek + Send + 'static> FileSink for LazWriter<W> {
+    type Config = LasConfig;
+
+    fn open_file_sink<P: AsRef<Path>>(path: P, config: LasConfig) -> Result<Box<Sink>> {
+        let compressor = Compressor::new(BufWriter::new(try!(File::create(path))));
+        Ok(Box::new(LazWriter(try!(Writer::new(compressor, config)))))
+    }
+}
+
+fn configure<W: Write + Seek>(mut writer: las::Writer<W>,
+                              config: LasConfig)
+                              -> Result<las::Writer<W>> {
+    if let Some(s) = config.scale_factors {
+        writer = writer.scale_factors(s.x, s.y, s.z);
+    }
+    if let Some(a) = config.auto_offsets {
+        writer = writer.auto_offsets(a);
+    }
+    if let Some(v) = config.version {
+        writer = writer.version(v.major, v.minor);
+    }
+    Ok(writer)
+}
+
+/// Decodable configuration, shared by las and laz sinks.
+///
+/// If `point_format` isn't provided, it is picked based on whether the source has color and GPS
+/// time, or is 1 if we don't know.
+/// `extra_dimensions` are the names of entries in `Point::extra` to write as las extra bytes. They
+/// are all written as doubles.
+/// If `waveforms` is true, or `point_format` is 4 or 5, point waveforms are written to a .wdp file
+/// next to the las file. Room is kept for `wave_packet_descriptors` different waveform shapes,
+/// 16 by default.
+/// `gps_time_type` is "week_seconds" or "adjusted_standard", and sets the las 1.2+ global encoding
+/// bit. If it isn't provided, it is guessed from the first GPS time.
+/// If `strict` is true, values that las can't hold exactly are errors, rather than warnings.
+#[derive(Clone, Debug, RustcDecodable)]
 pub struct LasConfig {
     scale_factors: Option<ScaleFactors>,
     auto_offsets: Option<bool>,
     point_format: Option<u8>,
     version: Option<Version>,
+    extra_dimensions: Option<Vec<String>>,
+    waveforms: Option<bool>,
+    wave_packet_descriptors: Option<usize>,
+    gps_time_type: Option<String>,
+    strict: Option<bool>,
+}
+
+impl LasConfig {
+    fn waveforms(&self) -> bool {
+        self.waveforms.unwrap_or(false) || self.point_format.map_or(false, |p| p == 4 || p == 5)
+    }
 }
 
 impl Default for LasConfig {
     fn default() -> LasConfig {
-       LasConfig {
-           auto_offsets: Some(true),
-           point_format: Some(1),
-           version: Some(Version { major: 1, minor: 2}),
-           scale_factors: None,
-       }
+        LasConfig {
+            auto_offsets: Some(true),
+            point_format: None,
+            version: Some(Version {
+                major: 1,
+                minor: 2,
+            }),
+            scale_factors: None,
+            extra_dimensions: None,
+            waveforms: None,
+            wave_packet_descriptors: None,
+            gps_time_type: None,
+            strict: None,
+        }
     }
 }
 
@@ -108,24 +549,33 @@ pub struct Version {
 
 #[cfg(test)]
 mod tests {
-    use std::fs::remove_file;
+    use std::fs::{File, remove_file};
+    use std::io::{Cursor, Read};
 
+    use byteorder::{ByteOrder, LittleEndian};
     use las;
+    use rustc_serialize::Decodable;
     use toml;
 
+    use gps_time::Standard;
+    use las_header::{EXTERNAL_WAVE_PACKETS, WKT, read_padded};
+    use metadata::{CreationDate, Metadata, Vlr};
+    use point::{Color, Dimension, Point, Value, Waveform};
     use sink::{open_file_sink, Sink};
     use source::{open_file_source, Source};
+    use source::las::Reader;
+    use super::*;
 
     #[test]
     fn read_write_las() {
-        let mut source = las::Reader::from_path("data/1.0_0.las").unwrap();
+        let mut source = Reader::from_path("data/1.0_0.las").unwrap();
         let mut sink = las::Writer::from_path("read_write_las.las").unwrap().open().unwrap();
         for point in &source.source_to_end(100).unwrap() {
             sink.sink(point).unwrap()
         }
         let _ = sink.close().unwrap();
 
-        let mut source = las::Reader::from_path("read_write_las.las").unwrap();
+        let mut source = Reader::from_path("read_write_las.las").unwrap();
         let points = source.source_to_end(100).unwrap();
         assert_eq!(1, points.len());
         remove_file("read_write_las.las").unwrap();
@@ -149,9 +599,347 @@ mod tests {
         }
         sink.close_sink().unwrap();
 
-        let mut source = las::Reader::from_path("source_and_sink.las").unwrap();
+        let mut source = Reader::from_path("source_and_sink.las").unwrap();
         let points = source.source_to_end(100).unwrap();
         assert_eq!(1, points.len());
         remove_file("source_and_sink.las").unwrap();
     }
+
+    #[test]
+    fn extra_dimensions() {
+        let mut source = open_file_source("data/1.0_0.las", None).unwrap();
+        let mut points = source.source_to_end(100).unwrap();
+        for point in &mut points {
+            let _ = point.extra.insert("deviation".to_string(), Value::U16(12));
+        }
+        let config = toml::Parser::new("extra_dimensions = [\"deviation\"]").parse().unwrap();
+        let mut sink = open_file_sink("extra_dimensions.las", Some(toml::Value::Table(config)))
+                           .unwrap();
+        for point in &points {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut source = open_file_source("extra_dimensions.las", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(1, points.len());
+        assert_eq!(Some(&Value::F64(12.0)), points[0].extra.get("deviation"));
+        remove_file("extra_dimensions.las").unwrap();
+    }
+
+    #[test]
+    fn color() {
+        let mut source = open_file_source("data/1.0_1.las", None).unwrap();
+        let mut points = source.source_to_end(100).unwrap();
+        let color = Color {
+            red: 1,
+            green: 2,
+            blue: 3,
+        };
+        for point in &mut points {
+            point.color = Some(color);
+        }
+        let mut sink = open_file_sink("color.las", None).unwrap();
+        sink.set_schema(&source.schema().unwrap().with("red").with("green").with("blue"));
+        for point in &points {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut reader = Reader::from_path("color.las").unwrap();
+        assert_eq!(3, reader.header().point_data_format.0);
+        let points = reader.source_to_end(100).unwrap();
+        assert_eq!(Some(color), points[0].color);
+        remove_file("color.las").unwrap();
+    }
+
+    #[test]
+    fn color_without_gps_time() {
+        let point = Point {
+            color: Some(Default::default()),
+            ..Default::default()
+        };
+        let mut sink = open_file_sink("color_without_gps_time.las", None).unwrap();
+        sink.set_schema(&Schema::from_dimensions(&[Dimension::X, Dimension::Red,
+                                                   Dimension::Green, Dimension::Blue]));
+        sink.sink(&point).unwrap();
+        sink.close_sink().unwrap();
+        let reader = las::Reader::from_path("color_without_gps_time.las").unwrap();
+        assert_eq!(2, reader.header().point_data_format.0);
+        remove_file("color_without_gps_time.las").unwrap();
+    }
+
+    #[test]
+    fn default_point_format() {
+        let mut writer = Writer::new(Cursor::new(Vec::new()), Default::default()).unwrap();
+        writer.sink(&Point::default()).unwrap();
+        writer.sink(&Point { color: Some(Default::default()), ..Default::default() }).unwrap();
+        assert_eq!(1,
+                   writer.warnings().get("color not written by point format 1").unwrap().count);
+        let bytes = writer.close().unwrap().into_inner();
+        assert_eq!(1, bytes[104]);
+    }
+
+    #[test]
+    fn uppercase_extension() {
+        let mut sink = open_file_sink("uppercase_extension.LAS", None).unwrap();
+        sink.sink(&Point::default()).unwrap();
+        sink.close_sink().unwrap();
+        let reader = las::Reader::from_path("uppercase_extension.LAS").unwrap();
+        assert_eq!(1, reader.header().number_of_point_records);
+        remove_file("uppercase_extension.LAS").unwrap();
+    }
+
+    #[test]
+    fn gps_time_type() {
+        let write = |gps_time: f64, config: &str| {
+            let config: toml::Value = config.parse().unwrap();
+            let mut writer = Writer::new(Cursor::new(Vec::new()),
+                                         LasConfig::decode(&mut toml::Decoder::new(config))
+                                             .unwrap())
+                                 .unwrap();
+            writer.sink(&Point { gps_time: Some(gps_time), ..Default::default() }).unwrap();
+            let bytes = writer.close().unwrap().into_inner();
+            LittleEndian::read_u16(&bytes[6..8])
+        };
+        let version = "version = { major = 1, minor = 2 }\n";
+        assert_eq!(1, write(1e8, version));
+        assert_eq!(0, write(1e5, version));
+        assert_eq!(1, write(1e5, &format!("{}gps_time_type = \"adjusted_standard\"", version)));
+        assert_eq!(0, write(1e8, "version = { major = 1, minor = 1 }"));
+    }
+
+    #[test]
+    fn warnings() {
+        let point = Point {
+            scan_angle: Some(-12.75),
+            scan_direction: ScanDirection::Forward,
+            return_number: Some(1),
+            number_of_returns: Some(1),
+            gps_time: Some(1.0),
+            ..Default::default()
+        };
+        let mut writer = Writer::new(Cursor::new(Vec::new()), LasConfig::default()).unwrap();
+        writer.sink(&point).unwrap();
+        writer.sink(&Point { scan_angle: Some(3.5), ..point.clone() }).unwrap();
+        writer.sink(&Point { scan_angle: Some(3.0), ..point.clone() }).unwrap();
+        writer.sink(&Point::default()).unwrap();
+        assert!(writer.sink(&Point { scan_angle: Some(3.5), classification: 40, ..point.clone() })
+                      .is_err());
+        assert!(writer.sink(&Point { return_number: Some(257), ..point.clone() }).is_err());
+        {
+            let warnings = writer.warnings();
+            let scan_angle = warnings.get("scan_angle truncated to a whole degree").unwrap();
+            assert_eq!(2, scan_angle.count);
+            assert_eq!(Some(0.75), scan_angle.max_error);
+            assert_eq!(1, warnings.get("unknown scan_direction written as forward").unwrap().count);
+            assert_eq!(1, warnings.get("unknown return_number written as 0").unwrap().count);
+            assert!(warnings.get("intensity rescaled to a u16").is_none());
+        }
+
+        let config = LasConfig { strict: Some(true), ..Default::default() };
+        let mut writer = Writer::new(Cursor::new(Vec::new()), config).unwrap();
+        writer.sink(&Point { scan_angle: Some(3.0), ..point.clone() }).unwrap();
+        match writer.sink(&Point { scan_angle: Some(3.5), ..point }) {
+            Err(Error::LossyConversion(ref what)) => {
+                assert_eq!("scan_angle truncated to a whole degree", what)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn missing_extra_dimension() {
+        let mut source = open_file_source("data/1.0_0.las", None).unwrap();
+        let config = toml::Parser::new("extra_dimensions = [\"deviation\"]").parse().unwrap();
+        let mut sink = open_file_sink("missing_extra_dimension.las",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        assert!(sink.sink(&source.source_to_end(1).unwrap()[0]).is_err());
+        remove_file("missing_extra_dimension.las").unwrap();
+    }
+
+    #[test]
+    fn waveforms() {
+        let mut points = Vec::new();
+        for &nsamples in &[10, 5, 10] {
+            points.push(Point {
+                gps_time: Some(1.0),
+                waveform: Some(Waveform {
+                    samples: vec![1; nsamples],
+                    sampling_interval: 1e-9,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+        points.push(Point { gps_time: Some(1.0), ..Default::default() });
+        let config = toml::Parser::new("waveforms = true").parse().unwrap();
+        let mut sink = open_file_sink("waveforms.las", Some(toml::Value::Table(config))).unwrap();
+        for point in &points {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut las = Vec::new();
+        let _ = File::open("waveforms.las").unwrap().read_to_end(&mut las).unwrap();
+        assert_eq!(3, las[25]);
+        assert_eq!(235, LittleEndian::read_u16(&las[94..96]));
+        assert_eq!(3, LittleEndian::read_u32(&las[100..104]));
+        assert_eq!(4, las[104]);
+        assert_eq!(57, LittleEndian::read_u16(&las[105..107]));
+        let offset_to_point_data = LittleEndian::read_u32(&las[96..100]) as usize;
+        assert_eq!(offset_to_point_data + 4 * 57, las.len());
+        assert_eq!(100, LittleEndian::read_u16(&las[235 + 18..235 + 20]));
+        assert_eq!(10, LittleEndian::read_u32(&las[235 + 56..235 + 60]));
+        let third = &las[offset_to_point_data + 2 * 57 + 28..];
+        assert_eq!(1, third[0]);
+        assert_eq!(90, LittleEndian::read_u64(&third[1..9]));
+
+        let mut wdp = Vec::new();
+        let _ = File::open("waveforms.wdp").unwrap().read_to_end(&mut wdp).unwrap();
+        assert_eq!(60 + 50, wdp.len());
+        remove_file("waveforms.las").unwrap();
+        remove_file("waveforms.wdp").unwrap();
+    }
+
+    #[test]
+    fn metadata() {
+        let mut metadata = Metadata::new();
+        metadata.system_identifier = Some("pabst test".to_string());
+        metadata.creation_date = Some(CreationDate {
+            year: 2016,
+            day_of_year: 200,
+        });
+        metadata.gps_time = Some(Standard::AdjustedStandard);
+        metadata.vlrs.push(Vlr::new("LASF_Projection", 2112, b"GEOGCS[]".to_vec()));
+        metadata.vlrs.push(Vlr::new("LASF_Spec", 4, vec![0; 192]));
+        let mut writer = Writer::new(Cursor::new(Vec::new()), Default::default()).unwrap();
+        let mut bad = metadata.clone();
+        bad.vlrs.push(Vlr::new("a user id that's too long for las", 1, Vec::new()));
+        assert!(writer.set_metadata(&bad).is_err());
+        writer.set_metadata(&metadata).unwrap();
+        writer.sink(&Point { gps_time: Some(1e8), ..Default::default() }).unwrap();
+        assert!(writer.set_metadata(&metadata).is_err());
+        let mut cursor = writer.close().unwrap();
+        assert_eq!(WKT | 1, LittleEndian::read_u16(&cursor.get_ref()[6..8]));
+        cursor.set_position(0);
+
+        let source = Reader::new(las::Reader::new(cursor).unwrap()).unwrap();
+        let read = source.metadata().unwrap();
+        assert_eq!(metadata.system_identifier, read.system_identifier);
+        assert_eq!(metadata.creation_date, read.creation_date);
+        assert_eq!(metadata.gps_time, read.gps_time);
+        assert_eq!(vec![&metadata.vlrs[0]], read.crs());
+        assert_eq!(1, read.vlrs.len());
+    }
+
+    #[test]
+    fn waveforms_and_crs() {
+        let mut metadata = Metadata::new();
+        metadata.vlrs.push(Vlr::new("LASF_Projection", 34735, vec![1; 16]));
+        metadata.vlrs.push(Vlr::new("LASF_Projection", 2112, b"GEOGCS[]".to_vec()));
+        let mut writer = Writer::with_wave_packets(Cursor::new(Vec::new()),
+                                                   Default::default(),
+                                                   "waveforms_and_crs.wdp")
+                             .unwrap();
+        writer.set_metadata(&metadata).unwrap();
+        writer.sink(&Point {
+                  gps_time: Some(1.0),
+                  waveform: Some(Waveform { samples: vec![1; 10], ..Default::default() }),
+                  ..Default::default()
+              })
+              .unwrap();
+        let las = writer.close().unwrap().into_inner();
+        remove_file("waveforms_and_crs.wdp").unwrap();
+
+        assert_eq!(EXTERNAL_WAVE_PACKETS | WKT, LittleEndian::read_u16(&las[6..8]));
+        let number_of_vlrs = LittleEndian::read_u32(&las[100..104]);
+        let mut offset = LittleEndian::read_u16(&las[94..96]) as usize;
+        let mut records = Vec::new();
+        for _ in 0..number_of_vlrs {
+            let user_id = read_padded(&las[offset + 2..offset + 18]);
+            let record_id = LittleEndian::read_u16(&las[offset + 18..offset + 20]);
+            records.push((user_id, record_id));
+            offset += 54 + LittleEndian::read_u16(&las[offset + 20..offset + 22]) as usize;
+        }
+        assert_eq!(LittleEndian::read_u32(&las[96..100]) as usize, offset);
+        assert_eq!(("LASF_Spec".to_string(), 100), records[0]);
+        assert!(records.contains(&("LASF_Projection".to_string(), 34735)));
+        assert!(records.contains(&("LASF_Projection".to_string(), 2112)));
+    }
+
+    #[test]
+    fn waveforms_need_a_wdp() {
+        let config = toml::Parser::new("point_format = 4").parse().unwrap();
+        let config = LasConfig::decode(&mut toml::Decoder::new(toml::Value::Table(config)))
+                         .unwrap();
+        assert!(Writer::new(Cursor::new(Vec::new()), config).is_err());
+    }
+
+    #[cfg(feature = "laz")]
+    #[test]
+    fn laz_adjusted_standard_time() {
+        let config = toml::Parser::new(r#"
+        version = { major = 1, minor = 2 }
+        gps_time_type = "adjusted_standard"
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("laz_adjusted_standard_time.laz",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        for &gps_time in &[1e8, 2e8] {
+            sink.sink(&Point { gps_time: Some(gps_time), ..Default::default() }).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut source = open_file_source("laz_adjusted_standard_time.laz", None).unwrap();
+        assert_eq!(Some(Standard::AdjustedStandard), source.metadata().unwrap().gps_time);
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(vec![Some(1e8), Some(2e8)],
+                   points.iter().map(|p| p.gps_time).collect::<Vec<_>>());
+        remove_file("laz_adjusted_standard_time.laz").unwrap();
+    }
+
+    #[cfg(feature = "laz")]
+    #[test]
+    fn source_and_sink_laz() {
+        let mut source = open_file_source("data/1.0_1.las", None).unwrap();
+        let before = source.source_to_end(100).unwrap();
+        let config = toml::Parser::new(r#"
+        scale_factors = { x = 0.01, y = 0.01, z = 0.01 }
+        point_format = 1
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("source_and_sink_laz.laz", Some(toml::Value::Table(config)))
+                           .unwrap();
+        for point in &before {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut source = open_file_source("source_and_sink_laz.laz", None).unwrap();
+        assert_eq!(Some(before.len()), source.source_len());
+        let after = source.source_to_end(100).unwrap();
+        assert_eq!(before.len(), after.len());
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert_eq!((a.x * 100.0).round(), (b.x * 100.0).round());
+            assert_eq!((a.y * 100.0).round(), (b.y * 100.0).round());
+            assert_eq!((a.z * 100.0).round(), (b.z * 100.0).round());
+            assert_eq!(a.gps_time, b.gps_time);
+            assert_eq!(a.intensity.as_u16(), b.intensity.as_u16());
+        }
+        assert!(las::Reader::from_path("source_and_sink_laz.laz").is_err());
+        remove_file("source_and_sink_laz.laz").unwrap();
+    }
+
+    #[cfg(feature = "laz")]
+    #[test]
+    fn laz_source_rejects_las() {
+        use std::fs::File;
+        use laszip::Decompressor;
+        assert!(Decompressor::new(File::open("data/1.0_0.las").unwrap()).is_err());
+    }
 }
@@ -1,6 +1,7 @@
 //! Point sinks.
 
 pub mod las;
+pub mod ply;
 pub mod sdc;
 pub mod text;
 
@@ -11,39 +12,70 @@ use std::path::Path;
 
 use las::Writer as LasWriter;
 use rustc_serialize::Decodable;
+use sdc::Writer as SdcWriter;
 use toml;
 
-use Result;
-use error::Error;
+use {Result, remove_driver};
+use error::{Context, Error};
+use metadata::Metadata;
 use point::Point;
+use schema::{Requirements, Schema};
+use warning::Warnings;
 
 enum SinkType {
     Las,
+    #[cfg(feature = "laz")]
+    Laz,
+    Ply,
+    Sdc,
     Text,
 }
 
 impl SinkType {
-    fn from_osstr_ref<S: AsRef<OsStr>>(s: S) -> Result<SinkType> {
-        match Path::new(&s).extension().and_then(|e| e.to_str()) {
+    fn from_path<S: AsRef<OsStr>>(s: S) -> Result<SinkType> {
+        let extension = Path::new(&s).extension().and_then(|e| e.to_str()).map(|e| {
+            e.to_lowercase()
+        });
+        match extension.as_ref().map(|e| e.as_str()) {
             Some("las") => Ok(SinkType::Las),
+            #[cfg(feature = "laz")]
+            Some("laz") => Ok(SinkType::Laz),
+            Some("ply") => Ok(SinkType::Ply),
+            Some("sdc") => Ok(SinkType::Sdc),
             Some("txt") => Ok(SinkType::Text),
             Some(_) | None => Err(Error::UnregisteredFileExtension(OsStr::new(&s).to_os_string())),
         }
     }
-}
 
-macro_rules! decode_or_default {
-    ($klass:path, $decoder:expr) => {{
-        match $decoder {
-            Some(ref mut decoder) => try!(<$klass as FileSink>::Config::decode(decoder)),
-            None => Default::default()
+    fn from_name(name: &str) -> Result<SinkType> {
+        match name {
+            "las" => Ok(SinkType::Las),
+            #[cfg(feature = "laz")]
+            "laz" => Ok(SinkType::Laz),
+            "ply" => Ok(SinkType::Ply),
+            "sdc" => Ok(SinkType::Sdc),
+            "text" => Ok(SinkType::Text),
+            _ => Err(Error::UnknownFormat(name.to_string())),
         }
-    }}
-}
+    }
 
+    fn name(&self) -> &'static str {
+        match *self {
+            SinkType::Las => "las",
+            #[cfg(feature = "laz")]
+            SinkType::Laz => "laz",
+            SinkType::Ply => "ply",
+            SinkType::Sdc => "sdc",
+            SinkType::Text => "text",
+        }
+    }
+}
 
 /// Opens a file sink with the given options
 ///
+/// The format is picked by the path's extension, ignoring case, unless a `driver` key in the
+/// configuration names it outright.
+///
 /// # Examples
 ///
 /// ```
@@ -54,11 +86,57 @@ macro_rules! decode_or_default {
 /// ```
 pub fn open_file_sink<P>(path: P, config: Option<toml::Value>) -> Result<Box<Sink>>
 where P: AsRef<Path> + AsRef<OsStr>
+{
+    let (driver, config) = try!(remove_driver(config));
+    let sink_type = match driver {
+        Some(ref name) => try!(SinkType::from_name(name)),
+        None => try!(SinkType::from_path(&path)),
+    };
+    open(sink_type, path, config)
+}
+
+/// Opens a file sink with a named format, rather than guessing the format from the extension.
+///
+/// The format names are `las`, `laz`, `ply`, `sdc`, and `text`.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::remove_file;
+/// use pabst::sink::open_file_sink_as;
+/// let sink = open_file_sink_as("temp.points", "text", None).unwrap();
+/// # remove_file("temp.points").unwrap();
+/// ```
+pub fn open_file_sink_as<P>(path: P, format: &str, config: Option<toml::Value>) -> Result<Box<Sink>>
+where P: AsRef<Path> + AsRef<OsStr>
+{
+    let (_, config) = try!(remove_driver(config));
+    open(try!(SinkType::from_name(format)), path, config)
+}
+
+fn open<P>(sink_type: SinkType, path: P, config: Option<toml::Value>) -> Result<Box<Sink>>
+where P: AsRef<Path> + AsRef<OsStr>
+{
+    let context = Context::new(sink_type.name()).path(&path);
+    let sink = try!(open_sink(sink_type, path, config).map_err(|e| e.context(context.clone())));
+    Ok(Box::new(ContextSink {
+        context: context,
+        count: 0,
+        sink: sink,
+    }))
+}
+
+fn open_sink<P>(sink_type: SinkType, path: P, config: Option<toml::Value>) -> Result<Box<Sink>>
+where P: AsRef<Path> + AsRef<OsStr>
 {
     let mut decoder = config.map(|c| toml::Decoder::new(c));
-    match try!(SinkType::from_osstr_ref(&path)) {
-        SinkType::Las =>  LasWriter::<BufWriter<File>>::open_file_sink(path, decode_or_default!(LasWriter<BufWriter<File>>, decoder)),
-        SinkType::Text =>  text::Writer::<BufWriter<File>>::open_file_sink(path, decode_or_default!(text::Writer<BufWriter<File>>, decoder)),
+    match sink_type {
+        SinkType::Las =>  LasWriter::<BufWriter<File>>::open_file_sink(path, decode_or_default!(FileSink, LasWriter<BufWriter<File>>, decoder)),
+        #[cfg(feature = "laz")]
+        SinkType::Laz =>  las::LazWriter::<BufWriter<File>>::open_file_sink(path, decode_or_default!(FileSink, las::LazWriter<BufWriter<File>>, decoder)),
+        SinkType::Ply =>  ply::Writer::<BufWriter<File>>::open_file_sink(path, decode_or_default!(FileSink, ply::Writer<BufWriter<File>>, decoder)),
+        SinkType::Sdc =>  SdcWriter::<BufWriter<File>>::open_file_sink(path, decode_or_default!(FileSink, SdcWriter<BufWriter<File>>, decoder)),
+        SinkType::Text =>  text::Writer::<BufWriter<File>>::open_file_sink(path, decode_or_default!(FileSink, text::Writer<BufWriter<File>>, decoder)),
     }
 }
 
@@ -71,6 +149,29 @@ pub trait Sink {
 
     /// Close a sink, probably writing its points out or something.
     fn close_sink(self: Box<Self>) -> Result<()>;
+
+    /// Returns the values this sink couldn't write exactly, if it keeps track.
+    fn warnings(&self) -> Option<&Warnings> {
+        None
+    }
+
+    /// Returns the dimensions this sink needs and can write, if it knows.
+    fn requirements(&self) -> Option<Requirements> {
+        None
+    }
+
+    /// Tells this sink which dimensions its points will have, before any points arrive.
+    ///
+    /// Sinks that pick their layout on their own, e.g. a las point format, use this to fit the
+    /// points.
+    fn set_schema(&mut self, _: &Schema) {}
+
+    /// Tells this sink where its points came from, before any points arrive.
+    ///
+    /// Sinks write whatever metadata their format can hold, and ignore the rest.
+    fn set_metadata(&mut self, _: &Metadata) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl Sink for Box<Sink> {
@@ -80,6 +181,56 @@ impl Sink for Box<Sink> {
     fn close_sink(self: Box<Self>) -> Result<()> {
         (*self).close_sink()
     }
+    fn warnings(&self) -> Option<&Warnings> {
+        (**self).warnings()
+    }
+    fn requirements(&self) -> Option<Requirements> {
+        (**self).requirements()
+    }
+    fn set_schema(&mut self, schema: &Schema) {
+        (**self).set_schema(schema)
+    }
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        (**self).set_metadata(metadata)
+    }
+}
+
+/// A sink that says where its errors happened.
+struct ContextSink {
+    context: Context,
+    count: usize,
+    sink: Box<Sink>,
+}
+
+impl Sink for ContextSink {
+    fn sink(&mut self, point: &Point) -> Result<()> {
+        // Only points that were written count, so retrying a point doesn't move the index.
+        try!(self.sink.sink(point).map_err(|e| e.context(self.context.clone().index(self.count))));
+        self.count += 1;
+        Ok(())
+    }
+
+    fn close_sink(self: Box<Self>) -> Result<()> {
+        let ContextSink { context, sink, .. } = *self;
+        sink.close_sink().map_err(|e| e.context(context))
+    }
+
+    fn warnings(&self) -> Option<&Warnings> {
+        self.sink.warnings()
+    }
+
+    fn requirements(&self) -> Option<Requirements> {
+        self.sink.requirements()
+    }
+
+    fn set_schema(&mut self, schema: &Schema) {
+        self.sink.set_schema(schema)
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<()> {
+        let context = &self.context;
+        self.sink.set_metadata(metadata).map_err(|e| e.context(context.clone()))
+    }
 }
 
 /// A sink that puts points into a path.
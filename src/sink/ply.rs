@@ -0,0 +1,313 @@
+//! Sink points into the vertices of a PLY file.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use {Error, Result};
+use ply::{DataType, Encoding};
+use point::{Dimension, Point};
+use schema::{Requirements, Schema};
+use sink::{FileSink, Sink};
+
+/// The vertex count is written into a fixed-width, space-padded field in the header and filled
+/// in when the sink is closed.
+const VERTEX_COUNT_WIDTH: usize = 20;
+
+/// A PLY writer.
+#[derive(Debug)]
+pub struct Writer<W: Write + Seek> {
+    count_position: u64,
+    dimensions: Vec<Dimension>,
+    encoding: Encoding,
+    extra_dimensions: Vec<String>,
+    npoints: usize,
+    writer: W,
+}
+
+impl Writer<BufWriter<File>> {
+    /// Opens a writer for a path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::point::Dimension;
+    /// use pabst::sink::ply::Writer;
+    /// let writer = Writer::from_path("/dev/null",
+    ///                                "ascii",
+    ///                                vec![Dimension::X, Dimension::Y, Dimension::Z],
+    ///                                vec![]).unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P,
+                                     encoding: &str,
+                                     dimensions: Vec<Dimension>,
+                                     extra_dimensions: Vec<String>)
+                                     -> Result<Writer<BufWriter<File>>> {
+        // Check the encoding first so a bad one doesn't clobber an existing file.
+        let _ = try!(Encoding::from_str(encoding));
+        Writer::new(BufWriter::new(try!(File::create(path))),
+                    encoding,
+                    dimensions,
+                    extra_dimensions)
+    }
+}
+
+impl<W: Write + Seek> Writer<W> {
+    /// Creates a new writer and writes the PLY header.
+    ///
+    /// The extra dimensions are entries in `Point::extra`, and are written as doubles after the
+    /// other dimensions.
+    pub fn new(mut writer: W,
+               encoding: &str,
+               dimensions: Vec<Dimension>,
+               extra_dimensions: Vec<String>)
+               -> Result<Writer<W>> {
+        let encoding = try!(Encoding::from_str(encoding));
+        try!(write!(writer, "ply\nformat {} 1.0\n", encoding.name()));
+        try!(write!(writer,
+                    "comment generated by pabst {}\n",
+                    env!("CARGO_PKG_VERSION")));
+        try!(write!(writer, "element vertex "));
+        let count_position = try!(writer.seek(SeekFrom::Current(0)));
+        try!(write!(writer, "{:<1$}\n", 0, VERTEX_COUNT_WIDTH));
+        for &dimension in &dimensions {
+            try!(write!(writer,
+                        "property {} {}\n",
+                        DataType::for_dimension(dimension).name(),
+                        dimension));
+        }
+        for name in &extra_dimensions {
+            try!(write!(writer, "property {} {}\n", DataType::Double.name(), name));
+        }
+        try!(write!(writer, "end_header\n"));
+        Ok(Writer {
+            count_position: count_position,
+            dimensions: dimensions,
+            encoding: encoding,
+            extra_dimensions: extra_dimensions,
+            npoints: 0,
+            writer: writer,
+        })
+    }
+
+    fn data_types(&self) -> Vec<DataType> {
+        self.dimensions
+            .iter()
+            .map(|&d| DataType::for_dimension(d))
+            .chain(self.extra_dimensions.iter().map(|_| DataType::Double))
+            .collect()
+    }
+
+    fn write_binary<B: ByteOrder>(&mut self, values: &[f64]) -> Result<()> {
+        for (data_type, &value) in self.data_types().into_iter().zip(values) {
+            try!(data_type.write::<_, B>(&mut self.writer, value));
+        }
+        Ok(())
+    }
+
+    fn write_ascii(&mut self, values: &[f64]) -> Result<()> {
+        for (i, (data_type, &value)) in self.data_types().into_iter().zip(values).enumerate() {
+            if i > 0 {
+                try!(write!(self.writer, " "));
+            }
+            if data_type.is_float() {
+                try!(write!(self.writer, "{}", value));
+            } else {
+                try!(write!(self.writer, "{}", value as i64));
+            }
+        }
+        try!(write!(self.writer, "\n"));
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Sink for Writer<W> {
+    fn sink(&mut self, point: &Point) -> Result<()> {
+        let mut values = Vec::with_capacity(self.dimensions.len() + self.extra_dimensions.len());
+        for &dimension in &self.dimensions {
+            match point.get(dimension) {
+                Some(value) => {
+                    let data_type = DataType::for_dimension(dimension);
+                    values.push(data_type.from_dimension(dimension, value.as_f64()));
+                }
+                None => return Err(Error::MissingDimension(dimension.to_string())),
+            }
+        }
+        for name in &self.extra_dimensions {
+            match point.extra.get(name) {
+                Some(value) => values.push(value.as_f64()),
+                None => return Err(Error::MissingDimension(name.clone())),
+            }
+        }
+        match self.encoding {
+            Encoding::Ascii => try!(self.write_ascii(&values)),
+            Encoding::BinaryLittleEndian => try!(self.write_binary::<LittleEndian>(&values)),
+            Encoding::BinaryBigEndian => try!(self.write_binary::<BigEndian>(&values)),
+        }
+        self.npoints += 1;
+        Ok(())
+    }
+
+    fn close_sink(self: Box<Self>) -> Result<()> {
+        let mut writer = *self;
+        let _ = try!(writer.writer.seek(SeekFrom::Start(writer.count_position)));
+        try!(write!(writer.writer, "{:<1$}", writer.npoints, VERTEX_COUNT_WIDTH));
+        try!(writer.writer.flush());
+        Ok(())
+    }
+
+    fn requirements(&self) -> Option<Requirements> {
+        let mut required = Schema::new();
+        for dimension in self.dimensions.iter().filter(|d| d.is_optional()) {
+            required.insert(dimension.name());
+        }
+        for name in &self.extra_dimensions {
+            required.insert(name.clone());
+        }
+        Some(Requirements::new(required, Schema::from_dimensions(&self.dimensions)))
+    }
+}
+
+impl<W: Write + Seek> FileSink for Writer<W> {
+    type Config = PlyConfig;
+
+    fn open_file_sink<P: AsRef<Path>>(path: P, config: PlyConfig) -> Result<Box<Sink>> {
+        let dimensions = match config.dimensions {
+            Some(names) => {
+                let mut dimensions = Vec::with_capacity(names.len());
+                for name in &names {
+                    dimensions.push(try!(name.parse()));
+                }
+                dimensions
+            }
+            None => vec![Dimension::X, Dimension::Y, Dimension::Z],
+        };
+        let encoding = config.encoding.unwrap_or("binary_little_endian".to_string());
+        Ok(Box::new(try!(Writer::from_path(path,
+                                           &encoding,
+                                           dimensions,
+                                           config.extra_dimensions.unwrap_or(Vec::new())))))
+    }
+}
+
+/// Decodable configuration object.
+///
+/// `encoding` is one of `ascii`, `binary_little_endian` (the default), or `binary_big_endian`.
+/// `dimensions` are the names of the `Point` fields to write as vertex properties, and default to
+/// x, y, and z; colors are written as eight-bit `uchar`s. `extra_dimensions` are the names of
+/// entries in `Point::extra` to write as double properties after the others.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct PlyConfig {
+    encoding: Option<String>,
+    dimensions: Option<Vec<String>>,
+    extra_dimensions: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, remove_file};
+    use std::io::Read;
+    use std::path::Path;
+
+    use toml;
+
+    use point::{Color, Point, Value};
+    use sink::open_file_sink;
+    use source::open_file_source;
+
+    fn round_trip(path: &str, encoding: &str) {
+        let mut source = open_file_source("data/4-points.sdc", None).unwrap();
+        let mut before = source.source_to_end(100).unwrap();
+        for (i, point) in before.iter_mut().enumerate() {
+            let _ = point.extra.insert("deviation".to_string(), Value::F64(i as f64 + 0.5));
+        }
+        let config = toml::Parser::new(&format!(r#"
+        encoding = "{}"
+        dimensions = ["x", "y", "z", "intensity", "range", "rg_index", "facet_number",
+                      "return_number", "high_channel"]
+        extra_dimensions = ["deviation"]
+        "#,
+                                                encoding))
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink(path, Some(toml::Value::Table(config))).unwrap();
+        for point in &before {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+
+        let mut source = open_file_source(path, None).unwrap();
+        assert_eq!(Some(4), source.source_len());
+        let after = source.source_to_end(100).unwrap();
+        assert_eq!(4, after.len());
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert_eq!(a.x, b.x);
+            assert_eq!(a.y, b.y);
+            assert_eq!(a.z, b.z);
+            assert_eq!(a.intensity.as_u16(), b.intensity.as_u16());
+            assert_eq!(a.range, b.range);
+            assert_eq!(a.rg_index, b.rg_index);
+            assert_eq!(a.facet_number, b.facet_number);
+            assert_eq!(a.return_number, b.return_number);
+            assert_eq!(a.high_channel, b.high_channel);
+            assert_eq!(a.extra.get("deviation"), b.extra.get("deviation"));
+        }
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ascii() {
+        round_trip("ply_ascii.ply", "ascii");
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        round_trip("ply_binary_little_endian.ply", "binary_little_endian");
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        round_trip("ply_binary_big_endian.ply", "binary_big_endian");
+    }
+
+    #[test]
+    fn uchar_colors() {
+        let point = Point {
+            color: Some(Color {
+                red: 65535,
+                green: 32896,
+                blue: 0,
+            }),
+            ..Default::default()
+        };
+        let config = toml::Parser::new(r#"
+        encoding = "ascii"
+        dimensions = ["red", "green", "blue"]
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("ply_uchar_colors.ply", Some(toml::Value::Table(config)))
+                           .unwrap();
+        sink.sink(&point).unwrap();
+        sink.close_sink().unwrap();
+
+        let mut ply = String::new();
+        let _ = File::open("ply_uchar_colors.ply").unwrap().read_to_string(&mut ply).unwrap();
+        assert!(ply.contains("property uchar red\n"));
+        assert!(ply.ends_with("end_header\n255 128 0\n"));
+        let mut source = open_file_source("ply_uchar_colors.ply", None).unwrap();
+        assert_eq!(point.color, source.source_to_end(10).unwrap()[0].color);
+        remove_file("ply_uchar_colors.ply").unwrap();
+    }
+
+    #[test]
+    fn bad_encoding() {
+        let path = Path::new("target/debug/bad_encoding.ply");
+        let _ = remove_file(path);
+        let config = toml::Parser::new("encoding = \"utf8\"").parse().unwrap();
+        assert!(open_file_sink(path, Some(toml::Value::Table(config))).is_err());
+        assert!(!path.exists());
+    }
+}
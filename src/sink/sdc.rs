@@ -1,17 +1,112 @@
 //! Implement sink for .sdc.
 
+use std::{cmp, u16, u8};
 use std::io::Write;
+use std::path::Path;
 
 use sdc;
 
 use Result;
 use error::Error;
-use point::Point;
-use sink::Sink;
+use point::{Dimension, Point};
+use schema::{Requirements, Schema};
+use sink::{FileSink, Sink};
+use warning::Warnings;
+
+const DIMENSIONS: [Dimension; 15] = [Dimension::X,
+                                     Dimension::Y,
+                                     Dimension::Z,
+                                     Dimension::Intensity,
+                                     Dimension::ReturnNumber,
+                                     Dimension::NumberOfReturns,
+                                     Dimension::GpsTime,
+                                     Dimension::Range,
+                                     Dimension::ScanAngle,
+                                     Dimension::Width,
+                                     Dimension::RgIndex,
+                                     Dimension::TargetType,
+                                     Dimension::FacetNumber,
+                                     Dimension::HighChannel,
+                                     Dimension::Classification];
+
+/// An sdc writer that knows how to fill in the dimensions sdc requires.
+///
+/// Sdc points must have a time, a range, and a scan angle, but not all pabst points have those.
+/// This wrapper uses an `SdcConfig` to decide what to do when they are missing.
+///
+/// Sdc coordinates are single precision, so the precision lost writing them, along with any
+/// intensities that had to be rescaled, is counted in the writer's `Warnings`.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    config: SdcConfig,
+    warnings: Warnings,
+    writer: sdc::Writer<W>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Wraps an sdc writer with the given configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::remove_file;
+    /// extern crate sdc;
+    /// # extern crate pabst;
+    /// use pabst::sink::sdc::{SdcConfig, Writer};
+    /// # fn main() {
+    /// let writer = Writer::new(sdc::Writer::from_path("sdc_writer_new.sdc").unwrap(),
+    ///                          SdcConfig::default());
+    /// # remove_file("sdc_writer_new.sdc").unwrap();
+    /// # }
+    /// ```
+    pub fn new(writer: sdc::Writer<W>, config: SdcConfig) -> Writer<W> {
+        Writer {
+            config: config,
+            warnings: Warnings::new(config.strict.unwrap_or(false)),
+            writer: writer,
+        }
+    }
+
+    /// Returns the values that couldn't be written exactly, so far.
+    pub fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    fn sink(&mut self, point: &Point) -> Result<()> {
+        try!(self.writer.write_point(&try!(from_point(point, &self.config, &mut self.warnings))));
+        Ok(())
+    }
+
+    fn close_sink(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+
+    fn warnings(&self) -> Option<&Warnings> {
+        Some(&self.warnings)
+    }
+
+    fn requirements(&self) -> Option<Requirements> {
+        let mut required = Schema::new();
+        if self.config.default_gps_time.is_none() {
+            required.insert(Dimension::GpsTime.name());
+        }
+        if self.config.default_scan_angle.is_none() {
+            required.insert(Dimension::ScanAngle.name());
+        }
+        if !self.config.synthesize_range.unwrap_or(true) {
+            required.insert(Dimension::Range.name());
+        }
+        Some(Requirements::new(required, Schema::from_dimensions(&DIMENSIONS)))
+    }
+}
 
 impl<W: Write> Sink for sdc::Writer<W> {
     fn sink(&mut self, point: &Point) -> Result<()> {
-        try!(self.write_point(&try!(from_point(point))));
+        try!(self.write_point(&try!(from_point(point,
+                                               &Default::default(),
+                                               &mut Warnings::new(false)))));
         Ok(())
     }
 
@@ -20,24 +115,74 @@ impl<W: Write> Sink for sdc::Writer<W> {
     }
 }
 
-fn from_point(point: &Point) -> Result<sdc::Point> {
+impl<W: Write> FileSink for sdc::Writer<W> {
+    type Config = SdcConfig;
+
+    fn open_file_sink<P: AsRef<Path>>(path: P, config: SdcConfig) -> Result<Box<Sink>> {
+        if let Some(v) = config.version {
+            if v.major != 5 || v.minor != 0 {
+                return Err(Error::Configuration(format!("sdc sink can only write version 5.0, \
+                                                         not {}.{}",
+                                                        v.major,
+                                                        v.minor)));
+            }
+        }
+        Ok(Box::new(Writer::new(try!(sdc::Writer::from_path(path)), config)))
+    }
+}
+
+fn from_point(point: &Point, config: &SdcConfig, warnings: &mut Warnings) -> Result<sdc::Point> {
+    let range = match point.range {
+        Some(range) => range,
+        None => {
+            if config.synthesize_range.unwrap_or(true) {
+                (point.x.powi(2) + point.y.powi(2) + point.z.powi(2)).sqrt()
+            } else {
+                return Err(Error::MissingDimension("range".to_string()));
+            }
+        }
+    };
+    let time = try!(point.gps_time
+                         .or(config.default_gps_time)
+                         .ok_or(Error::MissingDimension("gps_time".to_string())));
+    let theta = try!(point.scan_angle
+                          .or(config.default_scan_angle)
+                          .ok_or(Error::MissingDimension("scan_angle".to_string())));
+    let target_type = try!(sdc::TargetType::from_u8(point.target_type
+                                                         .or(config.target_type)
+                                                         .unwrap_or(3)));
+
+    // Only count warnings once the point is sure to be written.
+    let (x, y, z) = (point.x as f32, point.y as f32, point.z as f32);
+    let error = (x as f64 - point.x)
+                    .abs()
+                    .max((y as f64 - point.y).abs())
+                    .max((z as f64 - point.z).abs());
+    try!(warnings.warn_error("coordinates rounded to single precision", error));
+    try!(warnings.warn_error("intensity rescaled to a u16", point.intensity.u16_error()));
+    let width = point.width.unwrap_or(0.0);
+    let truncated_width = width.max(0.0).min(u16::MAX as f64).trunc();
+    try!(warnings.warn_error("width truncated to a u16", width - truncated_width));
+    let target = point.return_number.unwrap_or(0);
+    let clamped_target = cmp::min(target, u8::MAX as usize);
+    try!(warnings.warn_error("return_number clamped to a u8", (target - clamped_target) as f64));
+    let num_target = point.number_of_returns.unwrap_or(0);
+    let clamped_num_target = cmp::min(num_target, u8::MAX as usize);
+    try!(warnings.warn_error("number_of_returns clamped to a u8",
+                             (num_target - clamped_num_target) as f64));
     Ok(sdc::Point {
-        time: try!(point.gps_time.ok_or(Error::MissingDimension("time".to_string()))),
-        range: point.range.unwrap_or_else(|| {
-            (point.x.powi(2) + point.y.powi(2) + point.z.powi(2)).sqrt()
-        }) as f32,
-        theta: try!(point.scan_angle
-                         .ok_or(Error::MissingDimension("scan angle"
-                                                            .to_string()))) as f32,
-        x: point.x as f32,
-        y: point.y as f32,
-        z: point.z as f32,
+        time: time,
+        range: range as f32,
+        theta: theta as f32,
+        x: x,
+        y: y,
+        z: z,
         amplitude: point.intensity.as_u16(),
-        width: point.width.unwrap_or(0.0) as u16,
-        target: point.return_number.unwrap_or(0) as u8,
-        num_target: point.number_of_returns.unwrap_or(0) as u8,
+        width: truncated_width as u16,
+        target: clamped_target as u8,
+        num_target: clamped_num_target as u8,
         rg_index: point.rg_index.unwrap_or(0.0) as u16,
-        target_type: try!(sdc::TargetType::from_u8(point.target_type.unwrap_or(3))),
+        target_type: target_type,
         facet_number: point.facet_number.unwrap_or(0),
         high_channel: point.high_channel.unwrap_or(false),
         class_id: Some(point.classification),
@@ -46,28 +191,156 @@ fn from_point(point: &Point) -> Result<sdc::Point> {
     })
 }
 
+/// Decodable configuration for an sdc sink.
+///
+/// If `strict` is true, coordinates and intensities that sdc can't hold exactly are errors,
+/// rather than warnings.
+#[derive(Clone, Copy, Debug, Default, RustcDecodable)]
+pub struct SdcConfig {
+    version: Option<Version>,
+    synthesize_range: Option<bool>,
+    target_type: Option<u8>,
+    default_gps_time: Option<f64>,
+    default_scan_angle: Option<f64>,
+    strict: Option<bool>,
+}
+
+/// The sdc format version.
+///
+/// The underlying writer only knows how to write 5.0, so that's the only version we accept.
+#[derive(Clone, Copy, Debug, RustcDecodable)]
+pub struct Version {
+    major: u16,
+    minor: u16,
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs::remove_file;
 
-    use las;
+    use rustc_serialize::Decodable;
     use sdc;
+    use toml;
 
-    use source::Source;
-    use sink::Sink;
+    use point::Point;
+    use super::*;
+    use source::{open_file_source, Source};
+    use source::las::Reader;
+    use source::sdc::Reader as SdcReader;
+    use sink::{open_file_sink, Sink};
+
+    fn round_trip(path: &str, config: &str) -> (Vec<Point>, Vec<Point>) {
+        let mut source = open_file_source("data/4-points.sdc", None).unwrap();
+        let before = source.source_to_end(100).unwrap();
+        let config = toml::Parser::new(config).parse().unwrap();
+        let mut sink = open_file_sink(path, Some(toml::Value::Table(config))).unwrap();
+        for point in &before {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+        let after = SdcReader::from_path(path).unwrap().source_to_end(100).unwrap();
+        remove_file(path).unwrap();
+        (before, after)
+    }
 
     #[test]
     fn sink_las() {
-        let mut source = las::Reader::from_path("data/1.0_1.las").unwrap();
+        let mut source = Reader::from_path("data/1.0_1.las").unwrap();
         {
             let mut sink = sdc::Writer::from_path("temp.sdc").unwrap();
             for point in &source.source_to_end(100).unwrap() {
                 sink.sink(point).unwrap();
             }
         }
-        let mut source = sdc::Reader::from_path("temp.sdc").unwrap();
+        let mut source = SdcReader::from_path("temp.sdc").unwrap();
         let points = source.source_to_end(100).unwrap();
         assert_eq!(1, points.len());
         remove_file("temp.sdc").unwrap();
     }
+
+    #[test]
+    fn round_trip_sdc() {
+        let (before, after) = round_trip("round_trip_sdc.sdc", "");
+        assert_eq!(4, after.len());
+        for (a, b) in before.iter().zip(after.iter()) {
+            assert_eq!(a.x, b.x);
+            assert_eq!(a.y, b.y);
+            assert_eq!(a.z, b.z);
+            assert_eq!(a.gps_time, b.gps_time);
+            assert_eq!(a.range, b.range);
+            assert_eq!(a.scan_angle, b.scan_angle);
+            assert_eq!(a.intensity.as_u16(), b.intensity.as_u16());
+            assert_eq!(a.return_number, b.return_number);
+            assert_eq!(a.number_of_returns, b.number_of_returns);
+            assert_eq!(a.facet_number, b.facet_number);
+        }
+    }
+
+    #[test]
+    fn round_trip_sdc_with_config() {
+        let (_, after) = round_trip("round_trip_sdc_with_config.sdc",
+                                    r#"
+        version = { major = 5, minor = 0 }
+        synthesize_range = false
+        target_type = 2
+        "#);
+        assert_eq!(4, after.len());
+    }
+
+    #[test]
+    fn warnings() {
+        let point = Point {
+            x: 0.1,
+            gps_time: Some(1.0),
+            scan_angle: Some(0.0),
+            ..Default::default()
+        };
+        let mut writer = Writer::new(sdc::Writer::new(Vec::new()).unwrap(), Default::default());
+        writer.sink(&point).unwrap();
+        writer.sink(&Point { x: 0.5, ..point.clone() }).unwrap();
+        let warning = *writer.warnings().get("coordinates rounded to single precision").unwrap();
+        assert_eq!(1, warning.count);
+        assert_eq!(Some((0.1f32 as f64 - 0.1).abs()), warning.max_error);
+        assert!(writer.sink(&Point { x: 0.1, scan_angle: None, ..point.clone() }).is_err());
+        let warning = *writer.warnings().get("coordinates rounded to single precision").unwrap();
+        assert_eq!(1, warning.count);
+        writer.sink(&Point { width: Some(70000.5), return_number: Some(300), ..point.clone() })
+              .unwrap();
+        let warning = *writer.warnings().get("width truncated to a u16").unwrap();
+        assert_eq!(Some(70000.5 - 65535.0), warning.max_error);
+        assert!(writer.warnings().get("return_number clamped to a u8").is_some());
+        assert!(writer.warnings().get("number_of_returns clamped to a u8").is_none());
+
+        let config: toml::Value = "strict = true".parse().unwrap();
+        let config = SdcConfig::decode(&mut toml::Decoder::new(config)).unwrap();
+        let mut writer = Writer::new(sdc::Writer::new(Vec::new()).unwrap(), config);
+        writer.sink(&Point { x: 0.5, ..point.clone() }).unwrap();
+        assert!(writer.sink(&point).is_err());
+    }
+
+    #[test]
+    fn bad_version() {
+        let config = toml::Parser::new("version = { major = 4, minor = 0 }").parse().unwrap();
+        assert!(open_file_sink("bad_version.sdc", Some(toml::Value::Table(config))).is_err());
+    }
+
+    #[test]
+    fn missing_dimensions() {
+        let mut source = open_file_source("data/1.0_0.las", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        let config = toml::Parser::new(r#"
+        default_gps_time = 0.0
+        synthesize_range = false
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("missing_dimensions.sdc",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        let err = sink.sink(&points[0]).unwrap_err();
+        assert_eq!("sdc missing_dimensions.sdc, point 0: Missing dimension: range",
+                   err.to_string());
+        sink.close_sink().unwrap();
+        remove_file("missing_dimensions.sdc").unwrap();
+    }
 }
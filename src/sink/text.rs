@@ -5,38 +5,45 @@ use std::io::{BufWriter, Write};
 use std::path::Path;
 
 use {Error, Result};
-use point::Point;
+use point::{Dimension, Point};
+use schema::{Requirements, Schema};
 use sink::{FileSink, Sink};
 
 /// A very dumb text writer.
 #[derive(Debug)]
 pub struct Writer<W: Write> {
-    dimensions: Vec<String>,
+    dimensions: Vec<Dimension>,
+    extra_dimensions: Vec<String>,
     writer: W,
 }
 
 impl Writer<BufWriter<File>> {
     /// Opens a writer for a path.
     ///
+    /// The extra dimensions are columns from `Point::extra`, written after the other dimensions.
+    ///
     /// # Examples
     ///
     /// ```
+    /// use pabst::point::Dimension;
     /// use pabst::sink::text::Writer;
-    /// let writer = Writer::from_path("/dev/null", vec!["x".to_string(), "y".to_string(), "z".to_string()]).unwrap();
+    /// let writer = Writer::from_path("/dev/null",
+    ///                                vec![Dimension::X, Dimension::Y, Dimension::Z],
+    ///                                vec![]).unwrap();
     /// ```
     pub fn from_path<P: AsRef<Path>>(path: P,
-                                     dimensions: Vec<String>)
+                                     dimensions: Vec<Dimension>,
+                                     extra_dimensions: Vec<String>)
                                      -> Result<Writer<BufWriter<File>>> {
         let mut writer = BufWriter::new(try!(File::create(path)));
-        for (i, dimension) in dimensions.iter().enumerate() {
-            try!(write!(writer, "{}", dimension));
-            if i < dimensions.len() - 1 {
-                try!(write!(writer, " "));
-            }
-        }
-        try!(write!(writer, "\n"));
+        let names = dimensions.iter()
+                              .map(|d| d.name())
+                              .chain(extra_dimensions.iter().map(|s| &s[..]))
+                              .collect::<Vec<_>>();
+        try!(write!(writer, "{}\n", names.join(" ")));
         Ok(Writer {
             dimensions: dimensions,
+            extra_dimensions: extra_dimensions,
             writer: writer,
         })
     }
@@ -44,50 +51,37 @@ impl Writer<BufWriter<File>> {
 
 impl<W: Write> Sink for Writer<W> {
     fn sink(&mut self, point: &Point) -> Result<()> {
-        for (i, name) in self.dimensions.iter().enumerate() {
-            match name.as_ref() {
-                "x" => try!(write!(self.writer, "{}", point.x)),
-                "y" => try!(write!(self.writer, "{}", point.y)),
-                "z" => try!(write!(self.writer, "{}", point.z)),
-                "intensity" => try!(write!(self.writer, "{}", point.intensity.as_u16())),
-                "range" => {
-                    if let Some(range) = point.range {
-                        try!(write!(self.writer, "{}", range));
-                    } else {
-                        return Err(Error::MissingDimension("Point does not have range".to_string()));
-                    }
-                }
-                "scan_angle" => {
-                    if let Some(scan_angle) = point.scan_angle {
-                        try!(write!(self.writer, "{}", scan_angle));
-                    } else {
-                        return Err(Error::MissingDimension("Point does not have scan angle".to_string()));
-                    }
-                }
-                "gps_time" => {
-                    if let Some(time) = point.gps_time {
-                        try!(write!(self.writer, "{}", time));
-                    } else {
-                        return Err(Error::MissingDimension("Point does not have gps time".to_string()));
-                    }
-                }
-                _ => {
-                    return Err(Error::MissingDimension(format!("Text writer doesn't know how to \
-                                                                write dimension '{}'",
-                                                               name)))
-                }
-            };
-            if i < self.dimensions.len() - 1 {
-                try!(write!(self.writer, " "));
+        let mut values = Vec::with_capacity(self.dimensions.len() + self.extra_dimensions.len());
+        for &dimension in &self.dimensions {
+            match point.get(dimension) {
+                Some(value) => values.push(value.to_string()),
+                None => return Err(Error::MissingDimension(dimension.to_string())),
             }
         }
-        try!(write!(self.writer, "\n"));
+        for name in &self.extra_dimensions {
+            match point.extra.get(name) {
+                Some(value) => values.push(value.to_string()),
+                None => return Err(Error::MissingDimension(name.clone())),
+            }
+        }
+        try!(write!(self.writer, "{}\n", values.join(" ")));
         Ok(())
     }
 
     fn close_sink(self: Box<Self>) -> Result<()> {
         Ok(())
     }
+
+    fn requirements(&self) -> Option<Requirements> {
+        let mut required = Schema::new();
+        for dimension in self.dimensions.iter().filter(|d| d.is_optional()) {
+            required.insert(dimension.name());
+        }
+        for name in &self.extra_dimensions {
+            required.insert(name.clone());
+        }
+        Some(Requirements::new(required, Schema::from_dimensions(&self.dimensions)))
+    }
 }
 
 impl<W: Write> FileSink for Writer<W> {
@@ -95,24 +89,42 @@ impl<W: Write> FileSink for Writer<W> {
     fn open_file_sink<P>(path: P, config: TextConfig) -> Result<Box<Sink>>
         where P: AsRef<Path>
     {
-        Ok(Box::new(try!(Writer::from_path(path, config.dimensions))))
+        let mut dimensions = Vec::with_capacity(config.dimensions.len());
+        for name in &config.dimensions {
+            dimensions.push(try!(name.parse()));
+        }
+        Ok(Box::new(try!(Writer::from_path(path,
+                                           dimensions,
+                                           config.extra_dimensions.unwrap_or(Vec::new())))))
     }
 }
 
 /// A simple configuration object.
+///
+/// `extra_dimensions` are the names of entries in `Point::extra` to write after `dimensions`.
 #[derive(Debug, RustcDecodable)]
 pub struct TextConfig {
     dimensions: Vec<String>,
+    extra_dimensions: Option<Vec<String>>,
 }
 
 impl Default for TextConfig {
     fn default() -> TextConfig {
-        TextConfig { dimensions: vec!["x".to_string(), "y".to_string(), "z".to_string()] }
+        TextConfig {
+            dimensions: vec!["x".to_string(), "y".to_string(), "z".to_string()],
+            extra_dimensions: None,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use toml;
+
+    use point::{Point, Value};
     use sink::open_file_sink;
     use source::open_file_source;
 
@@ -125,4 +137,64 @@ mod tests {
         }
         sink.close_sink().unwrap();
     }
+
+    #[test]
+    fn every_dimension() {
+        let mut source = open_file_source("data/4-points.sdc", None).unwrap();
+        let config = toml::Parser::new(r#"
+        dimensions = ["x", "y", "z", "return_number", "classification", "rg_index",
+                      "facet_number", "high_channel", "withheld", "gps_time"]
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("target/debug/every_dimension.txt",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        for ref point in source.source_to_end(1000).unwrap() {
+            sink.sink(point).unwrap();
+        }
+        sink.close_sink().unwrap();
+    }
+
+    #[test]
+    fn unknown_dimension() {
+        let config = toml::Parser::new("dimensions = [\"x\", \"wat\"]").parse().unwrap();
+        assert!(open_file_sink("target/debug/unknown_dimension.txt",
+                               Some(toml::Value::Table(config)))
+                    .is_err());
+    }
+
+    #[test]
+    fn missing_dimension() {
+        let mut source = open_file_source("data/1.0_0.las", None).unwrap();
+        let config = toml::Parser::new("dimensions = [\"x\", \"range\"]").parse().unwrap();
+        let mut sink = open_file_sink("target/debug/missing_dimension.txt",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        assert!(sink.sink(&source.source_to_end(1).unwrap()[0]).is_err());
+    }
+
+    #[test]
+    fn extra_dimensions() {
+        let mut point = Point::default();
+        let _ = point.extra.insert("deviation".to_string(), Value::U16(12));
+        let config = toml::Parser::new(r#"
+        dimensions = ["x"]
+        extra_dimensions = ["deviation"]
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut sink = open_file_sink("target/debug/extra_dimensions.txt",
+                                      Some(toml::Value::Table(config)))
+                           .unwrap();
+        sink.sink(&point).unwrap();
+        assert!(sink.sink(&Point::default()).is_err());
+        sink.close_sink().unwrap();
+        let mut contents = String::new();
+        let _ = File::open("target/debug/extra_dimensions.txt")
+                    .unwrap()
+                    .read_to_string(&mut contents)
+                    .unwrap();
+        assert_eq!("x deviation\n0 12\n", contents);
+    }
 }
@@ -1,45 +1,220 @@
 //! Source las points.
 
-use std::io::{Read, Seek};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
 use std::path::Path;
 
+use byteorder::{ByteOrder, LittleEndian};
 use las;
 
-use Result;
-use point::{Intensity, Point, ScanDirection};
+use {Error, Result};
+use extra_bytes::ExtraBytes;
+use gps_time::Standard;
+use las_header::read_padded;
+#[cfg(feature = "laz")]
+use laszip::Decompressor;
+use metadata::{CreationDate, Metadata, Vlr};
+use point::{Color, Dimension, Intensity, Point, ScanDirection};
+use schema::Schema;
 use source::{FileSource, Source};
 
-impl<R: Read + Seek> Source for las::Reader<R> {
+/// The dimensions that every las point format has.
+const DIMENSIONS: [Dimension; 15] = [Dimension::X,
+                                     Dimension::Y,
+                                     Dimension::Z,
+                                     Dimension::Intensity,
+                                     Dimension::ReturnNumber,
+                                     Dimension::NumberOfReturns,
+                                     Dimension::ScanDirection,
+                                     Dimension::EdgeOfFlightLine,
+                                     Dimension::Classification,
+                                     Dimension::Synthetic,
+                                     Dimension::KeyPoint,
+                                     Dimension::Withheld,
+                                     Dimension::ScanAngle,
+                                     Dimension::UserData,
+                                     Dimension::PointSourceId];
+
+/// Returns the dimensions held by a las point format.
+///
+/// Extra bytes aren't part of the point format, so they aren't included.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::source::las::point_format_schema;
+/// assert!(!point_format_schema(0).contains("gps_time"));
+/// assert!(point_format_schema(3).contains("red"));
+/// ```
+pub fn point_format_schema(point_format: u8) -> Schema {
+    let mut schema = Schema::from_dimensions(&DIMENSIONS);
+    let point_format = las::PointFormat(point_format);
+    if point_format.has_time() {
+        schema.insert(Dimension::GpsTime.name());
+    }
+    if point_format.has_color() {
+        for dimension in &[Dimension::Red, Dimension::Green, Dimension::Blue] {
+            schema.insert(dimension.name());
+        }
+    }
+    schema
+}
+
+/// A las reader that decodes each point's extra bytes.
+#[derive(Debug)]
+pub struct Reader<R: Read + Seek> {
+    error: Option<Error>,
+    extra_bytes: Vec<ExtraBytes>,
+    next: u32,
+    reader: las::Reader<R>,
+}
+
+impl Reader<BufReader<File>> {
+    /// Opens a las reader for a path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::source::las::Reader;
+    /// let reader = Reader::from_path("data/1.0_0.las").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<BufReader<File>>> {
+        Reader::new(try!(las::Reader::from_path(path)))
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Wraps a las reader, reading the extra bytes descriptions from its VLRs.
+    pub fn new(reader: las::Reader<R>) -> Result<Reader<R>> {
+        Ok(Reader {
+            error: None,
+            extra_bytes: try!(ExtraBytes::from_vlrs(reader.vlrs())),
+            next: 0,
+            reader: reader,
+        })
+    }
+
+    /// Returns the las header.
+    pub fn header(&self) -> &las::Header {
+        self.reader.header()
+    }
+}
+
+impl<R: Read + Seek> Source for Reader<R> {
+    /// Reads points and decodes their extra bytes.
+    ///
+    /// If a point can't be read, the points before it are returned, and the error is returned by
+    /// the next call. Reading can carry on after the error, starting with the next point record.
     fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         let mut points = Vec::with_capacity(want);
         for _ in 0..want {
-            match try!(self.read_point()) {
-                Some(point) => points.push(Point::from(point)),
-                None => {
+            match self.reader.read_point() {
+                Ok(Some(mut point)) => {
+                    self.next += 1;
+                    let bytes = point.extra_bytes.take();
+                    let mut point = Point::from(point);
+                    if let Some(bytes) = bytes {
+                        ExtraBytes::decode(&self.extra_bytes, &bytes, &mut point);
+                    }
+                    points.push(point);
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // The reader stops partway through a bad record, so we seek to the next one.
+                    self.next += 1;
+                    let err = match self.reader.seek(self.next) {
+                        Ok(()) => Error::from(err),
+                        Err(seek_err) => Error::from(seek_err),
+                    };
                     if points.is_empty() {
-                        return Ok(None);
-                    } else {
-                        return Ok(Some(points));
+                        return Err(err);
                     }
+                    self.error = Some(err);
+                    break;
                 }
             }
         }
-        Ok(Some(points))
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
+    }
+
+    fn resumable(&self) -> bool {
+        true
     }
 
     fn source_len(&mut self) -> Option<usize> {
-        Some(self.npoints() as usize)
+        Some(self.reader.npoints() as usize)
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        let mut schema = point_format_schema(self.reader.header().point_data_format.0);
+        for extra in &self.extra_bytes {
+            if let Some(name) = extra.dimension() {
+                schema.insert(name);
+            }
+        }
+        Some(schema)
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        Some(header_metadata(self.reader.header(), self.reader.vlrs()))
+    }
+}
+
+/// Returns the metadata held in a las header and its variable length records.
+fn header_metadata(header: &las::Header, vlrs: &[las::Vlr]) -> Metadata {
+    let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+    let mut metadata = Metadata::new();
+    metadata.system_identifier = non_empty(read_padded(&header.system_identifier));
+    metadata.generating_software = non_empty(read_padded(&header.generating_software));
+    if header.file_creation_year != 0 {
+        metadata.creation_date = Some(CreationDate {
+            year: header.file_creation_year,
+            day_of_year: header.file_creation_day_of_year,
+        });
+    }
+    metadata.file_source_id = Some(header.file_source_id);
+    let mut guid = [0; 16];
+    LittleEndian::write_u32(&mut guid[0..4], header.guid_data_1);
+    LittleEndian::write_u16(&mut guid[4..6], header.guid_data_2);
+    LittleEndian::write_u16(&mut guid[6..8], header.guid_data_3);
+    guid[8..].copy_from_slice(&header.guid_data_4);
+    metadata.guid = Some(guid);
+    if header.point_data_format.has_time() && header.version.has_gps_time_type() {
+        metadata.gps_time = Some(match header.gps_time_type {
+            las::GpsTimeType::Week => Standard::WeekSeconds,
+            las::GpsTimeType::AdjustedStandard => Standard::AdjustedStandard,
+        });
+    }
+    metadata.vlrs = vlrs.iter().map(Vlr::from).collect();
+    let _ = metadata.properties.insert("las_version".to_string(),
+                                       format!("{}.{}", header.version.major, header.version.minor));
+    metadata
+}
+
+impl<R: Read + Seek> FileSource for Reader<R> {
+    type Config = LasConfig;
+    fn open_file_source<P>(path: P, _: LasConfig) -> Result<Box<Source>> where P: AsRef<Path> {
+        Ok(Box::new(try!(Reader::from_path(path))))
     }
 }
 
-impl<R: Read + Seek> FileSource for las::Reader<R> {
+#[cfg(feature = "laz")]
+impl<R: Read + Seek + Send + 'static> FileSource for Decompressor<R> {
     type Config = LasConfig;
     fn open_file_source<P>(path: P, _: LasConfig) -> Result<Box<Source>> where P: AsRef<Path> {
-        Ok(Box::new(try!(las::Reader::from_path(path))))
+        let decompressor = try!(Decompressor::new(BufReader::new(try!(File::open(path)))));
+        Ok(Box::new(try!(Reader::new(try!(las::Reader::new(decompressor))))))
     }
 }
 
-/// Decodable configuration object.
+/// Decodable configuration object, shared by las and laz sources.
 #[derive(Clone, Copy, Debug, Default, RustcDecodable)]
 pub struct LasConfig;
 
@@ -65,7 +240,53 @@ impl From<las::Point> for Point {
             scan_angle: Some(point.scan_angle_rank as f64),
             point_source_id: Some(point.point_source_id),
             user_data: Some(point.user_data),
+            color: match (point.red, point.green, point.blue) {
+                (Some(red), Some(green), Some(blue)) => {
+                    Some(Color {
+                        red: red,
+                        green: green,
+                        blue: blue,
+                    })
+                }
+                _ => None,
+            },
             ..Default::default()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LittleEndian};
+    use las;
+
+    use source::Source;
+    use super::*;
+
+    #[test]
+    fn no_gps_time_type_before_1_2() {
+        let reader = Reader::from_path("data/1.0_1.las").unwrap();
+        assert!(reader.header().point_data_format.has_time());
+        assert_eq!(None, reader.metadata().unwrap().gps_time);
+    }
+
+    #[test]
+    fn skip_bad_record() {
+        let mut writer = las::Writer::new(Cursor::new(Vec::new())).open().unwrap();
+        for x in 1..4 {
+            let point = las::Point { x: x as f64, ..las::Point::new() };
+            writer.write_point(&point).unwrap();
+        }
+        let mut bytes = writer.close().unwrap().into_inner().into_inner();
+        let offset = LittleEndian::read_u32(&bytes[96..100]) as usize;
+        bytes[offset + 20 + 14] = 7;
+        let mut reader = Reader::new(las::Reader::new(Cursor::new(bytes)).unwrap()).unwrap();
+        assert!(reader.resumable());
+        assert_eq!(1.0, reader.source(10).unwrap().unwrap()[0].x);
+        assert!(reader.source(10).is_err());
+        assert_eq!(3.0, reader.source(10).unwrap().unwrap()[0].x);
+        assert!(reader.source(10).unwrap().is_none());
+    }
+}
@@ -3,56 +3,173 @@
 //! These don't necessarily have to be file format readers, but they usually are.
 
 pub mod las;
+pub mod ply;
 pub mod sdc;
+pub mod text;
 #[cfg(feature = "rxp-source")]
 pub mod rxp;
+#[cfg(feature = "sdf-source")]
+pub mod sdf;
 
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
-use las::Reader as LasReader;
-#[cfg(feature = "rxp-source")]
-use rivlib::Stream as RxpStream;
+use byteorder::{ByteOrder, LittleEndian};
+#[cfg(feature = "laz")]
+use laszip::Decompressor as LazDecompressor;
 use rustc_serialize::Decodable;
-use sdc::Reader as SdcReader;
+use source::las::Reader as LasReader;
+use source::sdc::Reader as SdcReader;
 use toml;
 
-use Result;
-use error::Error;
+use {Result, remove_driver};
+use error::{Context, Error};
+use metadata::Metadata;
 use point::Point;
+use schema::Schema;
+
+const SNIFF_LENGTH: u64 = 128;
+#[cfg(feature = "laz")]
+const LAS_POINT_FORMAT_OFFSET: usize = 104;
+const RXP_SIGNATURE: &'static [u8] = b"RieglLMS";
+const RXP_SIGNATURE_OFFSET: usize = 8;
+const SDC_HEADER_SIZE: u32 = 8;
+const SDC_MAJOR_VERSION: u16 = 5;
 
 enum SourceType {
     Las,
+    #[cfg(feature = "laz")]
+    Laz,
+    Ply,
     #[cfg(feature = "rxp-source")]
     Rxp,
     Sdc,
+    #[cfg(feature = "sdf-source")]
+    Sdf,
+    Text,
 }
 
 impl SourceType {
-    fn from_osstr_ref<S: AsRef<OsStr>>(s: S) -> Result<SourceType> {
-        match Path::new(&s).extension().and_then(|e| e.to_str()) {
+    /// Picks a source type by looking at the start of the file, and then at its extension.
+    fn from_path<S: AsRef<OsStr>>(s: S) -> Result<SourceType> {
+        if let Some(source_type) = SourceType::sniff(Path::new(&s)) {
+            return Ok(source_type);
+        }
+        let extension = Path::new(&s).extension().and_then(|e| e.to_str()).map(|e| {
+            e.to_lowercase()
+        });
+        match extension.as_ref().map(|e| e.as_str()) {
             Some("las") => Ok(SourceType::Las),
+            #[cfg(feature = "laz")]
+            Some("laz") => Ok(SourceType::Laz),
+            Some("ply") => Ok(SourceType::Ply),
             #[cfg(feature = "rxp-source")]
             Some("rxp") => Ok(SourceType::Rxp),
             Some("sdc") => Ok(SourceType::Sdc),
+            #[cfg(feature = "sdf-source")]
+            Some("sdf") => Ok(SourceType::Sdf),
+            Some("txt") | Some("csv") | Some("xyz") => Ok(SourceType::Text),
             Some(_) | None => Err(Error::UnregisteredFileExtension(OsStr::new(&s).to_os_string())),
         }
     }
-}
 
-macro_rules! decode_or_default {
-    ($klass:path, $decoder:expr) => {{
-        match $decoder {
-            Some(ref mut decoder) => try!(<$klass as FileSource>::Config::decode(decoder)),
-            None => Default::default()
+    /// Looks for the magic bytes of binary formats.
+    ///
+    /// Files that can't be read, or that we don't recognize, return `None` so the extension gets
+    /// a say. Text and sdf files don't have anything to look for.
+    fn sniff(path: &Path) -> Option<SourceType> {
+        let mut bytes = Vec::new();
+        match File::open(path) {
+            Ok(file) => {
+                if file.take(SNIFF_LENGTH).read_to_end(&mut bytes).is_err() {
+                    return None;
+                }
+            }
+            Err(_) => return None,
+        }
+        if bytes.starts_with(b"LASF") {
+            return Some(las_or_laz(&bytes));
         }
-    }}
+        if bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n") {
+            return Some(SourceType::Ply);
+        }
+        if bytes.len() >= RXP_SIGNATURE_OFFSET + RXP_SIGNATURE.len() &&
+           &bytes[RXP_SIGNATURE_OFFSET..RXP_SIGNATURE_OFFSET + RXP_SIGNATURE.len()] ==
+           RXP_SIGNATURE {
+            return rxp();
+        }
+        if bytes.len() >= 8 && LittleEndian::read_u32(&bytes[0..4]) == SDC_HEADER_SIZE &&
+           LittleEndian::read_u16(&bytes[4..6]) == SDC_MAJOR_VERSION {
+            return Some(SourceType::Sdc);
+        }
+        None
+    }
+
+    fn from_name(name: &str) -> Result<SourceType> {
+        match name {
+            "las" => Ok(SourceType::Las),
+            #[cfg(feature = "laz")]
+            "laz" => Ok(SourceType::Laz),
+            "ply" => Ok(SourceType::Ply),
+            #[cfg(feature = "rxp-source")]
+            "rxp" => Ok(SourceType::Rxp),
+            "sdc" => Ok(SourceType::Sdc),
+            #[cfg(feature = "sdf-source")]
+            "sdf" => Ok(SourceType::Sdf),
+            "text" => Ok(SourceType::Text),
+            _ => Err(Error::UnknownFormat(name.to_string())),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            SourceType::Las => "las",
+            #[cfg(feature = "laz")]
+            SourceType::Laz => "laz",
+            SourceType::Ply => "ply",
+            #[cfg(feature = "rxp-source")]
+            SourceType::Rxp => "rxp",
+            SourceType::Sdc => "sdc",
+            #[cfg(feature = "sdf-source")]
+            SourceType::Sdf => "sdf",
+            SourceType::Text => "text",
+        }
+    }
+}
+
+#[cfg(feature = "laz")]
+fn las_or_laz(bytes: &[u8]) -> SourceType {
+    // LASzip sets the top bits of the point format of compressed files.
+    if bytes.len() > LAS_POINT_FORMAT_OFFSET && bytes[LAS_POINT_FORMAT_OFFSET] & 0xc0 != 0 {
+        SourceType::Laz
+    } else {
+        SourceType::Las
+    }
+}
+
+#[cfg(not(feature = "laz"))]
+fn las_or_laz(_: &[u8]) -> SourceType {
+    SourceType::Las
+}
+
+#[cfg(feature = "rxp-source")]
+fn rxp() -> Option<SourceType> {
+    Some(SourceType::Rxp)
+}
+
+#[cfg(not(feature = "rxp-source"))]
+fn rxp() -> Option<SourceType> {
+    None
 }
 
 /// Opens a file source with the given options.
 ///
+/// The format is picked by looking for the magic bytes of las, laz, ply, rxp, and sdc files, and
+/// then by the path's extension, ignoring case. A `driver` key in the configuration names the
+/// format outright.
+///
 /// # Examples
 ///
 /// ```
@@ -60,16 +177,72 @@ macro_rules! decode_or_default {
 /// let source = open_file_source("data/1.0_0.las", None).unwrap();
 /// ```
 pub fn open_file_source<P>(path: P, config: Option<toml::Value>) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr>
+{
+    let (driver, config) = try!(remove_driver(config));
+    let source_type = match driver {
+        Some(ref name) => try!(SourceType::from_name(name)),
+        None => try!(SourceType::from_path(&path)),
+    };
+    open(source_type, path, config)
+}
+
+/// Opens a file source with a named format, rather than guessing the format from the extension.
+///
+/// The format names are the ones returned by `file_format`.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::source::open_file_source_as;
+/// let source = open_file_source_as("data/1.0_0.las", "las", None).unwrap();
+/// assert!(open_file_source_as("data/1.0_0.las", "not-a-format", None).is_err());
+/// ```
+pub fn open_file_source_as<P>(path: P, format: &str, config: Option<toml::Value>) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr>
+{
+    let (_, config) = try!(remove_driver(config));
+    open(try!(SourceType::from_name(format)), path, config)
+}
+
+fn open<P>(source_type: SourceType, path: P, config: Option<toml::Value>) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr>
+{
+    let context = Context::new(source_type.name()).path(&path);
+    let source = try!(open_source(source_type, path, config).map_err(|e| e.context(context.clone())));
+    Ok(Box::new(ContextSource {
+        context: context,
+        count: 0,
+        source: source,
+    }))
+}
+
+fn open_source<P>(source_type: SourceType, path: P, config: Option<toml::Value>) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr>
 {
     let mut decoder = config.map(|c| toml::Decoder::new(c));
-    match try!(SourceType::from_osstr_ref(&path)) {
-        SourceType::Las => LasReader::<BufReader<File>>::open_file_source(path, decode_or_default!(LasReader<BufReader<File>>, decoder)),
+    match source_type {
+        SourceType::Las => LasReader::<BufReader<File>>::open_file_source(path, decode_or_default!(FileSource, LasReader<BufReader<File>>, decoder)),
+        #[cfg(feature = "laz")]
+        SourceType::Laz => LazDecompressor::<BufReader<File>>::open_file_source(path, decode_or_default!(FileSource, LazDecompressor<BufReader<File>>, decoder)),
+        SourceType::Ply => ply::Reader::<BufReader<File>>::open_file_source(path, decode_or_default!(FileSource, ply::Reader<BufReader<File>>, decoder)),
         #[cfg(feature = "rxp-source")]
-        SourceType::Rxp => RxpStream::open_file_source(path, decode_or_default!(RxpStream, decoder)),
-        SourceType::Sdc => SdcReader::<BufReader<File>>::open_file_source(path, decode_or_default!(SdcReader<BufReader<File>>, decoder)),
+        SourceType::Rxp => rxp::Reader::open_file_source(path, decode_or_default!(FileSource, rxp::Reader, decoder)),
+        SourceType::Sdc => SdcReader::<BufReader<File>>::open_file_source(path, decode_or_default!(FileSource, SdcReader<BufReader<File>>, decoder)),
+        #[cfg(feature = "sdf-source")]
+        SourceType::Sdf => sdf::Reader::open_file_source(path, decode_or_default!(FileSource, sdf::Reader, decoder)),
+        SourceType::Text => text::Reader::<BufReader<File>>::open_file_source(path, decode_or_default!(FileSource, text::Reader<BufReader<File>>, decoder)),
     }
 }
 
+/// Returns the name of the format that `open_file_source` would use for a path.
+///
+/// # Examples
+///
+/// ```
+/// use pabst::source::file_format;
+/// assert_eq!("text", file_format("points.csv").unwrap());
+/// ```
+pub fn file_format<P: AsRef<OsStr>>(path: P) -> Result<&'static str> {
+    SourceType::from_path(path).map(|s| s.name())
+}
+
 /// A point source.
 pub trait Source {
     /// Sources some points from the `Source`.
@@ -100,6 +273,27 @@ pub trait Source {
     ///
     /// Sources that cannot know their point count should return `None`.
     fn source_len(&mut self) -> Option<usize>;
+
+    /// Returns true if `source` can be called again after it returns an error.
+    ///
+    /// A resumable source skips whatever failed and carries on with the points after it. Other
+    /// sources might return garbage after an error, so their errors should stop the read.
+    fn resumable(&self) -> bool {
+        false
+    }
+
+    /// Returns the dimensions that this source's points carry, if the source knows.
+    ///
+    /// The fixed dimensions that every `Point` has, e.g. `x`, are only listed if the source
+    /// actually fills them in.
+    fn schema(&self) -> Option<Schema> {
+        None
+    }
+
+    /// Returns information about where this source's points came from, if it has any.
+    fn metadata(&self) -> Option<Metadata> {
+        None
+    }
 }
 
 impl Source for Box<Source> {
@@ -110,6 +304,62 @@ impl Source for Box<Source> {
     fn source_len(&mut self) -> Option<usize> {
         (**self).source_len()
     }
+
+    fn resumable(&self) -> bool {
+        (**self).resumable()
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        (**self).schema()
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        (**self).metadata()
+    }
+}
+
+/// A source that says where its errors happened.
+struct ContextSource {
+    context: Context,
+    count: usize,
+    source: Box<Source>,
+}
+
+impl Source for ContextSource {
+    fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        match self.source.source(want) {
+            Ok(Some(points)) => {
+                self.count += points.len();
+                Ok(Some(points))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                // Sources return the good points before an error, so the count is the index of the
+                // point that failed, and a resumable source has skipped past it.
+                let context = self.context.clone().index(self.count);
+                if self.source.resumable() {
+                    self.count += 1;
+                }
+                Err(err.context(context))
+            }
+        }
+    }
+
+    fn source_len(&mut self) -> Option<usize> {
+        self.source.source_len()
+    }
+
+    fn resumable(&self) -> bool {
+        self.source.resumable()
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        self.source.schema()
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        self.source.metadata()
+    }
 }
 
 /// A point source that can be opened from a path.
@@ -120,3 +370,79 @@ pub trait FileSource {
     /// Opens a file source with the given config.
     fn open_file_source<P>(path: P, config: Self::Config) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{OpenOptions, copy, remove_file};
+    use std::io::Write;
+
+    use toml;
+
+    use super::*;
+
+    #[test]
+    fn sniff() {
+        let _ = copy("data/1.0_0.las", "sniff-las").unwrap();
+        let _ = copy("data/4-points.sdc", "sniff-sdc.txt").unwrap();
+        let _ = copy("data/4-points.xyz", "sniff-text.XYZ").unwrap();
+        assert_eq!("las", file_format("sniff-las").unwrap());
+        assert_eq!("sdc", file_format("sniff-sdc.txt").unwrap());
+        assert_eq!("text", file_format("sniff-text.XYZ").unwrap());
+        assert_eq!(1, open_file_source("sniff-las", None).unwrap().source_to_end(10).unwrap().len());
+        remove_file("sniff-las").unwrap();
+        remove_file("sniff-sdc.txt").unwrap();
+        remove_file("sniff-text.XYZ").unwrap();
+    }
+
+    #[test]
+    fn context() {
+        let err = open_file_source("data/not-a-file.las", None).err().unwrap();
+        assert_eq!(Some("data/not-a-file.las".to_string()),
+                   match err {
+                       Error::Context(context, _) => context.path,
+                       _ => None,
+                   });
+        let _ = copy("data/4-points.xyz", "context.xyz").unwrap();
+        let mut file = OpenOptions::new().append(true).open("context.xyz").unwrap();
+        file.write_all(b"1 2 wat\n1 2 3\n1 2 wat\n").unwrap();
+        let mut source = open_file_source("context.xyz", None).unwrap();
+        assert_eq!(3, source.source(3).unwrap().unwrap().len());
+        assert_eq!(1, source.source(3).unwrap().unwrap().len());
+        for &index in &[4, 6] {
+            let err = source.source(3).err().unwrap();
+            match err {
+                Error::Context(context, _) => {
+                    assert_eq!("text", context.name);
+                    assert_eq!(Some(index), context.index);
+                }
+                _ => panic!("{} has no context", err),
+            }
+            let _ = source.source(3);
+        }
+        remove_file("context.xyz").unwrap();
+    }
+
+    #[test]
+    fn schema() {
+        let schema = open_file_source("data/1.0_0.las", None).unwrap().schema().unwrap();
+        assert!(schema.contains("scan_angle"));
+        assert!(!schema.contains("gps_time"));
+        let schema = open_file_source("data/4-points.sdc", None).unwrap().schema().unwrap();
+        assert!(schema.contains("range"));
+        let schema = open_file_source("data/4-points.csv", None).unwrap().schema().unwrap();
+        assert_eq!(vec!["gps_time", "intensity", "x", "y", "z"],
+                   schema.iter().map(|s| s.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn driver() {
+        let _ = copy("data/4-points.xyz", "driver.points").unwrap();
+        assert!(open_file_source("driver.points", None).is_err());
+        let config: toml::Value = "driver = \"text\"".parse().unwrap();
+        let mut source = open_file_source("driver.points", Some(config)).unwrap();
+        assert_eq!(4, source.source_to_end(10).unwrap().len());
+        let config: toml::Value = "driver = \"wat\"".parse().unwrap();
+        assert!(open_file_source("driver.points", Some(config)).is_err());
+        remove_file("driver.points").unwrap();
+    }
+}
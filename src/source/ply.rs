@@ -0,0 +1,382 @@
+//! Source points from the vertices of a PLY file.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use {Error, Result};
+use ply::{DataType, Encoding};
+use point::{Dimension, Point, Value};
+use schema::Schema;
+use source::{FileSource, Source};
+
+/// A PLY reader.
+///
+/// Only the vertex element is turned into points. Vertex properties that don't match a
+/// `Dimension` name are put in the point's extra dimensions, and any elements that come after the
+/// vertices are skipped.
+/// Eight-bit `uchar` colors are scaled up to fill the sixteen-bit range.
+#[derive(Debug)]
+pub struct Reader<R: BufRead> {
+    encoding: Encoding,
+    error: Option<Error>,
+    line: String,
+    npoints: usize,
+    properties: Vec<Property>,
+    reader: R,
+    remaining: usize,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug)]
+struct Property {
+    dimension: Option<Dimension>,
+    kind: Kind,
+    name: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Scalar(DataType),
+    List(DataType, DataType),
+}
+
+impl Reader<BufReader<File>> {
+    /// Opens a PLY reader for a path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::source::ply::Reader;
+    /// let reader = Reader::from_path("data/4-points.ply").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<BufReader<File>>> {
+        Reader::new(BufReader::new(try!(File::open(path))))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a new PLY reader, reading the header and skipping to the first vertex.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::source::ply::Reader;
+    /// let ply = "ply\nformat ascii 1.0\nelement vertex 0\nproperty float x\nend_header\n";
+    /// let reader = Reader::new(Cursor::new(ply)).unwrap();
+    /// ```
+    pub fn new(reader: R) -> Result<Reader<R>> {
+        let mut reader = Reader {
+            encoding: Encoding::Ascii,
+            error: None,
+            line: String::new(),
+            npoints: 0,
+            properties: Vec::new(),
+            reader: reader,
+            remaining: 0,
+        };
+        if try!(reader.read_header_line()) != "ply" {
+            return Err(Error::Ply("missing ply magic number".to_string()));
+        }
+        let mut elements: Vec<Element> = Vec::new();
+        loop {
+            let line = try!(reader.read_header_line());
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.first().map(|s| *s) {
+                Some("format") if words.len() == 3 => {
+                    reader.encoding = try!(Encoding::from_str(words[1]));
+                }
+                Some("element") if words.len() == 3 => {
+                    elements.push(Element {
+                        name: words[1].to_string(),
+                        count: try!(words[2].parse()),
+                        properties: Vec::new(),
+                    });
+                }
+                Some("property") => {
+                    let element = try!(elements.last_mut().ok_or_else(|| {
+                        Error::Ply("property before any element".to_string())
+                    }));
+                    let (kind, name) = match (words.get(1).map(|s| *s), words.len()) {
+                        (Some("list"), 5) => {
+                            (Kind::List(try!(DataType::from_str(words[2])),
+                                        try!(DataType::from_str(words[3]))),
+                             words[4])
+                        }
+                        (Some(data_type), 3) => {
+                            (Kind::Scalar(try!(DataType::from_str(data_type))), words[2])
+                        }
+                        _ => return Err(Error::Ply(format!("invalid property line: {}", line))),
+                    };
+                    element.properties.push(Property {
+                        dimension: name.parse().ok(),
+                        kind: kind,
+                        name: name.to_string(),
+                    });
+                }
+                Some("comment") | Some("obj_info") | None => {}
+                Some("end_header") => break,
+                _ => return Err(Error::Ply(format!("invalid header line: {}", line))),
+            }
+        }
+        for element in elements {
+            if element.name == "vertex" {
+                reader.npoints = element.count;
+                reader.remaining = element.count;
+                reader.properties = element.properties;
+                return Ok(reader);
+            }
+            for _ in 0..element.count {
+                try!(reader.read_element(&element.properties));
+            }
+        }
+        Err(Error::Ply("no vertex element".to_string()))
+    }
+
+    fn read_header_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if try!(self.reader.read_line(&mut line)) == 0 {
+            return Err(Error::Ply("unexpected end of header".to_string()));
+        }
+        Ok(line.trim().to_string())
+    }
+
+    fn read_element(&mut self, properties: &[Property]) -> Result<Vec<f64>> {
+        match self.encoding {
+            Encoding::Ascii => self.read_ascii_element(properties),
+            Encoding::BinaryLittleEndian => self.read_binary_element::<LittleEndian>(properties),
+            Encoding::BinaryBigEndian => self.read_binary_element::<BigEndian>(properties),
+        }
+    }
+
+    fn read_ascii_element(&mut self, properties: &[Property]) -> Result<Vec<f64>> {
+        self.line.clear();
+        if try!(self.reader.read_line(&mut self.line)) == 0 {
+            return Err(Error::Ply("unexpected end of file".to_string()));
+        }
+        let mut words = self.line.split_whitespace();
+        let mut values = Vec::with_capacity(properties.len());
+        for property in properties {
+            match property.kind {
+                Kind::Scalar(_) => {
+                    let word = try!(words.next().ok_or_else(|| {
+                        Error::Ply("too few values in ascii element".to_string())
+                    }));
+                    values.push(try!(word.parse()));
+                }
+                Kind::List(_, _) => {
+                    let word = try!(words.next().ok_or_else(|| {
+                        Error::Ply("too few values in ascii element".to_string())
+                    }));
+                    for _ in 0..try!(word.parse::<usize>()) {
+                        let _ = words.next();
+                    }
+                    values.push(0.0);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    fn read_binary_element<B: ByteOrder>(&mut self, properties: &[Property]) -> Result<Vec<f64>> {
+        let mut values = Vec::with_capacity(properties.len());
+        for property in properties {
+            match property.kind {
+                Kind::Scalar(data_type) => {
+                    values.push(try!(data_type.read::<_, B>(&mut self.reader)))
+                }
+                Kind::List(count_type, data_type) => {
+                    for _ in 0..try!(count_type.read::<_, B>(&mut self.reader)) as usize {
+                        let _ = try!(data_type.read::<_, B>(&mut self.reader));
+                    }
+                    values.push(0.0);
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl<R: BufRead> Source for Reader<R> {
+    /// Reads vertices as points.
+    ///
+    /// If a vertex can't be read, the points before it are returned, and the error is returned by
+    /// the next call. Reading an ascii file can carry on after the error, starting with the next
+    /// vertex, but a binary vertex that can't be read leaves us at an unknown place in the file,
+    /// so binary sources aren't resumable.
+    fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let properties = ::std::mem::replace(&mut self.properties, Vec::new());
+        let mut points = Vec::with_capacity(want);
+        let mut result = Ok(());
+        while points.len() < want && self.remaining > 0 {
+            self.remaining -= 1;
+            match self.read_element(&properties) {
+                Ok(values) => {
+                    let mut point = Point::default();
+                    for (property, value) in properties.iter().zip(values) {
+                        match (property.dimension, property.kind) {
+                            (Some(dimension), Kind::Scalar(data_type)) => {
+                                let value = data_type.to_dimension(dimension, value);
+                                point.set(dimension, Value::F64(value));
+                            }
+                            (Some(dimension), Kind::List(_, _)) => {
+                                point.set(dimension, Value::F64(value))
+                            }
+                            (None, Kind::Scalar(_)) => {
+                                let _ = point.extra.insert(property.name.clone(),
+                                                           Value::F64(value));
+                            }
+                            (None, Kind::List(_, _)) => {}
+                        }
+                    }
+                    points.push(point);
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.properties = properties;
+        if let Err(err) = result {
+            if points.is_empty() {
+                return Err(err);
+            }
+            self.error = Some(err);
+        }
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
+    }
+
+    fn source_len(&mut self) -> Option<usize> {
+        Some(self.npoints)
+    }
+
+    fn resumable(&self) -> bool {
+        self.encoding == Encoding::Ascii
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        let mut schema = Schema::new();
+        for property in &self.properties {
+            match (property.dimension, property.kind) {
+                (Some(dimension), _) => schema.insert(dimension.name()),
+                (None, Kind::Scalar(_)) => schema.insert(property.name.clone()),
+                (None, Kind::List(_, _)) => {}
+            }
+        }
+        Some(schema)
+    }
+}
+
+impl<R: BufRead> FileSource for Reader<R> {
+    type Config = PlyConfig;
+
+    fn open_file_source<P>(path: P, _: PlyConfig) -> Result<Box<Source>>
+        where P: AsRef<Path> + AsRef<OsStr>
+    {
+        Ok(Box::new(try!(Reader::from_path(path))))
+    }
+}
+
+/// Decodable configuration object.
+#[derive(Clone, Copy, Debug, Default, RustcDecodable)]
+pub struct PlyConfig;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use las;
+
+    use point::Value;
+    use sink::Sink;
+    use sink::las::Writer as LasWriter;
+    use source::{open_file_source, Source};
+    use source::las::Reader as LasReader;
+    use super::*;
+
+    #[test]
+    fn ascii() {
+        let mut source = open_file_source("data/4-points.ply", None).unwrap();
+        assert_eq!(Some(4), source.source_len());
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(4, points.len());
+        assert_eq!(4.0, points[3].x);
+        assert_eq!(Some(4.5), points[3].range);
+        assert_eq!(Some(2), points[3].facet_number);
+        assert_eq!(Some(&Value::F64(0.0)), points[3].extra.get("nx"));
+    }
+
+    #[test]
+    fn skip_elements_before_vertex() {
+        let ply = "ply\nformat ascii 1.0\nelement face 1\nproperty list uchar int vertex_index\n\
+                   element vertex 1\nproperty float x\nend_header\n3 0 1 2\n42\n";
+        let mut reader = Reader::new(Cursor::new(ply)).unwrap();
+        let points = reader.source_to_end(10).unwrap();
+        assert_eq!(1, points.len());
+        assert_eq!(42.0, points[0].x);
+    }
+
+    #[test]
+    fn uchar_colors_to_las() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                   property uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n\
+                   1 255 128 0\n";
+        let mut reader = Reader::new(Cursor::new(ply)).unwrap();
+        let points = reader.source_to_end(10).unwrap();
+        let mut writer = LasWriter::new(Cursor::new(Vec::new()), Default::default()).unwrap();
+        writer.set_schema(&reader.schema().unwrap());
+        writer.sink(&points[0]).unwrap();
+        let mut cursor = writer.close().unwrap();
+        cursor.set_position(0);
+        let mut reader = LasReader::new(las::Reader::new(cursor).unwrap()).unwrap();
+        let color = reader.source_to_end(10).unwrap()[0].color.unwrap();
+        assert_eq!((65535, 32896, 0), (color.red, color.green, color.blue));
+    }
+
+    #[test]
+    fn skip_bad_vertex() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nend_header\n\
+                   1\nfoo\n3\n";
+        let mut reader = Reader::new(Cursor::new(ply)).unwrap();
+        assert_eq!(1, reader.source(10).unwrap().unwrap().len());
+        assert!(reader.source(10).is_err());
+        assert_eq!(3.0, reader.source(10).unwrap().unwrap()[0].x);
+        assert!(reader.source(10).unwrap().is_none());
+        assert!(reader.resumable());
+    }
+
+    #[test]
+    fn binary_is_not_resumable() {
+        let mut ply = b"ply\nformat binary_little_endian 1.0\nelement vertex 2\nproperty float x\n\
+                        end_header\n"
+                          .to_vec();
+        ply.extend_from_slice(&[0, 0, 128, 63, 0, 0]);
+        let mut reader = Reader::new(Cursor::new(ply)).unwrap();
+        assert!(!reader.resumable());
+        assert_eq!(1.0, reader.source(10).unwrap().unwrap()[0].x);
+        assert!(reader.source(10).is_err());
+    }
+
+    #[test]
+    fn not_ply() {
+        assert!(Reader::new(Cursor::new("las\n")).is_err());
+    }
+}
@@ -2,84 +2,327 @@
 //!
 //! `rxp` is a data layout format from Riegl.
 
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::path::Path;
 
 use rivlib;
+use rivlib::stream::{EchoType, Facet};
 
 use error::Error;
-use point::{Intensity, Point};
+use gps_time::Standard;
+use metadata::Metadata;
+use point::{Dimension, Intensity, Point, Value};
 use Result;
+use schema::Schema;
 use source::{FileSource, Source};
 
-impl Source for rivlib::Stream {
+const DEVIATION: &'static str = "deviation";
+const TARGET_INDEX: &'static str = "target_index";
+
+const DIMENSIONS: [Dimension; 8] = [Dimension::X,
+                                    Dimension::Y,
+                                    Dimension::Z,
+                                    Dimension::Intensity,
+                                    Dimension::ReturnNumber,
+                                    Dimension::NumberOfReturns,
+                                    Dimension::Range,
+                                    Dimension::FacetNumber];
+
+/// An rxp reader that numbers the echoes of each shot.
+///
+/// Riegl only tells us whether an echo is the first, an interior, or the last of its shot, so we
+/// hold on to a shot's echoes until its last echo shows up. That might not be in the same chunk.
+#[derive(Debug)]
+pub struct Reader {
+    error: Option<Error>,
+    options: Options,
+    shot: Shot,
+    stream: rivlib::Stream,
+}
+
+impl Reader {
+    /// Opens an rxp file or stream.
+    pub fn open(uri: &str, config: RxpConfig) -> Result<Reader> {
+        Ok(Reader {
+            error: None,
+            options: try!(Options::from_config(&config)),
+            shot: Shot::default(),
+            stream: try!(rivlib::Stream::open(uri, config.sync_to_pps.unwrap_or(true))),
+        })
+    }
+}
+
+impl Source for Reader {
+    /// Reads points, shot by shot.
+    ///
+    /// If the stream can't be read, the finished shots before the failure are returned, and the
+    /// error is returned by the next call. Reading can't carry on after an error.
     fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
-        self.read(want as u32)
-            .map_err(|e| Error::from(e))
-            .map(|o| o.map(|v| v.into_iter().map(|p| Point::from(p)).collect()))
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let mut points = Vec::with_capacity(want);
+        while points.len() < want {
+            match self.stream.read(want as u32) {
+                Ok(Some(echoes)) => {
+                    for echo in echoes {
+                        self.shot.push(echo.echo_type, self.options.point(&echo), &mut points);
+                    }
+                }
+                Ok(None) => {
+                    self.shot.finish(&mut points);
+                    break;
+                }
+                Err(err) => {
+                    if points.is_empty() {
+                        return Err(Error::from(err));
+                    }
+                    self.error = Some(Error::from(err));
+                    break;
+                }
+            }
+        }
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
     }
 
     fn source_len(&mut self) -> Option<usize> {
         None
     }
+
+    /// GPS time is only listed if the stream is synced to the PPS.
+    fn schema(&self) -> Option<Schema> {
+        let mut schema = Schema::from_dimensions(&DIMENSIONS).with(DEVIATION).with(TARGET_INDEX);
+        if self.stream.sync_to_pps() {
+            schema.insert(Dimension::GpsTime.name());
+        }
+        Some(schema)
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        let mut metadata = Metadata::new();
+        if self.stream.sync_to_pps() {
+            metadata.gps_time = Some(Standard::WeekSeconds);
+        }
+        Some(metadata)
+    }
 }
 
-impl From<rivlib::Point> for Point {
-    fn from(point: rivlib::Point) -> Point {
-        Point {
-            x: point.x as f64,
-            y: point.y as f64,
-            z: point.z as f64,
-            intensity: Intensity::new(point.reflectance as f64, -50.0, 50.0),
-            return_number: match point.echo_type {
-                rivlib::stream::EchoType::Single | rivlib::stream::EchoType::First => Some(1),
-                _ => None,
-            },
-            number_of_returns: match point.echo_type {
-                rivlib::stream::EchoType::Single => Some(1),
-                _ => None,
-            },
-            gps_time: if point.pps {
-                Some(point.time as f64 * 1e-9)
+/// The echoes of the shot we're in the middle of.
+///
+/// Echoes that were dropped at read time are kept as `None`, so they still count when the rest
+/// are numbered.
+#[derive(Debug, Default)]
+struct Shot {
+    echoes: Vec<Option<Point>>,
+}
+
+impl Shot {
+    /// Adds an echo, moving any finished shots into `points`.
+    fn push(&mut self, echo_type: EchoType, point: Option<Point>, points: &mut Vec<Point>) {
+        if echo_type == EchoType::Single || echo_type == EchoType::First {
+            self.finish(points);
+        }
+        self.echoes.push(point);
+        if echo_type == EchoType::Single || echo_type == EchoType::Last {
+            self.finish(points);
+        }
+    }
+
+    /// Numbers the echoes we have and moves them into `points`.
+    ///
+    /// If a shot is cut short, e.g. because the file ends, its echoes are numbered as if the ones
+    /// we have were all there were.
+    fn finish(&mut self, points: &mut Vec<Point>) {
+        let number_of_returns = self.echoes.len();
+        for (i, point) in self.echoes.drain(..).enumerate() {
+            if let Some(mut point) = point {
+                point.return_number = Some(i + 1);
+                point.number_of_returns = Some(number_of_returns);
+                let _ = point.extra.insert(TARGET_INDEX.to_string(), Value::Usize(i + 1));
+                points.push(point);
+            }
+        }
+    }
+}
+
+/// Our `RxpConfig`, checked and with its defaults filled in.
+#[derive(Clone, Debug)]
+struct Options {
+    amplitude: bool,
+    echo_types: Option<Vec<EchoType>>,
+    intensity_max: f64,
+    intensity_min: f64,
+    max_deviation: Option<u16>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            amplitude: false,
+            echo_types: None,
+            intensity_max: 50.0,
+            intensity_min: -50.0,
+            max_deviation: None,
+        }
+    }
+}
+
+impl Options {
+    fn from_config(config: &RxpConfig) -> Result<Options> {
+        let defaults = Options::default();
+        let amplitude = match config.intensity.as_ref().map(|s| &s[..]) {
+            None | Some("reflectance") => false,
+            Some("amplitude") => true,
+            Some(s) => {
+                return Err(Error::Configuration(format!("rxp intensity must be reflectance or \
+                                                         amplitude, not {}",
+                                                        s)))
+            }
+        };
+        let echo_types = match config.echo_types {
+            Some(ref names) => {
+                let mut echo_types = Vec::with_capacity(names.len());
+                for name in names {
+                    echo_types.push(match &name[..] {
+                        "single" => EchoType::Single,
+                        "first" => EchoType::First,
+                        "interior" => EchoType::Interior,
+                        "last" => EchoType::Last,
+                        _ => {
+                            return Err(Error::Configuration(format!("unknown rxp echo type: {}",
+                                                                    name)))
+                        }
+                    });
+                }
+                Some(echo_types)
+            }
+            None => None,
+        };
+        Ok(Options {
+            amplitude: amplitude,
+            echo_types: echo_types,
+            intensity_max: config.intensity_max.unwrap_or(defaults.intensity_max),
+            intensity_min: config.intensity_min.unwrap_or(defaults.intensity_min),
+            max_deviation: config.max_deviation,
+        })
+    }
+
+    /// Converts an echo into a point, or returns `None` if the echo should be dropped.
+    fn point(&self, echo: &rivlib::Point) -> Option<Point> {
+        if let Some(ref echo_types) = self.echo_types {
+            if !echo_types.contains(&echo.echo_type) {
+                return None;
+            }
+        }
+        if self.max_deviation.map_or(false, |max| echo.deviation > max) {
+            return None;
+        }
+        let mut extra = BTreeMap::new();
+        let _ = extra.insert(DEVIATION.to_string(), Value::U16(echo.deviation));
+        let (x, y, z) = (echo.x as f64, echo.y as f64, echo.z as f64);
+        let intensity = if self.amplitude {
+            echo.amplitude
+        } else {
+            echo.reflectance
+        };
+        Some(Point {
+            x: x,
+            y: y,
+            z: z,
+            intensity: Intensity::new(intensity as f64, self.intensity_min, self.intensity_max),
+            gps_time: if echo.pps {
+                Some(echo.time as f64 * 1e-9)
             } else {
                 None
             },
+            range: Some((x * x + y * y + z * z).sqrt()),
+            facet_number: facet_number(echo.facet),
+            extra: extra,
             ..Default::default()
-        }
+        })
     }
 }
 
-/// Rxp's decodable configuration object.
-#[derive(Copy, Clone, Debug, RustcDecodable)]
-pub struct RxpConfig {
-    sync_to_pps: bool,
+/// Returns the number of a mirror facet.
+///
+/// rivlib's `Facet` keeps its number private and can't be compared, so we match its `Debug`
+/// output against the facets that a scanner can report, which are two bits wide. Anything else
+/// isn't a facet we know, and is `None` rather than a made-up number.
+fn facet_number(facet: Facet) -> Option<u8> {
+    match &format!("{:?}", facet)[..] {
+        "Facet(0)" => Some(0),
+        "Facet(1)" => Some(1),
+        "Facet(2)" => Some(2),
+        "Facet(3)" => Some(3),
+        _ => None,
+    }
 }
 
-impl Default for RxpConfig {
-    fn default() -> RxpConfig {
-        RxpConfig {
-            sync_to_pps: true,
-        }
+/// Converts a single echo with the default options.
+///
+/// Interior and last echoes can't be numbered on their own, so they don't get return numbers. Use
+/// a `Reader` to number every echo.
+impl From<rivlib::Point> for Point {
+    fn from(echo: rivlib::Point) -> Point {
+        let mut point = Options::default().point(&echo).unwrap();
+        point.return_number = match echo.echo_type {
+            EchoType::Single | EchoType::First => Some(1),
+            _ => None,
+        };
+        point.number_of_returns = match echo.echo_type {
+            EchoType::Single => Some(1),
+            _ => None,
+        };
+        point
     }
 }
 
-impl FileSource for rivlib::Stream {
+/// Rxp's decodable configuration object.
+///
+/// `intensity` is the attribute used for each point's intensity, either `"reflectance"` (the
+/// default) or `"amplitude"`, in dB between `intensity_min` and `intensity_max` (-50 and 50 by
+/// default). Only echoes whose type is in `echo_types` (`"single"`, `"first"`, `"interior"`, or
+/// `"last"`) and whose deviation is at most `max_deviation` are kept, though dropped echoes still
+/// count towards the return numbers of the rest of their shot.
+///
+/// Each point's range is its distance from the scanner's origin. Each echo's index in its shot,
+/// counting dropped echoes, is Riegl's target index. It's the point's return number, and is also
+/// kept as the `target_index` extra dimension. The `deviation` extra dimension is Riegl's pulse
+/// shape deviation.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct RxpConfig {
+    sync_to_pps: Option<bool>,
+    intensity: Option<String>,
+    intensity_min: Option<f64>,
+    intensity_max: Option<f64>,
+    echo_types: Option<Vec<String>>,
+    max_deviation: Option<u16>,
+}
+
+impl FileSource for Reader {
     type Config = RxpConfig;
 
     fn open_file_source<P>(path: P, config: Self::Config) -> Result<Box<Source>>
         where P: AsRef<Path> + AsRef<OsStr>
     {
         let path = OsStr::new(&path).to_str().unwrap();
-        Ok(Box::new(try!(rivlib::Stream::open(path, config.sync_to_pps))))
+        Ok(Box::new(try!(Reader::open(path, config))))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use rivlib;
+    use rivlib::stream::EchoType;
     use toml;
 
+    use point::Point;
     use source::{open_file_source, Source};
+    use super::*;
 
     fn xyz_from_first_point<S: Source>(source: &mut S) {
         let points = source.source(1).unwrap().unwrap();
@@ -91,14 +334,23 @@ mod tests {
 
     #[test]
     fn source_all() {
-        let mut source = rivlib::Stream::open("data/130501_232206_cut.rxp", true).unwrap();
+        let mut source = Reader::open("data/130501_232206_cut.rxp", Default::default()).unwrap();
         let points = source.source_to_end(100).unwrap();
         assert_eq!(177208, points.len());
     }
 
+    #[test]
+    fn schema() {
+        let source = Reader::open("data/130501_232206_cut.rxp", Default::default()).unwrap();
+        let schema = source.schema().unwrap();
+        for name in &["x", "intensity", "gps_time", "facet_number", "deviation", "target_index"] {
+            assert!(schema.contains(name), "{} isn't in the schema", name);
+        }
+    }
+
     #[test]
     fn xyz() {
-        let ref mut source = rivlib::Stream::open("data/130501_232206_cut.rxp", true).unwrap();
+        let ref mut source = Reader::open("data/130501_232206_cut.rxp", Default::default()).unwrap();
         xyz_from_first_point(source);
     }
 
@@ -111,4 +363,75 @@ mod tests {
         let points = source.source_to_end(200000).unwrap();
         assert_eq!(177208, points.len());
     }
+
+    #[test]
+    fn return_numbers() {
+        let mut source = Reader::open("data/130501_232206_cut.rxp", Default::default()).unwrap();
+        for point in source.source_to_end(1000).unwrap() {
+            let return_number = point.return_number.unwrap();
+            assert!(return_number >= 1);
+            assert!(return_number <= point.number_of_returns.unwrap());
+        }
+    }
+
+    #[test]
+    fn facets_and_target_indices() {
+        let mut source = Reader::open("data/130501_232206_cut.rxp", Default::default()).unwrap();
+        for point in source.source_to_end(1000).unwrap() {
+            assert!(point.facet_number.unwrap() < 4);
+            assert_eq!(Some(&Value::Usize(point.return_number.unwrap())),
+                       point.extra.get("target_index"));
+        }
+    }
+
+    #[test]
+    fn select_echoes() {
+        let config = toml::Parser::new(r#"
+        intensity = "amplitude"
+        echo_types = ["single"]
+        max_deviation = 10
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut source = open_file_source("data/130501_232206_cut.rxp",
+                                          Some(toml::Value::Table(config)))
+                             .unwrap();
+        let points = source.source_to_end(1000).unwrap();
+        assert!(points.len() < 177208);
+        for point in points {
+            assert_eq!(Some(1), point.number_of_returns);
+            assert!(point.extra["deviation"].as_f64() <= 10.0);
+            assert!(point.range.is_some());
+            assert!(point.facet_number.is_some());
+        }
+    }
+
+    #[test]
+    fn bad_options() {
+        let config = RxpConfig { intensity: Some("loudness".to_string()), ..Default::default() };
+        assert!(Options::from_config(&config).is_err());
+        let config = RxpConfig {
+            echo_types: Some(vec!["middle".to_string()]),
+            ..Default::default()
+        };
+        assert!(Options::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn shot_across_chunks() {
+        let mut shot = Shot::default();
+        let mut points = Vec::new();
+        shot.push(EchoType::Single, Some(Point::default()), &mut points);
+        shot.push(EchoType::First, Some(Point::default()), &mut points);
+        shot.push(EchoType::Interior, None, &mut points);
+        assert_eq!(1, points.len());
+        shot.push(EchoType::Last, Some(Point::default()), &mut points);
+        assert_eq!(3, points.len());
+        assert_eq!(Some(1), points[0].number_of_returns);
+        assert_eq!(Some(3), points[2].return_number);
+        assert_eq!(Some(3), points[2].number_of_returns);
+        shot.push(EchoType::First, Some(Point::default()), &mut points);
+        shot.finish(&mut points);
+        assert_eq!(Some(1), points[3].number_of_returns);
+    }
 }
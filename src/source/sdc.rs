@@ -1,36 +1,114 @@
 //! Implement source for .sdc, Riegl's simple file-based discrete return format.
 
 use std::ffi::OsStr;
-use std::io::Read;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
 use sdc;
 
-use Result;
-use point::{Intensity, Point};
+use {Error, Result};
+use metadata::Metadata;
+use point::{Dimension, Intensity, Point};
+use schema::Schema;
 use source::{FileSource, Source};
 
-impl<R: Read> Source for sdc::Reader<R> {
+const DIMENSIONS: [Dimension; 12] = [Dimension::X,
+                                     Dimension::Y,
+                                     Dimension::Z,
+                                     Dimension::Intensity,
+                                     Dimension::ReturnNumber,
+                                     Dimension::NumberOfReturns,
+                                     Dimension::GpsTime,
+                                     Dimension::Range,
+                                     Dimension::ScanAngle,
+                                     Dimension::RgIndex,
+                                     Dimension::FacetNumber,
+                                     Dimension::HighChannel];
+
+/// An sdc reader that holds on to errors until its good points have been returned.
+#[derive(Debug)]
+pub struct Reader<R: Read> {
+    error: Option<Error>,
+    reader: sdc::Reader<R>,
+}
+
+impl Reader<BufReader<File>> {
+    /// Opens an sdc reader for a path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::source::sdc::Reader;
+    /// let reader = Reader::from_path("data/4-points.sdc").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Reader<BufReader<File>>> {
+        Ok(Reader::new(try!(sdc::Reader::from_path(path))))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Wraps an sdc reader.
+    pub fn new(reader: sdc::Reader<R>) -> Reader<R> {
+        Reader {
+            error: None,
+            reader: reader,
+        }
+    }
+}
+
+impl<R: Read> Source for Reader<R> {
+    /// Reads points.
+    ///
+    /// If a point can't be read, the points before it are returned, and the error is returned by
+    /// the next call. Sdc records aren't framed, so reading can't carry on after an error.
     fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         let mut points = Vec::with_capacity(want);
         for _ in 0..want {
-            match try!(self.next_point()) {
-                Some(point) => points.push(Point::from(point)),
-                None => {
+            match self.reader.next_point() {
+                Ok(Some(point)) => points.push(Point::from(point)),
+                Ok(None) => break,
+                Err(err) => {
                     if points.is_empty() {
-                        return Ok(None);
-                    } else {
-                        break;
+                        return Err(Error::from(err));
                     }
+                    self.error = Some(Error::from(err));
+                    break;
                 }
             }
         }
-        Ok(Some(points))
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
     }
 
     fn source_len(&mut self) -> Option<usize> {
         None
     }
+
+    fn schema(&self) -> Option<Schema> {
+        Some(Schema::from_dimensions(&DIMENSIONS))
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        let mut metadata = Metadata::new();
+        let version = self.reader.version();
+        let _ = metadata.properties
+                        .insert("sdc_version".to_string(),
+                                format!("{}.{}", version.major, version.minor));
+        if let Ok(header) = self.reader.header_information_as_str() {
+            let header = header.trim_matches('\0').trim();
+            if !header.is_empty() {
+                let _ = metadata.properties.insert("sdc_header".to_string(), header.to_string());
+            }
+        }
+        Some(metadata)
+    }
 }
 
 impl From<sdc::Point> for Point {
@@ -53,10 +131,10 @@ impl From<sdc::Point> for Point {
     }
 }
 
-impl<R: Read> FileSource for sdc::Reader<R> {
+impl<R: Read> FileSource for Reader<R> {
     type Config = SdcConfig;
     fn open_file_source<P>(path: P, _: Self::Config) -> Result<Box<Source>> where P: AsRef<Path> + AsRef<OsStr> {
-        Ok(Box::new(try!(sdc::Reader::from_path(path))))
+        Ok(Box::new(try!(Reader::from_path(path))))
     }
 }
 
@@ -67,13 +145,12 @@ pub struct SdcConfig;
 #[cfg(test)]
 mod tests {
 
-    use sdc;
-
     use source::{open_file_source, Source};
+    use super::*;
 
     #[test]
     fn simple_read_format_5_0() {
-        let mut source = sdc::Reader::from_path("data/4-points.sdc").unwrap();
+        let mut source = Reader::from_path("data/4-points.sdc").unwrap();
         let points = source.source_to_end(100).unwrap();
         assert_eq!(4, points.len());
     }
@@ -0,0 +1,298 @@
+//! Source points from Riegl's sdf full-waveform files.
+//!
+//! sdf files hold sampled waveforms rather than points, so we discretize each record by detecting
+//! peaks in its waveforms. The thresholds for peak detection are configurable through `SdfConfig`,
+//! and default to the values used by the sdf crate's own `discretize`.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use peakbag::{Peak, PeakDetector};
+use sdf;
+use sdf::file::{Block, Channel, FileInfo, Record};
+
+use Result;
+use error::Error;
+use point::{Dimension, Intensity, Point, Waveform};
+use schema::Schema;
+use source::{FileSource, Source};
+
+const DIMENSIONS: [Dimension; 11] = [Dimension::X,
+                                     Dimension::Y,
+                                     Dimension::Z,
+                                     Dimension::Intensity,
+                                     Dimension::ReturnNumber,
+                                     Dimension::NumberOfReturns,
+                                     Dimension::ScanAngle,
+                                     Dimension::GpsTime,
+                                     Dimension::Range,
+                                     Dimension::FacetNumber,
+                                     Dimension::HighChannel];
+
+/// An sdf reader.
+#[derive(Debug)]
+pub struct Reader {
+    config: SdfConfig,
+    error: Option<Error>,
+    file: sdf::File,
+    file_info: FileInfo,
+}
+
+impl Reader {
+    /// Opens an sdf file, creating its index if necessary.
+    pub fn from_path<P: AsRef<Path>>(path: P, config: SdfConfig) -> Result<Reader> {
+        let path = match path.as_ref().to_str() {
+            Some(path) => path,
+            None => {
+                return Err(Error::Configuration(format!("sdf path is not valid unicode: {}",
+                                                        path.as_ref().display())))
+            }
+        };
+        let mut file = try!(sdf::File::open(path));
+        if !file.indexed() {
+            try!(file.reindex());
+        }
+        let file_info = try!(file.info());
+        Ok(Reader {
+            config: config,
+            error: None,
+            file: file,
+            file_info: file_info,
+        })
+    }
+}
+
+/// Turns one sdf record into zero or more points.
+///
+/// Records that don't have exactly one reference peak can't be ranged, so they produce no points.
+fn discretize(record: &Record, file_info: &FileInfo, config: &SdfConfig) -> Vec<Point> {
+    let mut high_blocks = Vec::new();
+    let mut low_blocks = Vec::new();
+    let mut reference_blocks = Vec::new();
+    for block in &record.blocks {
+        match block.channel {
+            Channel::High => high_blocks.push(block),
+            Channel::Low => low_blocks.push(block),
+            Channel::Reference => reference_blocks.push(block),
+            Channel::Saturation => {}
+        }
+    }
+    if reference_blocks.len() != 1 {
+        return Vec::new();
+    }
+
+    let min_height_above_background = config.min_height_above_background.unwrap_or(5.0);
+    let max_kurtosis = config.max_kurtosis.unwrap_or(0.04);
+    let high_ceiling = config.high_ceiling.unwrap_or(255);
+    let high_detector = PeakDetector::new(config.high_width.unwrap_or(2),
+                                          config.high_floor.unwrap_or(15),
+                                          high_ceiling)
+                            .min_height_above_background(min_height_above_background)
+                            .max_kurtosis(max_kurtosis);
+    let (low_width, low_ceiling) = if high_blocks.is_empty() {
+        (3, 250)
+    } else {
+        (2, 255)
+    };
+    let low_saturation = config.low_saturation.unwrap_or(255);
+    let low_detector = PeakDetector::new(config.low_width.unwrap_or(low_width),
+                                         config.low_floor.unwrap_or(15),
+                                         config.low_ceiling.unwrap_or(low_ceiling))
+                           .min_height_above_background(min_height_above_background)
+                           .max_kurtosis(max_kurtosis)
+                           .saturation(low_saturation);
+
+    let reference_block = reference_blocks[0];
+    let reference_peaks = high_detector.detect_peaks(&reference_block.samples);
+    if reference_peaks.len() != 1 {
+        return Vec::new();
+    }
+    let t_ref = timestamp(file_info, &reference_peaks[0], reference_block);
+    // x is straight out of the scanner, and the mirror pans it along the z axis.
+    let theta = (record.direction[2] / record.direction[0]).atan().to_degrees();
+
+    let mut points = Vec::new();
+    let blocks = low_blocks.iter()
+                           .map(|&b| (b, &low_detector, low_saturation))
+                           .chain(high_blocks.iter().map(|&b| (b, &high_detector, high_ceiling)));
+    for (block, detector, max) in blocks {
+        let peaks = detector.detect_peaks(&block.samples);
+        let waveform = if config.waveforms.unwrap_or(false) {
+            let speed = file_info.v_group / 2.0;
+            let range = speed * (block.time_sosbl - t_ref);
+            let mut waveform = Waveform {
+                samples: block.samples.clone(),
+                sampling_interval: file_info.sampling_time,
+                ..Default::default()
+            };
+            for i in 0..3 {
+                waveform.anchor[i] = record.origin[i] + record.direction[i] * range;
+                waveform.direction[i] = record.direction[i] * speed;
+            }
+            Some(waveform)
+        } else {
+            None
+        };
+        let number_of_returns = peaks.len();
+        for (i, peak) in peaks.iter().enumerate() {
+            let time = timestamp(file_info, peak, block);
+            let range = file_info.v_group / 2.0 * (time - t_ref);
+            points.push(Point {
+                x: record.origin[0] + record.direction[0] * range,
+                y: record.origin[1] + record.direction[1] * range,
+                z: record.origin[2] + record.direction[2] * range,
+                intensity: Intensity::new(peak.amplitude as f64, 0.0, max as f64),
+                return_number: Some(i + 1),
+                number_of_returns: Some(number_of_returns),
+                scan_angle: Some(theta),
+                gps_time: Some(time - record.time_sorg + record.time_external),
+                range: Some(range),
+                facet_number: Some(record.facet as u8),
+                high_channel: Some(block.channel == Channel::High),
+                waveform: waveform.clone(),
+                ..Default::default()
+            });
+        }
+    }
+    points
+}
+
+fn timestamp(file_info: &FileInfo, peak: &Peak<u16>, block: &Block) -> f64 {
+    block.time_sosbl + peak.index as f64 * file_info.sampling_time
+}
+
+impl Source for Reader {
+    /// Reads records and turns their peaks into points.
+    ///
+    /// If a record can't be read, the points before it are returned, and the error is returned by
+    /// the next call. Reading can't carry on after an error.
+    fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let mut points = Vec::with_capacity(want);
+        while points.len() < want {
+            let record = match self.file.read() {
+                Ok(record) => record,
+                Err(sdf::Error::EndOfFile(_)) => break,
+                Err(err) => {
+                    if points.is_empty() {
+                        return Err(Error::from(err));
+                    }
+                    self.error = Some(Error::from(err));
+                    break;
+                }
+            };
+            points.extend(discretize(&record, &self.file_info, &self.config));
+        }
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
+    }
+
+    fn source_len(&mut self) -> Option<usize> {
+        None
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        Some(Schema::from_dimensions(&DIMENSIONS))
+    }
+}
+
+impl FileSource for Reader {
+    type Config = SdfConfig;
+
+    fn open_file_source<P>(path: P, config: SdfConfig) -> Result<Box<Source>>
+        where P: AsRef<Path> + AsRef<OsStr>
+    {
+        Ok(Box::new(try!(Reader::from_path(path, config))))
+    }
+}
+
+/// Decodable configuration for peak detection.
+///
+/// The high channel is also used for the reference peak. Widths are the number of samples on
+/// either side of a peak, floors and ceilings bound the peak amplitudes, and peaks that are too
+/// flat (`min_height_above_background`) or too sharp (`max_kurtosis`) are thrown out. If not
+/// provided, the low channel's width and ceiling depend on whether the record has any high
+/// channel blocks. If `waveforms` is true, each point keeps the samples of the block it was found
+/// in.
+#[derive(Clone, Copy, Debug, Default, RustcDecodable)]
+pub struct SdfConfig {
+    high_width: Option<usize>,
+    high_floor: Option<u16>,
+    high_ceiling: Option<u16>,
+    low_width: Option<usize>,
+    low_floor: Option<u16>,
+    low_ceiling: Option<u16>,
+    low_saturation: Option<u16>,
+    min_height_above_background: Option<f64>,
+    max_kurtosis: Option<f64>,
+    waveforms: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use sdf::file::{Block, Channel, FileInfo, Record};
+
+    use super::*;
+
+    fn waveform(peak: usize) -> Vec<u16> {
+        let mut samples = vec![0; 20];
+        samples[peak - 2] = 20;
+        samples[peak - 1] = 60;
+        samples[peak] = 100;
+        samples[peak + 1] = 60;
+        samples[peak + 2] = 20;
+        samples
+    }
+
+    #[test]
+    fn discretize_one_return() {
+        let record = Record {
+            time_sorg: 0.0,
+            time_external: 10.0,
+            origin: [0.0; 3],
+            direction: [1.0, 0.0, 0.0],
+            synchronized: true,
+            sync_lastsec: true,
+            housekeeping: false,
+            facet: 1,
+            blocks: vec![Block {
+                             time_sosbl: 0.0,
+                             channel: Channel::Reference,
+                             samples: waveform(5),
+                         },
+                         Block {
+                             time_sosbl: 0.0,
+                             channel: Channel::High,
+                             samples: waveform(10),
+                         }],
+        };
+        let file_info = FileInfo {
+            instrument: "Q680I".to_string(),
+            serial: String::new(),
+            epoch: "UNKNOWN".to_string(),
+            v_group: 2.0,
+            sampling_time: 1.0,
+            gps_synchronized: true,
+            num_facets: 4,
+        };
+        let config = SdfConfig { max_kurtosis: Some(10.0), ..Default::default() };
+        let points = discretize(&record, &file_info, &config);
+        assert_eq!(1, points.len());
+        assert_eq!(5.0, points[0].x);
+        assert_eq!(Some(20.0), points[0].gps_time);
+        assert_eq!(Some(true), points[0].high_channel);
+        assert!(points[0].waveform.is_none());
+
+        let config = SdfConfig { waveforms: Some(true), ..config };
+        let points = discretize(&record, &file_info, &config);
+        let waveform = points[0].waveform.as_ref().unwrap();
+        assert_eq!(20, waveform.samples.len());
+        assert_eq!([-5.0, 0.0, 0.0], waveform.anchor);
+        assert_eq!(10.0, waveform.time_at([points[0].x, points[0].y, points[0].z]));
+    }
+}
@@ -0,0 +1,414 @@
+//! Source points from delimited text files.
+//!
+//! These are the whitespace- or comma-delimited xyz files that every piece of LiDAR software seems
+//! to be able to export.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+use std::u16;
+
+use {Error, Result};
+use error::Context;
+use point::{Dimension, Intensity, Point, ScanDirection, Value};
+use schema::Schema;
+use source::{FileSource, Source};
+
+/// A delimited text reader.
+#[derive(Debug)]
+pub struct Reader<R: BufRead> {
+    columns: Vec<Option<Dimension>>,
+    delimiter: Option<char>,
+    error: Option<Error>,
+    line_number: usize,
+    lines: Lines<R>,
+    pending: Option<String>,
+}
+
+impl Reader<BufReader<File>> {
+    /// Opens a text reader for a path.
+    ///
+    /// If the config doesn't specify a delimiter, `.csv` files, in any case, are split on commas
+    /// and everything else is split on whitespace.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::source::text::Reader;
+    /// let reader = Reader::from_path("data/4-points.xyz", Default::default()).unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P,
+                                     mut config: TextSourceConfig)
+                                     -> Result<Reader<BufReader<File>>> {
+        if config.delimiter.is_none() &&
+           path.as_ref()
+               .extension()
+               .and_then(|e| e.to_str())
+               .map_or(false, |e| e.to_lowercase() == "csv") {
+            config.delimiter = Some(",".to_string());
+        }
+        Reader::new(BufReader::new(try!(File::open(path))), config)
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a new text reader from a `BufRead`.
+    ///
+    /// Any skipped lines and the header row, if there is one, are consumed here.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::source::text::Reader;
+    /// let reader = Reader::new(Cursor::new("1 2 3\n"), Default::default()).unwrap();
+    /// ```
+    pub fn new(reader: R, config: TextSourceConfig) -> Result<Reader<R>> {
+        let delimiter = match config.delimiter {
+            Some(ref s) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => {
+                        return Err(Error::Configuration(format!("text delimiter must be a \
+                                                                 single character, not '{}'",
+                                                                s)))
+                    }
+                }
+            }
+            None => None,
+        };
+        let mut reader = Reader {
+            columns: Vec::new(),
+            delimiter: delimiter,
+            error: None,
+            line_number: 0,
+            lines: reader.lines(),
+            pending: None,
+        };
+        for _ in 0..config.skip_lines.unwrap_or(0) {
+            if try!(reader.next_line()).is_none() {
+                break;
+            }
+        }
+        let mut header = None;
+        if let Some(line) = try!(reader.next_line()) {
+            let is_header = match config.header {
+                Some(is_header) => is_header,
+                None => {
+                    reader.split(&line)
+                          .next()
+                          .map_or(false, |s| s.parse::<f64>().is_err())
+                }
+            };
+            if is_header {
+                header = Some(reader.split(&line).map(|s| s.to_string()).collect::<Vec<_>>());
+            } else {
+                reader.pending = Some(line);
+                reader.line_number -= 1;
+            }
+        }
+        reader.columns = match (config.columns, header) {
+            (Some(columns), _) => {
+                let mut mapping = Vec::with_capacity(columns.len());
+                for name in columns {
+                    if name.is_empty() || name == "-" {
+                        mapping.push(None);
+                    } else {
+                        mapping.push(Some(try!(name.parse())));
+                    }
+                }
+                mapping
+            }
+            (None, Some(header)) => {
+                header.iter().map(|s| s.parse().ok()).collect()
+            }
+            (None, None) => vec![Some(Dimension::X), Some(Dimension::Y), Some(Dimension::Z)],
+        };
+        Ok(reader)
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            self.line_number += 1;
+            return Ok(Some(line));
+        }
+        loop {
+            match self.lines.next() {
+                Some(line) => {
+                    let line = try!(line);
+                    self.line_number += 1;
+                    if !line.trim().is_empty() {
+                        return Ok(Some(line));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn split<'a>(&self, line: &'a str) -> Box<Iterator<Item = &'a str> + 'a> {
+        match self.delimiter {
+            Some(c) => Box::new(line.split(c).map(|s| s.trim())),
+            None => Box::new(line.split_whitespace()),
+        }
+    }
+
+    /// Parses a line into a point, adding the line number to any error.
+    fn parse_point(&self, line: &str) -> Result<Point> {
+        self.parse_fields(line).map_err(|e| e.context(Context::new("text").line(self.line_number)))
+    }
+
+    fn parse_fields(&self, line: &str) -> Result<Point> {
+        let mut point = Point::default();
+        let mut fields = self.split(line);
+        for column in &self.columns {
+            let field = match fields.next() {
+                Some(field) => field,
+                None => {
+                    return Err(Error::Format(format!("expected {} columns", self.columns.len())))
+                }
+            };
+            let column = match *column {
+                Some(column) => column,
+                None => continue,
+            };
+            match column {
+                Dimension::X => point.x = try!(field.parse()),
+                Dimension::Y => point.y = try!(field.parse()),
+                Dimension::Z => point.z = try!(field.parse()),
+                Dimension::Intensity => {
+                    point.intensity = Intensity::new(try!(field.parse()),
+                                                     u16::MIN as f64,
+                                                     u16::MAX as f64)
+                }
+                Dimension::ReturnNumber => point.return_number = Some(try!(field.parse())),
+                Dimension::NumberOfReturns => {
+                    point.number_of_returns = Some(try!(field.parse()))
+                }
+                Dimension::ScanDirection => {
+                    point.scan_direction = match try!(field.parse::<u8>()) {
+                        0 => ScanDirection::Backward,
+                        1 => ScanDirection::Forward,
+                        n => {
+                            return Err(Error::Format(format!("scan_direction must be 0 or 1, \
+                                                              not {}",
+                                                             n)))
+                        }
+                    }
+                }
+                Dimension::EdgeOfFlightLine => point.edge_of_flight_line = try!(field.parse()),
+                Dimension::Classification => point.classification = try!(field.parse()),
+                Dimension::Synthetic => point.synthetic = try!(field.parse()),
+                Dimension::KeyPoint => point.key_point = try!(field.parse()),
+                Dimension::Withheld => point.withheld = try!(field.parse()),
+                Dimension::ScanAngle => point.scan_angle = Some(try!(field.parse())),
+                Dimension::PointSourceId => {
+                    point.point_source_id = Some(try!(field.parse()))
+                }
+                Dimension::UserData => point.user_data = Some(try!(field.parse())),
+                Dimension::GpsTime => point.gps_time = Some(try!(field.parse())),
+                Dimension::Range => point.range = Some(try!(field.parse())),
+                Dimension::Width => point.width = Some(try!(field.parse())),
+                Dimension::RgIndex => point.rg_index = Some(try!(field.parse())),
+                Dimension::FacetNumber => point.facet_number = Some(try!(field.parse())),
+                Dimension::TargetType => point.target_type = Some(try!(field.parse())),
+                Dimension::HighChannel => point.high_channel = Some(try!(field.parse())),
+                Dimension::Red | Dimension::Green | Dimension::Blue | Dimension::Nir => {
+                    point.set(column, Value::U16(try!(field.parse())))
+                }
+            }
+        }
+        Ok(point)
+    }
+}
+
+impl<R: BufRead> Source for Reader<R> {
+    /// Reads points, one per line.
+    ///
+    /// If a line can't be read, the points before it are returned, and the error is returned by
+    /// the next call. Reading can carry on after the error, starting with the next line.
+    fn source(&mut self, want: usize) -> Result<Option<Vec<Point>>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let mut points = Vec::with_capacity(want);
+        for _ in 0..want {
+            let point = match self.next_line() {
+                Ok(Some(line)) => self.parse_point(&line),
+                Ok(None) => break,
+                Err(err) => Err(err),
+            };
+            match point {
+                Ok(point) => points.push(point),
+                Err(err) => {
+                    if points.is_empty() {
+                        return Err(err);
+                    }
+                    self.error = Some(err);
+                    break;
+                }
+            }
+        }
+        if points.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(points))
+        }
+    }
+
+    fn source_len(&mut self) -> Option<usize> {
+        None
+    }
+
+    fn resumable(&self) -> bool {
+        true
+    }
+
+    fn schema(&self) -> Option<Schema> {
+        let dimensions = self.columns.iter().filter_map(|&c| c).collect::<Vec<_>>();
+        Some(Schema::from_dimensions(&dimensions))
+    }
+}
+
+impl<R: BufRead> FileSource for Reader<R> {
+    type Config = TextSourceConfig;
+
+    fn open_file_source<P>(path: P, config: TextSourceConfig) -> Result<Box<Source>>
+        where P: AsRef<Path> + AsRef<OsStr>
+    {
+        Ok(Box::new(try!(Reader::from_path(path, config))))
+    }
+}
+
+/// Decodable configuration for a text source.
+#[derive(Clone, Debug, Default, RustcDecodable)]
+pub struct TextSourceConfig {
+    /// The single-character column delimiter. If not provided, columns are split on whitespace
+    /// (or commas for `.csv` files).
+    delimiter: Option<String>,
+    /// Is the first line (after any skipped lines) a header row? If not provided, we guess by
+    /// checking whether the first field looks like a number.
+    header: Option<bool>,
+    /// The number of lines to skip at the start of the file, before any header row.
+    skip_lines: Option<usize>,
+    /// The point field for each column, in order, e.g. `["x", "y", "z", "intensity"]`. Use an
+    /// empty string or `"-"` to ignore a column. If not provided, the header row names are used,
+    /// and if there is no header row the first three columns are x, y, and z.
+    columns: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{copy, remove_file};
+    use std::io::Cursor;
+
+    use toml;
+
+    use Error;
+    use source::{open_file_source, Source};
+    use super::*;
+
+    #[test]
+    fn uppercase_csv() {
+        let _ = copy("data/4-points.csv", "UPPERCASE_CSV.CSV").unwrap();
+        let mut source = open_file_source("UPPERCASE_CSV.CSV", None).unwrap();
+        assert_eq!(4, source.source_to_end(100).unwrap().len());
+        remove_file("UPPERCASE_CSV.CSV").unwrap();
+    }
+
+    #[test]
+    fn xyz() {
+        let mut source = open_file_source("data/4-points.xyz", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(4, points.len());
+        assert_eq!(1.0, points[0].x);
+        assert_eq!(2.0, points[0].y);
+        assert_eq!(3.0, points[0].z);
+    }
+
+    #[test]
+    fn csv_with_header() {
+        let mut source = open_file_source("data/4-points.csv", None).unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(4, points.len());
+        assert_eq!(4.0, points[3].z);
+        assert_eq!(400, points[3].intensity.as_u16());
+        assert_eq!(Some(4.5), points[3].gps_time);
+    }
+
+    #[test]
+    fn column_mapping() {
+        let config = toml::Parser::new(r#"
+        skip_lines = 1
+        header = false
+        columns = ["return_number", "x", "-", "z"]
+        "#)
+                         .parse()
+                         .unwrap();
+        let mut source = open_file_source("data/4-points.xyz", Some(toml::Value::Table(config)))
+                             .unwrap();
+        let points = source.source_to_end(100).unwrap();
+        assert_eq!(3, points.len());
+        assert_eq!(Some(2), points[0].return_number);
+        assert_eq!(3.0, points[0].x);
+        assert_eq!(0.0, points[0].y);
+        assert_eq!(200.0, points[0].z);
+    }
+
+    #[test]
+    fn parse_error_has_line_number() {
+        let config = TextSourceConfig {
+            header: Some(true),
+            ..Default::default()
+        };
+        let mut reader = Reader::new(Cursor::new("x y z\n1 2 3\n\n1 2 foo\n4 5 6\n"), config)
+                             .unwrap();
+        assert_eq!(1, reader.source(10).unwrap().unwrap().len());
+        match reader.source(10) {
+            Err(Error::Context(ref context, ref err)) => {
+                assert_eq!(Some(4), context.line);
+                match **err {
+                    Error::ParseFloat(_) => {}
+                    ref err => panic!("unexpected error: {:?}", err),
+                }
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let points = reader.source(10).unwrap().unwrap();
+        assert_eq!(1, points.len());
+        assert_eq!(4.0, points[0].x);
+    }
+
+    #[test]
+    fn scan_direction() {
+        let mut reader = Reader::new(Cursor::new("x y z scan_direction\n1 2 3 1\n4 5 6 0\n"),
+                                     Default::default())
+                             .unwrap();
+        let points = reader.source(10).unwrap().unwrap();
+        assert_eq!(ScanDirection::Forward, points[0].scan_direction);
+        assert_eq!(ScanDirection::Backward, points[1].scan_direction);
+        let mut reader = Reader::new(Cursor::new("x y z scan_direction\n1 2 3 2\n1 2 3\n"),
+                                     Default::default())
+                             .unwrap();
+        for &line in &[2, 3] {
+            match reader.source(10).unwrap_err() {
+                Error::Context(context, err) => {
+                    assert_eq!(Some(line), context.line);
+                    match *err {
+                        Error::Format(_) => {}
+                        ref err => panic!("unexpected error: {:?}", err),
+                    }
+                }
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn bad_delimiter() {
+        let config = TextSourceConfig { delimiter: Some("ab".to_string()), ..Default::default() };
+        assert!(Reader::new(Cursor::new(""), config).is_err());
+    }
+}
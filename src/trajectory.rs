@@ -0,0 +1,456 @@
+//! Platform trajectories, i.e. position and attitude over time.
+//!
+//! Positions are in the same projected, z-up coordinate system as the points. Attitudes are the
+//! roll, pitch, and yaw of the platform's body frame (x forward, y right, z down) relative to
+//! north-east-down, in radians.
+//!
+//! Trajectories can be read from whitespace-delimited text files or from Applanix smoothed best
+//! estimate of trajectory (SBET) files. SBETs are geographic, so their positions are either kept as
+//! longitude and latitude in degrees or projected into a UTM zone.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use {Error, Result};
+use error::Context;
+
+const SBET_RECORD_LENGTH: usize = 17 * 8;
+
+/// A trajectory file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Whitespace-delimited text, `gps_time x y z roll pitch yaw` with angles in degrees.
+    Text,
+    /// Applanix SBET binary.
+    Sbet,
+}
+
+impl Format {
+    /// Returns the format with the given name, either "text" or "sbet".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::trajectory::Format;
+    /// assert_eq!(Format::Sbet, Format::from_name("sbet").unwrap());
+    /// assert!(Format::from_name("trj").is_err());
+    /// ```
+    pub fn from_name(name: &str) -> Result<Format> {
+        match name {
+            "text" => Ok(Format::Text),
+            "sbet" => Ok(Format::Sbet),
+            _ => Err(Error::UnknownFormat(name.to_string())),
+        }
+    }
+
+    /// Guesses the format of a trajectory file from its extension.
+    ///
+    /// Applanix names SBETs `sbet_*.out`, so `.out` and `.sbet` files are SBETs and everything else
+    /// is text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::trajectory::Format;
+    /// assert_eq!(Format::Sbet, Format::from_path("sbet_mission.out"));
+    /// assert_eq!(Format::Text, Format::from_path("trajectory.txt"));
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Format {
+        match path.as_ref().extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+            Some(ref e) if e == "out" || e == "sbet" => Format::Sbet,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// The position and attitude of the platform at one instant.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pose {
+    /// The GNSS time of this pose.
+    pub gps_time: f64,
+    /// The x position, e.g. easting.
+    pub x: f64,
+    /// The y position, e.g. northing.
+    pub y: f64,
+    /// The z position, up.
+    pub z: f64,
+    /// The roll, in radians, positive right wing down.
+    pub roll: f64,
+    /// The pitch, in radians, positive nose up.
+    pub pitch: f64,
+    /// The yaw, in radians, clockwise from north.
+    pub yaw: f64,
+}
+
+/// A time-ordered sequence of poses.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    geographic: bool,
+    poses: Vec<Pose>,
+}
+
+impl Trajectory {
+    /// Creates a new trajectory from some poses, which are sorted by time.
+    ///
+    /// Returns an error if there aren't any poses, or if a pose's time isn't a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::f64;
+    /// use pabst::trajectory::{Pose, Trajectory};
+    /// let trajectory = Trajectory::new(vec![Pose::default()]).unwrap();
+    /// assert!(Trajectory::new(Vec::new()).is_err());
+    /// assert!(Trajectory::new(vec![Pose { gps_time: f64::NAN, ..Default::default() }]).is_err());
+    /// ```
+    pub fn new(mut poses: Vec<Pose>) -> Result<Trajectory> {
+        if poses.is_empty() {
+            return Err(Error::Configuration("a trajectory needs at least one pose".to_string()));
+        }
+        if let Some(i) = poses.iter().position(|pose| pose.gps_time.is_nan()) {
+            return Err(Error::Configuration(format!("trajectory pose {} has a gps time that \
+                                                     isn't a number",
+                                                    i)));
+        }
+        // Without NaNs, the times are totally ordered.
+        poses.sort_by(|a, b| a.gps_time.partial_cmp(&b.gps_time).unwrap());
+        Ok(Trajectory {
+            geographic: false,
+            poses: poses,
+        })
+    }
+
+    /// Reads a trajectory from a whitespace-delimited text file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::trajectory::Trajectory;
+    /// let trajectory = Trajectory::from_path("data/trajectory.txt").unwrap();
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Trajectory> {
+        Trajectory::from_text(BufReader::new(try!(File::open(path))))
+    }
+
+    /// Reads a trajectory file in any format.
+    ///
+    /// If no format is given, it is guessed from the path's extension. `utm_zone` is only used
+    /// for SBETs; see `from_sbet`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::trajectory::Trajectory;
+    /// let trajectory = Trajectory::open("data/trajectory.txt", None, None).unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P,
+                                format: Option<Format>,
+                                utm_zone: Option<i32>)
+                                -> Result<Trajectory> {
+        match format.unwrap_or(Format::from_path(&path)) {
+            Format::Text => Trajectory::from_path(path),
+            Format::Sbet => Trajectory::from_sbet(try!(File::open(path)), utm_zone),
+        }
+    }
+
+    /// Reads a text trajectory.
+    ///
+    /// Each line is `gps_time x y z roll pitch yaw`, with angles in degrees. Blank lines and lines
+    /// starting with `#` are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::trajectory::Trajectory;
+    /// let trajectory = Trajectory::from_text(Cursor::new("0 1 2 3 0 0 90\n")).unwrap();
+    /// ```
+    pub fn from_text<R: BufRead>(reader: R) -> Result<Trajectory> {
+        let mut poses = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut values = [0.0; 7];
+            let mut words = line.split_whitespace();
+            for value in &mut values {
+                *value = match words.next() {
+                    Some(word) => {
+                        try!(word.parse().map_err(|e| {
+                            Error::from(e).context(Context::new("trajectory").line(i + 1))
+                        }))
+                    }
+                    None => {
+                        return Err(Error::Format("needs seven values".to_string())
+                                       .context(Context::new("trajectory").line(i + 1)))
+                    }
+                };
+            }
+            poses.push(Pose {
+                gps_time: values[0],
+                x: values[1],
+                y: values[2],
+                z: values[3],
+                roll: values[4].to_radians(),
+                pitch: values[5].to_radians(),
+                yaw: values[6].to_radians(),
+            });
+        }
+        Trajectory::new(poses)
+    }
+
+    /// Reads an Applanix SBET.
+    ///
+    /// Each record is seventeen little-endian doubles, of which we use the time, latitude,
+    /// longitude, altitude, roll, pitch, heading, and wander angle. If `utm_zone` is given,
+    /// positions are projected into that WGS84 UTM zone (negative zones are in the southern
+    /// hemisphere), otherwise x and y are longitude and latitude in degrees and the trajectory
+    /// `is_geographic`. Yaw is the heading less the wander angle, i.e. relative to true north; we
+    /// don't correct for grid convergence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::trajectory::Trajectory;
+    /// assert!(Trajectory::from_sbet(Cursor::new(vec![0; 136]), Some(11)).is_ok());
+    /// assert!(Trajectory::from_sbet(Cursor::new(vec![0; 100]), None).is_err());
+    /// ```
+    pub fn from_sbet<R: Read>(mut reader: R, utm_zone: Option<i32>) -> Result<Trajectory> {
+        let mut bytes = Vec::new();
+        let _ = try!(reader.read_to_end(&mut bytes));
+        if bytes.len() % SBET_RECORD_LENGTH != 0 {
+            return Err(Error::Format(format!("an sbet should be a whole number of {} byte \
+                                              records, but this one is {} bytes",
+                                             SBET_RECORD_LENGTH,
+                                             bytes.len())));
+        }
+        let mut poses = Vec::with_capacity(bytes.len() / SBET_RECORD_LENGTH);
+        for record in bytes.chunks(SBET_RECORD_LENGTH) {
+            let value = |i: usize| LittleEndian::read_f64(&record[8 * i..8 * i + 8]);
+            let (latitude, longitude) = (value(1), value(2));
+            let (x, y) = match utm_zone {
+                Some(zone) => try!(utm(latitude, longitude, zone)),
+                None => (longitude.to_degrees(), latitude.to_degrees()),
+            };
+            poses.push(Pose {
+                gps_time: value(0),
+                x: x,
+                y: y,
+                z: value(3),
+                roll: value(7),
+                pitch: value(8),
+                yaw: value(9) - value(10),
+            });
+        }
+        let mut trajectory = try!(Trajectory::new(poses));
+        trajectory.geographic = utm_zone.is_none();
+        Ok(trajectory)
+    }
+
+    /// Returns true if x and y are longitude and latitude in degrees, rather than meters.
+    ///
+    /// Geographic positions can't be used to compute ranges or to georeference points.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use pabst::trajectory::Trajectory;
+    /// assert!(Trajectory::from_sbet(Cursor::new(vec![0; 136]), None).unwrap().is_geographic());
+    /// assert!(!Trajectory::from_path("data/trajectory.txt").unwrap().is_geographic());
+    /// ```
+    pub fn is_geographic(&self) -> bool {
+        self.geographic
+    }
+
+    /// Returns the poses in this trajectory.
+    pub fn poses(&self) -> &[Pose] {
+        &self.poses
+    }
+
+    /// Returns the pose at a time, linearly interpolated between the nearest poses.
+    ///
+    /// Returns `None` if the time is outside of the trajectory, or isn't a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::f64;
+    /// use pabst::trajectory::{Pose, Trajectory};
+    /// let start = Pose { gps_time: 0.0, x: 0.0, ..Default::default() };
+    /// let end = Pose { gps_time: 1.0, x: 2.0, ..Default::default() };
+    /// let trajectory = Trajectory::new(vec![start, end]).unwrap();
+    /// assert_eq!(1.0, trajectory.pose_at(0.5).unwrap().x);
+    /// assert_eq!(None, trajectory.pose_at(1.5));
+    /// assert_eq!(None, trajectory.pose_at(f64::NAN));
+    /// ```
+    pub fn pose_at(&self, gps_time: f64) -> Option<Pose> {
+        if gps_time.is_nan() {
+            return None;
+        }
+        let after = match self.poses.binary_search_by(|pose| {
+            pose.gps_time.partial_cmp(&gps_time).unwrap()
+        }) {
+            Ok(i) => return Some(self.poses[i]),
+            Err(i) => i,
+        };
+        if after == 0 || after == self.poses.len() {
+            return None;
+        }
+        let (a, b) = (&self.poses[after - 1], &self.poses[after]);
+        let t = (gps_time - a.gps_time) / (b.gps_time - a.gps_time);
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let lerp_angle = |a: f64, b: f64| {
+            let mut delta = b - a;
+            if delta > PI {
+                delta -= 2.0 * PI;
+            } else if delta < -PI {
+                delta += 2.0 * PI;
+            }
+            a + delta * t
+        };
+        Some(Pose {
+            gps_time: gps_time,
+            x: lerp(a.x, b.x),
+            y: lerp(a.y, b.y),
+            z: lerp(a.z, b.z),
+            roll: lerp_angle(a.roll, b.roll),
+            pitch: lerp_angle(a.pitch, b.pitch),
+            yaw: lerp_angle(a.yaw, b.yaw),
+        })
+    }
+}
+
+/// Projects a latitude and longitude, in radians, into a WGS84 UTM zone.
+fn utm(latitude: f64, longitude: f64, zone: i32) -> Result<(f64, f64)> {
+    if zone == 0 || zone.abs() > 60 {
+        return Err(Error::Configuration(format!("invalid utm zone: {}", zone)));
+    }
+    let a = 6378137.0;
+    let f = 1.0 / 298.257223563;
+    let k0 = 0.9996;
+    let e2 = f * (2.0 - f);
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    let ep2 = e2 / (1.0 - e2);
+    let central_meridian = ((zone.abs() * 6 - 183) as f64).to_radians();
+
+    let (sin, cos, tan) = (latitude.sin(), latitude.cos(), latitude.tan());
+    let n = a / (1.0 - e2 * sin * sin).sqrt();
+    let t = tan * tan;
+    let c = ep2 * cos * cos;
+    let a_ = (longitude - central_meridian) * cos;
+    let m = a *
+            ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * latitude -
+             (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * latitude).sin() +
+             (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * latitude).sin() -
+             (35.0 * e6 / 3072.0) * (6.0 * latitude).sin());
+
+    let x = 500000.0 +
+            k0 * n *
+            (a_ + (1.0 - t + c) * a_.powi(3) / 6.0 +
+             (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a_.powi(5) / 120.0);
+    let mut y = k0 *
+                (m +
+                 n * tan *
+                 (a_ * a_ / 2.0 + (5.0 - t + 9.0 * c + 4.0 * c * c) * a_.powi(4) / 24.0 +
+                  (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a_.powi(6) / 720.0));
+    if zone < 0 {
+        y += 10000000.0;
+    }
+    Ok((x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use super::*;
+    use super::utm;
+
+    fn sbet(records: &[[f64; 17]]) -> Cursor<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for record in records {
+            for &value in record.iter() {
+                bytes.write_f64::<LittleEndian>(value).unwrap();
+            }
+        }
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn read_sbet() {
+        let mut first = [0.0; 17];
+        first[2] = (-117f64).to_radians();
+        first[3] = 1000.0;
+        first[9] = 0.5;
+        first[10] = 0.25;
+        let mut second = first;
+        second[0] = 1.0;
+        second[1] = 0.001;
+        let trajectory = Trajectory::from_sbet(sbet(&[second, first]), Some(11)).unwrap();
+        let poses = trajectory.poses();
+        assert_eq!(0.0, poses[0].gps_time);
+        assert!((poses[0].x - 500000.0).abs() < 1e-6);
+        assert!(poses[0].y.abs() < 1e-6);
+        assert!(poses[1].y > 6000.0);
+        assert_eq!(1000.0, poses[0].z);
+        assert_eq!(0.25, poses[0].yaw);
+
+        let trajectory = Trajectory::from_sbet(sbet(&[first]), None).unwrap();
+        assert!((trajectory.poses()[0].x + 117.0).abs() < 1e-12);
+        assert!(Trajectory::from_sbet(sbet(&[first]), Some(61)).is_err());
+    }
+
+    #[test]
+    fn utm_is_symmetric() {
+        let latitude = 45f64.to_radians();
+        let (east, north) = utm(latitude, (-104f64).to_radians(), 13).unwrap();
+        let (west, _) = utm(latitude, (-106f64).to_radians(), 13).unwrap();
+        assert!((east - 500000.0 - (500000.0 - west)).abs() < 1e-6);
+        let (_, south) = utm(-latitude, (-104f64).to_radians(), -13).unwrap();
+        assert!((north - (10000000.0 - south)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn read_text() {
+        let trajectory = Trajectory::from_path("data/trajectory.txt").unwrap();
+        assert_eq!(3, trajectory.poses().len());
+        let pose = trajectory.pose_at(100.5).unwrap();
+        assert_eq!(1000.0, pose.z);
+        assert_eq!(500005.0, pose.x);
+    }
+
+    #[test]
+    fn short_text_line() {
+        match Trajectory::from_text(Cursor::new("# time x y z r p h\n1 2 3 4 5 6 7\n1 2 3\n"))
+                  .unwrap_err() {
+            Error::Context(context, err) => {
+                assert_eq!(Some(3), context.line);
+                match *err {
+                    Error::Format(_) => {}
+                    ref err => panic!("unexpected error: {:?}", err),
+                }
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn yaw_wraps() {
+        let start = Pose { gps_time: 0.0, yaw: 3.1, ..Default::default() };
+        let end = Pose { gps_time: 1.0, yaw: -3.1, ..Default::default() };
+        let trajectory = Trajectory::new(vec![start, end]).unwrap();
+        let yaw = trajectory.pose_at(0.5).unwrap().yaw;
+        assert!((yaw.abs() - PI).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,111 @@
+//! Warnings about data that a sink couldn't write exactly.
+//!
+//! Formats can't always hold everything a `Point` can, e.g. las scan angles are whole degrees and
+//! sdc coordinates are single precision. Sinks that have to throw something away count it in
+//! their `Warnings`, along with the largest error for numeric values, so the loss can be reported
+//! when they're done. Strict warnings turn the first loss into an error instead.
+
+use std::collections::BTreeMap;
+use std::collections::btree_map::Iter;
+
+use {Error, Result};
+
+/// One kind of lossy conversion.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Warning {
+    /// The number of values that were changed.
+    pub count: usize,
+    /// The largest difference between a value and what was written, if the value is a number.
+    pub max_error: Option<f64>,
+}
+
+/// The lossy conversions made by a sink, by kind.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Warnings {
+    strict: bool,
+    warnings: BTreeMap<String, Warning>,
+}
+
+impl Warnings {
+    /// Creates new warnings, which turn into errors if `strict` is true.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::warning::Warnings;
+    /// let warnings = Warnings::new(false);
+    /// assert!(warnings.is_empty());
+    /// ```
+    pub fn new(strict: bool) -> Warnings {
+        Warnings {
+            strict: strict,
+            warnings: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a lossy conversion, which doesn't have a numeric error.
+    ///
+    /// Returns an error if these warnings are strict.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::warning::Warnings;
+    /// let mut warnings = Warnings::new(false);
+    /// warnings.warn("unknown scan_direction written as forward").unwrap();
+    /// assert_eq!(1, warnings.get("unknown scan_direction written as forward").unwrap().count);
+    /// assert!(Warnings::new(true).warn("unknown scan_direction written as forward").is_err());
+    /// ```
+    pub fn warn(&mut self, what: &str) -> Result<()> {
+        if self.strict {
+            return Err(Error::LossyConversion(what.to_string()));
+        }
+        self.warnings.entry(what.to_string()).or_insert_with(Warning::default).count += 1;
+        Ok(())
+    }
+
+    /// Counts a lossy conversion of a number, keeping track of the largest error.
+    ///
+    /// Errors of zero aren't lossy, and aren't counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pabst::warning::Warnings;
+    /// let mut warnings = Warnings::new(false);
+    /// warnings.warn_error("scan_angle truncated to whole degrees", 0.25).unwrap();
+    /// warnings.warn_error("scan_angle truncated to whole degrees", 0.5).unwrap();
+    /// warnings.warn_error("scan_angle truncated to whole degrees", 0.0).unwrap();
+    /// let warning = warnings.get("scan_angle truncated to whole degrees").unwrap();
+    /// assert_eq!(2, warning.count);
+    /// assert_eq!(Some(0.5), warning.max_error);
+    /// ```
+    pub fn warn_error(&mut self, what: &str, error: f64) -> Result<()> {
+        let error = error.abs();
+        if error == 0.0 {
+            return Ok(());
+        }
+        if self.strict {
+            return Err(Error::LossyConversion(what.to_string()));
+        }
+        let warning = self.warnings.entry(what.to_string()).or_insert_with(Warning::default);
+        warning.count += 1;
+        warning.max_error = Some(warning.max_error.map_or(error, |max| max.max(error)));
+        Ok(())
+    }
+
+    /// Returns the warning for one kind of lossy conversion, if there were any.
+    pub fn get(&self, what: &str) -> Option<&Warning> {
+        self.warnings.get(what)
+    }
+
+    /// Returns true if nothing was lost.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Returns an iterator over the kinds of lossy conversions, in order.
+    pub fn iter(&self) -> Iter<String, Warning> {
+        self.warnings.iter()
+    }
+}
@@ -0,0 +1,227 @@
+//! Las waveform packets, written to an external .wdp file.
+//!
+//! Each distinct sample count and sampling interval gets its own wave packet descriptor VLR. We
+//! don't know how many descriptors we'll need until every point has been written, but the VLRs
+//! come before the points, so we reserve room for a fixed number of descriptors up front and fill
+//! them in when the file is closed. Whatever room is left over is taken up by a padding VLR.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use las::Vlr;
+
+use {Error, Result};
+use las_header::WAVE_PACKET_LENGTH;
+use point::{Point, Waveform};
+
+const USER_ID: &'static [u8] = b"LASF_Spec";
+const PADDING_USER_ID: &'static [u8] = b"pabst";
+const FIRST_DESCRIPTOR_RECORD_ID: u16 = 100;
+const DESCRIPTOR_LENGTH: usize = 26;
+const VLR_HEADER_LENGTH: usize = 54;
+const EVLR_HEADER_LENGTH: u64 = 60;
+const WAVEFORM_DATA_PACKETS_RECORD_ID: u16 = 65535;
+const BITS_PER_SAMPLE: u8 = 16;
+const PICOSECONDS: f64 = 1e12;
+
+/// The largest number of descriptors a las file can hold.
+pub const MAX_DESCRIPTORS: usize = 255;
+
+/// Writes waveform samples to a .wdp file, and keeps track of their descriptors.
+#[derive(Debug)]
+pub struct WavePackets<W: Write + Seek> {
+    descriptors: Vec<(u32, u32)>,
+    max_descriptors: usize,
+    offset: u64,
+    writer: W,
+}
+
+impl<W: Write + Seek> WavePackets<W> {
+    /// Starts a new .wdp file, with room for `max_descriptors` descriptors in the las file.
+    pub fn new(mut writer: W, max_descriptors: usize) -> Result<WavePackets<W>> {
+        if max_descriptors == 0 || max_descriptors > MAX_DESCRIPTORS {
+            return Err(Error::Configuration(format!("las files can hold between one and {} \
+                                                     wave packet descriptors, not {}",
+                                                    MAX_DESCRIPTORS,
+                                                    max_descriptors)));
+        }
+        try!(write_evlr_header(&mut writer, 0));
+        Ok(WavePackets {
+            descriptors: Vec::new(),
+            max_descriptors: max_descriptors,
+            offset: EVLR_HEADER_LENGTH,
+            writer: writer,
+        })
+    }
+
+    /// Returns the VLR that holds the place of the descriptors until they are known.
+    pub fn placeholder(&self) -> Vlr {
+        padding(self.max_descriptors * (VLR_HEADER_LENGTH + DESCRIPTOR_LENGTH))
+    }
+
+    /// Writes a point's waveform, if it has one, and returns the point's wave packet fields.
+    ///
+    /// Points without a waveform get a descriptor index of zero, which las uses to say that there
+    /// isn't a waveform.
+    pub fn write(&mut self, point: &Point) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; WAVE_PACKET_LENGTH as usize];
+        let waveform = match point.waveform {
+            Some(ref waveform) => waveform,
+            None => return Ok(bytes),
+        };
+        let index = try!(self.descriptor_index(waveform));
+        for &sample in &waveform.samples {
+            try!(self.writer.write_u16::<LittleEndian>(sample));
+        }
+        let size = 2 * waveform.samples.len() as u32;
+        bytes[0] = index;
+        LittleEndian::write_u64(&mut bytes[1..9], self.offset);
+        LittleEndian::write_u32(&mut bytes[9..13], size);
+        let location = waveform.time_at([point.x, point.y, point.z]);
+        LittleEndian::write_f32(&mut bytes[13..17], (location * PICOSECONDS) as f32);
+        for i in 0..3 {
+            LittleEndian::write_f32(&mut bytes[17 + 4 * i..21 + 4 * i],
+                                    (waveform.direction[i] / PICOSECONDS) as f32);
+        }
+        self.offset += size as u64;
+        Ok(bytes)
+    }
+
+    /// Returns the descriptor VLRs, padded out to fill the placeholder, and the number of VLRs.
+    pub fn descriptors(&self) -> (Vec<u8>, u32) {
+        let mut bytes = Vec::with_capacity(self.placeholder().len() as usize);
+        for (i, &(number_of_samples, spacing)) in self.descriptors.iter().enumerate() {
+            let mut vlr = Vlr::new();
+            vlr.user_id[..USER_ID.len()].copy_from_slice(USER_ID);
+            vlr.record_id = FIRST_DESCRIPTOR_RECORD_ID + i as u16;
+            vlr.record = vec![0; DESCRIPTOR_LENGTH];
+            vlr.record[0] = BITS_PER_SAMPLE;
+            LittleEndian::write_u32(&mut vlr.record[2..6], number_of_samples);
+            LittleEndian::write_u32(&mut vlr.record[6..10], spacing);
+            LittleEndian::write_f64(&mut vlr.record[10..18], 1.0);
+            vlr.record_length_after_header = DESCRIPTOR_LENGTH as u16;
+            write_vlr(&mut bytes, &vlr);
+        }
+        let mut count = self.descriptors.len() as u32;
+        let remaining = self.placeholder().len() as usize - bytes.len();
+        if remaining > 0 {
+            write_vlr(&mut bytes, &padding(remaining));
+            count += 1;
+        }
+        (bytes, count)
+    }
+
+    /// Fills in the length of the waveform data and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let _ = try!(self.writer.seek(SeekFrom::Start(0)));
+        try!(write_evlr_header(&mut self.writer, self.offset - EVLR_HEADER_LENGTH));
+        let _ = try!(self.writer.seek(SeekFrom::End(0)));
+        Ok(self.writer)
+    }
+
+    fn descriptor_index(&mut self, waveform: &Waveform) -> Result<u8> {
+        let descriptor = (waveform.samples.len() as u32,
+                          (waveform.sampling_interval * PICOSECONDS).round() as u32);
+        let index = match self.descriptors.iter().position(|&d| d == descriptor) {
+            Some(index) => index,
+            None => {
+                if self.descriptors.len() == self.max_descriptors {
+                    return Err(Error::Configuration(format!("waveforms need more than {} wave \
+                                                             packet descriptors",
+                                                            self.max_descriptors)));
+                }
+                self.descriptors.push(descriptor);
+                self.descriptors.len() - 1
+            }
+        };
+        Ok(index as u8 + 1)
+    }
+}
+
+fn write_evlr_header<W: Write>(writer: &mut W, record_length: u64) -> Result<()> {
+    let mut user_id = [0; 16];
+    user_id[..USER_ID.len()].copy_from_slice(USER_ID);
+    try!(writer.write_u16::<LittleEndian>(0));
+    try!(writer.write_all(&user_id));
+    try!(writer.write_u16::<LittleEndian>(WAVEFORM_DATA_PACKETS_RECORD_ID));
+    try!(writer.write_u64::<LittleEndian>(record_length));
+    try!(writer.write_all(&[0; 32]));
+    Ok(())
+}
+
+/// A VLR of `len` bytes, header included, that no one should care about.
+fn padding(len: usize) -> Vlr {
+    let mut vlr = Vlr::new();
+    vlr.user_id[..PADDING_USER_ID.len()].copy_from_slice(PADDING_USER_ID);
+    vlr.record = vec![0; len - VLR_HEADER_LENGTH];
+    vlr.record_length_after_header = vlr.record.len() as u16;
+    vlr
+}
+
+fn write_vlr(bytes: &mut Vec<u8>, vlr: &Vlr) {
+    let mut header = [0; VLR_HEADER_LENGTH];
+    LittleEndian::write_u16(&mut header[0..2], vlr.reserved);
+    header[2..18].copy_from_slice(&vlr.user_id);
+    LittleEndian::write_u16(&mut header[18..20], vlr.record_id);
+    LittleEndian::write_u16(&mut header[20..22], vlr.record_length_after_header);
+    header[22..54].copy_from_slice(&vlr.description);
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&vlr.record);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use point::{Point, Waveform};
+    use super::*;
+
+    fn point(nsamples: usize) -> Point {
+        Point {
+            x: 2.0,
+            waveform: Some(Waveform {
+                samples: vec![1; nsamples],
+                sampling_interval: 1e-9,
+                anchor: [0.0; 3],
+                direction: [1e9, 0.0, 0.0],
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn descriptors() {
+        let mut wave_packets = WavePackets::new(Cursor::new(Vec::new()), 4).unwrap();
+        let placeholder = wave_packets.placeholder().len();
+        let first = wave_packets.write(&point(10)).unwrap();
+        assert_eq!(1, first[0]);
+        assert_eq!(60, LittleEndian::read_u64(&first[1..9]));
+        assert_eq!(20, LittleEndian::read_u32(&first[9..13]));
+        assert_eq!(2000.0, LittleEndian::read_f32(&first[13..17]));
+        assert_eq!(2, wave_packets.write(&point(5)).unwrap()[0]);
+        let third = wave_packets.write(&point(10)).unwrap();
+        assert_eq!(1, third[0]);
+        assert_eq!(90, LittleEndian::read_u64(&third[1..9]));
+        assert_eq!(0, wave_packets.write(&Point::default()).unwrap()[0]);
+
+        let (bytes, count) = wave_packets.descriptors();
+        assert_eq!(placeholder as usize, bytes.len());
+        assert_eq!(3, count);
+        assert_eq!(101, LittleEndian::read_u16(&bytes[80 + 18..80 + 20]));
+        assert_eq!(5, LittleEndian::read_u32(&bytes[80 + 56..80 + 60]));
+
+        let wdp = wave_packets.finish().unwrap().into_inner();
+        assert_eq!(110, wdp.len());
+        assert_eq!(50, LittleEndian::read_u64(&wdp[20..28]));
+    }
+
+    #[test]
+    fn too_many_descriptors() {
+        let mut wave_packets = WavePackets::new(Cursor::new(Vec::new()), 1).unwrap();
+        let _ = wave_packets.write(&point(10)).unwrap();
+        assert!(wave_packets.write(&point(5)).is_err());
+        assert!(WavePackets::new(Cursor::new(Vec::new()), 256).is_err());
+    }
+}